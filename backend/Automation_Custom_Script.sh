@@ -52,6 +52,10 @@ path=/bin/drivepi
 # Create Drive-PI directory to store files
 mkdir "$path"
 
+# Create the group that mounted drives and samba users belong to so the
+# samba users can write to the drives without joining the root group
+groupadd --system --force drivepi
+
 # Download Drive-PI executable from latest GitHub release
 echo "Downloading server executable"
 curl -L -o $path/server http://github.com/Jacobtread/Drive-PI/releases/latest/download/drivepi
@@ -65,6 +69,9 @@ DRIVEPI_PASSWORD=admin
 # Server port
 DRIVEPI_PORT=80
 
+# Group mounted drives belong to which samba users are added to
+DRIVEPI_MOUNT_GROUP=drivepi
+
 # Logging settings
 RUST_LOG=drivepi=info
 RUST_LOG_STYLE=always
@@ -88,9 +95,9 @@ echo "$start_script" | tee $path/start.sh
 chmod +x $path/start.sh
 
 # Samba configuration which is just a simple configuration
-# that adds a samba stored named [storage] which shares the
-# /bin/drivepi/mount folder which is where Drive-PI mounts
-# all its drives to (stored at /etc/samba/smb.conf)
+# that includes the shares Drive-PI generates for the drives
+# it mounts to the /bin/drivepi/mount folder from
+# /etc/samba/drivepi.conf (stored at /etc/samba/smb.conf)
 samba_config="[global]
    workgroup = WORKGROUP
    server string = %h server (Samba, Ubuntu)
@@ -107,19 +114,16 @@ samba_config="[global]
    map to guest = bad user
 
 
-# Shares the mounted paths to \\drivepi.local\storage
-[storage]
-   comment = DrivePI share
-   path = $path/mount
-   read only = no
-   browsable = yes
-
 [guest]
    comment = DrivePI guest share
    path = $path/mount
    read only = yes
    guest ok = yes
    browsable = yes
+
+# Shares the mounted drives to \\drivepi.local\drivepi (or one share per
+# drive when DRIVEPI_SAMBA_MODE=drives) generated by Drive-PI
+include = /etc/samba/drivepi.conf
 "
 
 echo "Writing samba config"
@@ -127,6 +131,8 @@ echo "Writing samba config"
 mv /etc/samba/smb.conf /etc/samba/smb.old.conf
 # Write the new samba config
 echo "$samba_config" | tee /etc/samba/smb.conf
+# Create the generated share config so samba can start before Drive-PI
+touch /etc/samba/drivepi.conf
 
 # Daemon service for automatically starting the Drive-PI server
 # automatically. This is a system service and must be a system
@@ -1,15 +1,32 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use actix_cors::Cors;
 use actix_web::web::{scope, Data};
 use actix_web::{App, HttpServer};
 use dotenv::dotenv;
-use log::info;
+use log::{info, warn};
 
 use stores::auth::AuthStore;
+use stores::automount::AutoMountStore;
+use stores::drives::DriveRegistry;
+use stores::events::EventBus;
+use stores::jobs::JobStore;
+use stores::known::KnownDriveStore;
+use stores::locks::DeviceLocks;
+use stores::uploads::{UploadStore, UPLOAD_EXPIRY_TIME};
 
 use crate::routes::auth_scope;
+use crate::utils::automount::{automount_drives, get_env_policy_path};
+use crate::utils::command::{CommandRunner, SystemRunner};
+use crate::utils::files::get_drive_dir;
 use crate::utils::get_env_port;
+use crate::utils::hotplug::monitor_drives;
+use crate::utils::known::{get_known_drives_path, track_known_drives};
+use crate::utils::mdns::start_mdns;
+use crate::utils::samba::update_samba_shares;
+use crate::utils::trash::{get_env_trash_retention, purge_expired_trash};
+use crate::utils::uploads::{remove_staging_file, remove_stale_staging_files};
 
 mod routes;
 
@@ -18,6 +35,12 @@ pub mod models;
 pub mod stores;
 pub mod utils;
 
+/// The interval in seconds between removing expired upload sessions
+const UPLOAD_CLEANUP_INTERVAL: u64 = 60 * 10;
+
+/// The interval in seconds between purging expired trash items
+const TRASH_PURGE_INTERVAL: u64 = 60 * 60;
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
@@ -28,6 +51,43 @@ async fn main() -> std::io::Result<()> {
     let port = get_env_port();
     let auth_store = AuthStore::new();
     let auth_store = Arc::new(auth_store);
+    let upload_store = Arc::new(UploadStore::new());
+    let drive_registry = Arc::new(DriveRegistry::new());
+    let event_bus = Arc::new(EventBus::new());
+    let automount_store = Arc::new(AutoMountStore::new(get_env_policy_path()));
+    let known_store = Arc::new(KnownDriveStore::new(get_known_drives_path()));
+    let command_runner: Arc<dyn CommandRunner> = Arc::new(SystemRunner);
+    let device_locks = Arc::new(DeviceLocks::new());
+    let job_store = Arc::new(JobStore::new());
+
+    // Subscribed before the monitor starts so drives present at startup are included
+    tokio::spawn(track_known_drives(
+        drive_registry.subscribe(),
+        known_store.clone(),
+    ));
+    tokio::spawn(automount_drives(
+        drive_registry.subscribe(),
+        drive_registry.clone(),
+        event_bus.clone(),
+        automount_store.clone(),
+        known_store.clone(),
+        command_runner.clone(),
+        device_locks.clone(),
+    ));
+    tokio::spawn(monitor_drives(
+        drive_registry.clone(),
+        command_runner.clone(),
+    ));
+    // Shares may be stale if drives were removed while the server was stopped
+    let samba_runner = command_runner.clone();
+    tokio::task::spawn_blocking(move || update_samba_shares(samba_runner.as_ref()));
+    tokio::spawn(cleanup_uploads(
+        upload_store.clone(),
+        drive_registry.clone(),
+    ));
+    tokio::spawn(purge_trash(get_env_trash_retention()));
+    // Kept until the server stops so the services stay advertised
+    let mdns = start_mdns(port);
 
     info!("Drive-PI starting on port {} if you are", port);
     info!("running this on the Raspberry PI access point ");
@@ -40,18 +100,94 @@ async fn main() -> std::io::Result<()> {
     let server = HttpServer::new(move || {
         let cors = Cors::permissive();
         let auth_store_data = Data::from(auth_store.clone());
+        let upload_store_data = Data::from(upload_store.clone());
+        let drive_registry_data = Data::from(drive_registry.clone());
+        let event_bus_data = Data::from(event_bus.clone());
+        let automount_store_data = Data::from(automount_store.clone());
+        let known_store_data = Data::from(known_store.clone());
+        let command_runner_data = Data::from(command_runner.clone());
+        let device_locks_data = Data::from(device_locks.clone());
+        let job_store_data = Data::from(job_store.clone());
         App::new()
             .wrap(cors)
             .app_data(auth_store_data)
+            .app_data(upload_store_data)
+            .app_data(drive_registry_data)
+            .app_data(event_bus_data)
+            .app_data(automount_store_data)
+            .app_data(known_store_data)
+            .app_data(command_runner_data)
+            .app_data(device_locks_data)
+            .app_data(job_store_data)
             .service(
                 scope("/api").configure(routes::auth::init_routes).service(
                     auth_scope(auth_store.clone())
                         .configure(routes::drives::init_routes)
-                        .configure(routes::files::init_routes),
+                        .configure(routes::automount::init_routes)
+                        .configure(routes::events::init_routes)
+                        .configure(routes::files::init_routes)
+                        .configure(routes::trash::init_routes)
+                        .configure(routes::search::init_routes)
+                        .configure(routes::samba::init_routes)
+                        .configure(routes::hotspot::init_routes)
+                        .configure(routes::jobs::init_routes)
+                        .configure(routes::uploads::init_routes),
                 ),
             )
             .configure(routes::app::init_routes)
     });
 
-    server.bind(("0.0.0.0", port))?.run().await
+    let result = server.bind(("0.0.0.0", port))?.run().await;
+
+    // Sends goodbye packets so clients forget the services
+    if let Some(mdns) = mdns {
+        if let Err(err) = mdns.shutdown() {
+            warn!("Failed to stop mDNS responder: {}", err);
+        }
+    }
+    result
+}
+
+/// Background task which periodically removes expired upload
+/// sessions along with their staging files. Staging files without
+/// a session (e.g. from before a restart) are removed once stale
+async fn cleanup_uploads(upload_store: Arc<UploadStore>, registry: Arc<DriveRegistry>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(UPLOAD_CLEANUP_INTERVAL));
+    loop {
+        interval.tick().await;
+        for session in upload_store.remove_expired().await {
+            let session = session.lock().await;
+            match get_drive_dir(&registry, &session.drive) {
+                Ok(drive_root) => remove_staging_file(&drive_root, &session),
+                Err(err) => warn!(
+                    "Failed to remove staging file for {}: {}",
+                    session.name, err
+                ),
+            }
+            info!("Removed expired upload for {}", session.name);
+        }
+
+        let expiry = Duration::from_secs(UPLOAD_EXPIRY_TIME);
+        match tokio::task::spawn_blocking(move || remove_stale_staging_files(expiry)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(removed)) => info!("Removed {} stale upload staging files", removed),
+            Ok(Err(err)) => warn!("Failed to remove stale upload staging files: {}", err),
+            Err(err) => warn!("Upload staging cleanup task failed: {}", err),
+        }
+    }
+}
+
+/// Background task which periodically permanently removes items
+/// that have been in the trash for longer than the retention period
+async fn purge_trash(retention: Duration) {
+    let mut interval = tokio::time::interval(Duration::from_secs(TRASH_PURGE_INTERVAL));
+    loop {
+        interval.tick().await;
+        match tokio::task::spawn_blocking(move || purge_expired_trash(retention)).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(removed)) => info!("Purged {} expired items from trash", removed),
+            Ok(Err(err)) => warn!("Failed to purge expired trash: {}", err),
+            Err(err) => warn!("Trash purge task failed: {}", err),
+        }
+    }
 }
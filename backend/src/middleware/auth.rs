@@ -1,6 +1,10 @@
 use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::Method;
+use actix_web::web::Query;
+use actix_web::HttpRequest;
 use futures::future::LocalBoxFuture;
 use futures::FutureExt;
+use serde::Deserialize;
 use std::future::{ready, Ready};
 use std::rc::Rc;
 use std::sync::Arc;
@@ -10,6 +14,34 @@ use crate::stores::auth::AuthStore;
 
 pub const TOKEN_HEADER: &str = "X-Token";
 
+/// The GET routes that accept the token through the query string because
+/// their clients cannot set headers (download links, media and EventSource).
+/// Tokens in URLs can end up in logs and history so no other routes accept them
+const QUERY_TOKEN_PATHS: &[&str] = &["/api/files/download", "/api/events"];
+
+/// Structure for extracting the token from the query string for
+/// requests that cannot set headers (e.g. download links and media)
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: String,
+}
+
+/// Retrieves the token for the provided request from the token header
+/// falling back to the token query parameter if the header is missing
+/// and the route accepts tokens through the query string
+pub fn get_request_token(req: &HttpRequest) -> Result<String, AuthError> {
+    if let Some(token_header) = req.headers().get(TOKEN_HEADER) {
+        let token = token_header.to_str().map_err(server_error)?;
+        return Ok(token.to_string());
+    }
+    if req.method() != Method::GET || !QUERY_TOKEN_PATHS.contains(&req.path()) {
+        return Err(AuthError::MissingToken);
+    }
+    Query::<TokenQuery>::from_query(req.query_string())
+        .map(|query| query.into_inner().token)
+        .map_err(|_| AuthError::MissingToken)
+}
+
 /// Struct representing a the base middleware for
 /// authentication tokens
 pub struct AuthMiddleware {
@@ -67,10 +99,8 @@ where
         let auth_store = self.auth_store.clone();
 
         async move {
-            let headers = req.headers();
-            let token_header = headers.get(TOKEN_HEADER).ok_or(AuthError::MissingToken)?;
-            let token = token_header.to_str().map_err(server_error)?;
-            let is_valid = auth_store.check_token(token).await;
+            let token = get_request_token(req.request())?;
+            let is_valid = auth_store.check_token(&token).await;
             if is_valid {
                 service.call(req).await
             } else {
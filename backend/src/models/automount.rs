@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+/// The action taken when a drive is connected
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoMountAction {
+    /// Mount the drive as Read/Write
+    #[default]
+    Mount,
+    /// Mount the drive as Read Only
+    ReadOnly,
+    /// Leave the drive unmounted
+    Ignore,
+}
+
+/// Structure representing a rule deciding what happens to connected
+/// drives. A rule applies to a drive when every criteria that is
+/// present matches the drive
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AutoMountRule {
+    /// The filesystem UUID of the drive (e.g. 21c89e37-a0aa-48bc-aead-cec8d9a8e8cc)
+    #[serde(default)]
+    pub uuid: Option<String>,
+    /// Glob pattern matching the filesystem label (e.g. Backup*)
+    #[serde(default)]
+    pub label: Option<String>,
+    /// The filesystem type of the drive (e.g. vfat)
+    #[serde(default)]
+    pub fstype: Option<String>,
+    /// Glob pattern matching the drive path (e.g. /dev/sd*)
+    #[serde(default)]
+    pub path: Option<String>,
+    /// The action to take for matching drives
+    pub action: AutoMountAction,
+    /// The name of the folder to mount matching drives to. Defaults
+    /// to the filesystem label or UUID when not provided
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Structure representing the auto-mount policy. Rules are checked in
+/// order and the first matching rule decides the action for a drive
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AutoMountPolicy {
+    /// Whether drives are automatically mounted when connected
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// The action to take for drives that don't match any rule
+    #[serde(default)]
+    pub default_action: AutoMountAction,
+    /// The rules for specific drives
+    #[serde(default)]
+    pub rules: Vec<AutoMountRule>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Default for AutoMountPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            default_action: AutoMountAction::Mount,
+            rules: Vec::new(),
+        }
+    }
+}
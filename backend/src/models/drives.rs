@@ -8,10 +8,13 @@ pub struct DrivesResponse {
     pub drives: Vec<Drive>,
     /// The location on the file system where drives are mounted
     pub mount_root: String,
+    /// Every drive that has been seen including drives that
+    /// are not currently connected
+    pub known: Vec<KnownDriveStatus>,
 }
 
 /// Structure representing a block device
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct Drive {
     /// Filesystem UUID (e.g. 21c89e37-a0aa-48bc-aead-cec8d9a8e8cc)
     pub uuid: String,
@@ -21,6 +24,8 @@ pub struct Drive {
     pub label: String,
     /// Path to device node (e.g. /dev/sda1)
     pub path: String,
+    /// Filesystem type (e.g. vfat). None if the device has no filesystem
+    pub fstype: Option<String>,
     /// Mounted file system path (e.g. /run/media/DRIVE)
     /// this is None if not mounted
     #[serde(rename(deserialize = "mountpoint"))]
@@ -42,4 +47,159 @@ pub struct MountRequest {
     pub path: String,
     /// The name of the folder to mount the drive to
     pub name: String,
+    /// Whether to mount the drive as read only
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// Structure for a request to unmount a drive
+#[derive(Deserialize)]
+pub struct UnmountRequest {
+    /// The drive path (e.g. /dev/sda1)
+    pub path: String,
+    /// The name of the folder the drive is mounted to
+    pub name: String,
+    /// How to unmount the drive if it is busy. Busy drives
+    /// are not unmounted when this is not provided
+    #[serde(default)]
+    pub force: Option<ForceUnmount>,
+    /// Whether samba processes holding the drive can be terminated
+    /// when forcing the unmount by terminating the holders
+    #[serde(default)]
+    pub include_samba: bool,
+}
+
+/// The ways a busy drive can be forcefully unmounted
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ForceUnmount {
+    /// Detaches the mount right away and cleans it up once
+    /// the processes holding it have closed their files
+    Lazy,
+    /// Terminates the processes holding the mount then unmounts it
+    Terminate,
+}
+
+/// Structure representing a process that is holding a mount busy
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct MountHolder {
+    /// The process ID
+    pub pid: u32,
+    /// The command name of the process (e.g. smbd)
+    pub command: String,
+    /// The name of the user running the process. None if the
+    /// user has no name
+    pub user: Option<String>,
+    /// The path on the mount that the process is using
+    pub path: String,
+}
+
+/// Structure for a request to eject the device a drive is on
+#[derive(Deserialize)]
+pub struct EjectRequest {
+    /// The path of a partition on the device or of the
+    /// device itself (e.g. /dev/sda1 or /dev/sda)
+    pub path: String,
+}
+
+/// The steps taken to safely eject a device
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum EjectAction {
+    /// Flushing pending writes to the device
+    Sync,
+    /// Unmounting a partition of the device
+    Unmount,
+    /// Powering off the device
+    PowerOff,
+}
+
+/// Structure representing the result of a step of ejecting a device
+#[derive(Serialize, Debug)]
+pub struct EjectStep {
+    /// The step that was taken
+    pub action: EjectAction,
+    /// The path of the device or partition the step was taken on
+    pub target: String,
+    /// The error message if the step failed
+    pub error: Option<String>,
+    /// The processes holding the partition if it couldn't be
+    /// unmounted because it was busy
+    pub holders: Vec<MountHolder>,
+}
+
+/// Structure representing the result of ejecting a device
+#[derive(Serialize, Debug)]
+pub struct EjectStatus {
+    /// The path of the device that was ejected (e.g. /dev/sda)
+    pub device: String,
+    /// Whether every step succeeded and the device can be unplugged
+    pub safe_to_remove: bool,
+    /// The steps that were taken. Steps after a failed unmount are skipped
+    pub steps: Vec<EjectStep>,
+}
+
+/// Event emitted by the hotplug monitor when a drive is connected,
+/// disconnected or when the details of a connected drive change
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", content = "drive", rename_all = "snake_case")]
+pub enum DriveEvent {
+    /// A drive was connected
+    Added(Drive),
+    /// A drive was disconnected
+    Removed(Drive),
+    /// The details of a drive changed (e.g. it was mounted)
+    Changed(Drive),
+}
+
+/// Structure representing a drive that has been connected before
+/// along with the settings saved for it
+#[derive(Deserialize, Serialize, Clone)]
+pub struct KnownDrive {
+    /// Filesystem UUID (e.g. 21c89e37-a0aa-48bc-aead-cec8d9a8e8cc)
+    pub uuid: String,
+    /// Filesystem label when the drive was last seen
+    pub label: String,
+    /// Filesystem type when the drive was last seen
+    #[serde(default)]
+    pub fstype: Option<String>,
+    /// Friendly name to display for the drive
+    #[serde(default)]
+    pub name: Option<String>,
+    /// The name of the folder the drive is mounted to
+    #[serde(default)]
+    pub mount_name: Option<String>,
+    /// Whether the drive is mounted as read only. None uses
+    /// the auto-mount policy
+    #[serde(default)]
+    pub read_only: Option<bool>,
+    /// Notes about the drive
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// The last time the drive was connected in milliseconds
+    pub last_seen: u128,
+}
+
+/// Structure representing a known drive and whether
+/// it is currently connected
+#[derive(Serialize)]
+pub struct KnownDriveStatus {
+    #[serde(flatten)]
+    pub drive: KnownDrive,
+    /// Whether the drive is currently connected
+    pub connected: bool,
+}
+
+/// Structure for a request to update the saved
+/// settings of a known drive
+#[derive(Deserialize)]
+pub struct KnownDriveRequest {
+    /// Friendly name to display for the drive
+    pub name: Option<String>,
+    /// The name of the folder the drive is mounted to
+    pub mount_name: Option<String>,
+    /// Whether the drive is mounted as read only
+    pub read_only: Option<bool>,
+    /// Notes about the drive
+    pub notes: Option<String>,
 }
@@ -1,9 +1,12 @@
 use std::fmt::{Debug, Display, Formatter};
 use std::{fmt, io};
 
-use actix_web::http::header::ToStrError;
+use actix_web::http::header::{ContentType, ToStrError};
 use actix_web::http::StatusCode;
-use actix_web::ResponseError;
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
+
+use crate::models::drives::MountHolder;
 use std::sync::PoisonError;
 use std::time::SystemTimeError;
 
@@ -47,7 +50,18 @@ pub enum DrivesError {
     ParseError,
     UnmountError,
     MountError,
-    TargetBusy,
+    TargetBusy(Vec<MountHolder>),
+    NotMounted,
+    UnknownDrive,
+    InvalidName,
+    DeviceNotFound,
+    SystemDevice,
+    SyncError,
+    PowerOffError,
+    Mounted,
+    FilesystemErrors,
+    FsckError,
+    Timeout,
     IOError,
 }
 
@@ -57,7 +71,20 @@ impl Display for DrivesError {
             DrivesError::ParseError => f.write_str("parse error"),
             DrivesError::UnmountError => f.write_str("unmount error"),
             DrivesError::MountError => f.write_str("mount error"),
-            DrivesError::TargetBusy => f.write_str("Target is busy cannot unmount"),
+            DrivesError::TargetBusy(_) => f.write_str("Target is busy cannot unmount"),
+            DrivesError::NotMounted => f.write_str("drive is not mounted"),
+            DrivesError::UnknownDrive => f.write_str("drive is not known"),
+            DrivesError::InvalidName => f.write_str("invalid mount folder name"),
+            DrivesError::DeviceNotFound => f.write_str("block device not found"),
+            DrivesError::SystemDevice => f.write_str("cannot eject the system device"),
+            DrivesError::SyncError => f.write_str("failed to flush writes"),
+            DrivesError::PowerOffError => f.write_str("failed to power off device"),
+            DrivesError::Mounted => f.write_str("drive is mounted"),
+            DrivesError::FilesystemErrors => {
+                f.write_str("filesystem has errors that could not be fixed")
+            }
+            DrivesError::FsckError => f.write_str("failed to check filesystem"),
+            DrivesError::Timeout => f.write_str("drive command timed out"),
             DrivesError::IOError => f.write_str("io error"),
         }
     }
@@ -65,49 +92,231 @@ impl Display for DrivesError {
 
 #[derive(Debug)]
 pub enum FilesError {
+    DriveNotFound,
+    DriveNotMounted,
     OutsideMountRoot,
     NotDirectory,
+    NotFile,
+    InvalidName,
+    InvalidTarget,
+    InvalidPattern,
+    InsufficientSpace,
+    UploadError,
+    AlreadyExists,
+    NotFound,
+    PermissionDenied,
+    CrossDevice,
     IOError,
 }
 
 impl Display for FilesError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
+            FilesError::DriveNotFound => f.write_str("drive not found"),
+            FilesError::DriveNotMounted => f.write_str("drive is not mounted"),
             FilesError::OutsideMountRoot => f.write_str("path outside mount root"),
             FilesError::NotDirectory => f.write_str("path was not a directory"),
+            FilesError::NotFile => f.write_str("path was not a file"),
+            FilesError::InvalidName => f.write_str("invalid file name"),
+            FilesError::InvalidTarget => f.write_str("invalid target path"),
+            FilesError::InvalidPattern => f.write_str("invalid search pattern"),
+            FilesError::InsufficientSpace => f.write_str("not enough free space on drive"),
+            FilesError::UploadError => f.write_str("failed to read uploaded file"),
+            FilesError::AlreadyExists => f.write_str("path already exists"),
+            FilesError::NotFound => f.write_str("path does not exist"),
+            FilesError::PermissionDenied => f.write_str("permission denied"),
+            FilesError::CrossDevice => f.write_str("path is on a different drive"),
             FilesError::IOError => f.write_str("io error"),
         }
     }
 }
 
+/// Error type for resumable upload errors. Allows files
+/// errors for failures writing to the drive
+#[derive(Debug)]
+pub enum UploadsError {
+    NotFound,
+    InvalidOffset,
+    OffsetMismatch,
+    LengthExceeded,
+    Incomplete,
+    FilesError(FilesError),
+}
+
+impl Display for UploadsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadsError::NotFound => f.write_str("upload not found"),
+            UploadsError::InvalidOffset => f.write_str("missing or invalid upload offset"),
+            UploadsError::OffsetMismatch => f.write_str("upload offset does not match"),
+            UploadsError::LengthExceeded => f.write_str("upload exceeds declared length"),
+            UploadsError::Incomplete => f.write_str("upload is not complete"),
+            UploadsError::FilesError(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+/// Error type for invalid auto-mount policies or
+/// failures saving the policy
+#[derive(Debug)]
+pub enum AutoMountError {
+    InvalidPattern,
+    InvalidName,
+    IOError,
+}
+
+impl Display for AutoMountError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AutoMountError::InvalidPattern => f.write_str("invalid rule pattern"),
+            AutoMountError::InvalidName => f.write_str("invalid mount folder name"),
+            AutoMountError::IOError => f.write_str("io error"),
+        }
+    }
+}
+
+/// Error type for failures generating the samba
+/// share configuration or reloading samba
+#[derive(Debug)]
+pub enum SambaError {
+    InvalidConfig(String),
+    ReloadError(String),
+    UserError(String),
+    InvalidName,
+    InvalidPassword,
+    UserNotFound,
+    SharedTogether,
+    PrivilegedGroup,
+    CommandError,
+    IOError,
+}
+
+impl Display for SambaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SambaError::InvalidConfig(err) => write!(f, "invalid samba config: {}", err),
+            SambaError::ReloadError(err) => write!(f, "failed to reload samba: {}", err),
+            SambaError::UserError(err) => write!(f, "failed to update samba user: {}", err),
+            SambaError::InvalidName => f.write_str("invalid user name"),
+            SambaError::InvalidPassword => f.write_str("invalid password"),
+            SambaError::UserNotFound => f.write_str("user not found"),
+            SambaError::PrivilegedGroup => {
+                f.write_str("samba users can't be added to a privileged group")
+            }
+            SambaError::SharedTogether => {
+                f.write_str("drives are shared together so they can't have their own settings")
+            }
+            SambaError::CommandError => f.write_str("failed to execute samba command"),
+            SambaError::IOError => f.write_str("io error"),
+        }
+    }
+}
+
+/// Error type for failures reading or changing the hotspot
+/// configuration or starting and stopping the hotspot
 #[derive(Debug)]
 pub enum HotspotError {
     NotActivated,
+    NotConfigured,
+    InvalidConfig(&'static str),
     CommandError,
     CommandOutputError,
+    IOError,
 }
 
 impl Display for HotspotError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             HotspotError::NotActivated => f.write_str("failed to activate hotspot"),
+            HotspotError::NotConfigured => f.write_str("hotspot is not configured"),
+            HotspotError::InvalidConfig(err) => write!(f, "invalid hotspot config: {}", err),
             HotspotError::CommandError => f.write_str("failed to execute hotspot command"),
             HotspotError::CommandOutputError => {
                 f.write_str("failed to parse output from hotspot command")
             }
+            HotspotError::IOError => f.write_str("io error"),
+        }
+    }
+}
+
+/// Error type for requests for background jobs
+#[derive(Debug)]
+pub enum JobsError {
+    NotFound,
+}
+
+impl Display for JobsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            JobsError::NotFound => f.write_str("job not found"),
         }
     }
 }
 
 impl From<io::Error> for FilesError {
+    fn from(value: io::Error) -> Self {
+        match value.kind() {
+            io::ErrorKind::NotFound => FilesError::NotFound,
+            io::ErrorKind::AlreadyExists => FilesError::AlreadyExists,
+            io::ErrorKind::PermissionDenied => FilesError::PermissionDenied,
+            io::ErrorKind::CrossesDevices => FilesError::CrossDevice,
+            io::ErrorKind::StorageFull => FilesError::InsufficientSpace,
+            _ => FilesError::IOError,
+        }
+    }
+}
+
+impl From<DrivesError> for FilesError {
+    fn from(value: DrivesError) -> Self {
+        match value {
+            DrivesError::NotMounted => FilesError::DriveNotMounted,
+            _ => FilesError::IOError,
+        }
+    }
+}
+
+impl From<io::Error> for UploadsError {
+    fn from(value: io::Error) -> Self {
+        UploadsError::FilesError(value.into())
+    }
+}
+
+impl From<FilesError> for UploadsError {
+    fn from(value: FilesError) -> Self {
+        UploadsError::FilesError(value)
+    }
+}
+
+impl From<io::Error> for AutoMountError {
     fn from(_: io::Error) -> Self {
-        FilesError::IOError
+        AutoMountError::IOError
     }
 }
 
-impl From<io::Error> for DrivesError {
+impl From<io::Error> for SambaError {
+    fn from(_: io::Error) -> Self {
+        SambaError::IOError
+    }
+}
+
+impl From<DrivesError> for SambaError {
+    fn from(_: DrivesError) -> Self {
+        SambaError::CommandError
+    }
+}
+
+impl From<io::Error> for HotspotError {
     fn from(_: io::Error) -> Self {
-        DrivesError::IOError
+        HotspotError::IOError
+    }
+}
+
+impl From<io::Error> for DrivesError {
+    fn from(value: io::Error) -> Self {
+        match value.kind() {
+            io::ErrorKind::TimedOut => DrivesError::Timeout,
+            _ => DrivesError::IOError,
+        }
     }
 }
 
@@ -125,7 +334,7 @@ impl From<io::Error> for DrivesError {
 /// .map_err(|_|GenericError::ServerError)?
 /// ```
 pub fn server_error<E>(_: E) -> GenericError {
-    return GenericError::ServerError;
+    GenericError::ServerError
 }
 
 /// From trait to allow generic errors to be turned into
@@ -156,13 +365,99 @@ impl<Guard> From<PoisonError<Guard>> for AuthError {
 
 impl ResponseError for GenericError {}
 
-impl ResponseError for DrivesError {}
+impl ResponseError for DrivesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            DrivesError::UnknownDrive | DrivesError::DeviceNotFound => StatusCode::NOT_FOUND,
+            DrivesError::InvalidName | DrivesError::SystemDevice => StatusCode::BAD_REQUEST,
+            DrivesError::TargetBusy(_) | DrivesError::Mounted => StatusCode::CONFLICT,
+            DrivesError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            // Busy drives include the processes holding them so they can be closed
+            DrivesError::TargetBusy(holders) => {
+                HttpResponse::build(self.status_code()).json(json!({
+                    "error": self.to_string(),
+                    "holders": holders,
+                }))
+            }
+            _ => HttpResponse::build(self.status_code())
+                .insert_header(ContentType::plaintext())
+                .body(self.to_string()),
+        }
+    }
+}
 
 impl ResponseError for FilesError {
     fn status_code(&self) -> StatusCode {
         match self {
             FilesError::IOError => StatusCode::INTERNAL_SERVER_ERROR,
-            FilesError::NotDirectory | FilesError::OutsideMountRoot => StatusCode::BAD_REQUEST,
+            FilesError::NotDirectory
+            | FilesError::NotFile
+            | FilesError::InvalidName
+            | FilesError::InvalidTarget
+            | FilesError::InvalidPattern
+            | FilesError::UploadError
+            | FilesError::CrossDevice
+            | FilesError::OutsideMountRoot => StatusCode::BAD_REQUEST,
+            FilesError::AlreadyExists | FilesError::DriveNotMounted => StatusCode::CONFLICT,
+            FilesError::NotFound | FilesError::DriveNotFound => StatusCode::NOT_FOUND,
+            FilesError::PermissionDenied => StatusCode::FORBIDDEN,
+            FilesError::InsufficientSpace => StatusCode::INSUFFICIENT_STORAGE,
+        }
+    }
+}
+
+impl ResponseError for UploadsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UploadsError::NotFound => StatusCode::NOT_FOUND,
+            UploadsError::InvalidOffset => StatusCode::BAD_REQUEST,
+            UploadsError::OffsetMismatch | UploadsError::Incomplete => StatusCode::CONFLICT,
+            UploadsError::LengthExceeded => StatusCode::PAYLOAD_TOO_LARGE,
+            UploadsError::FilesError(err) => err.status_code(),
+        }
+    }
+}
+
+impl ResponseError for AutoMountError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AutoMountError::InvalidPattern | AutoMountError::InvalidName => StatusCode::BAD_REQUEST,
+            AutoMountError::IOError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl ResponseError for SambaError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SambaError::InvalidName | SambaError::InvalidPassword => StatusCode::BAD_REQUEST,
+            SambaError::UserNotFound => StatusCode::NOT_FOUND,
+            SambaError::SharedTogether => StatusCode::CONFLICT,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl ResponseError for HotspotError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            HotspotError::InvalidConfig(_) => StatusCode::BAD_REQUEST,
+            HotspotError::NotConfigured => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl ResponseError for JobsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            JobsError::NotFound => StatusCode::NOT_FOUND,
         }
     }
 }
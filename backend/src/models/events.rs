@@ -0,0 +1,65 @@
+use serde::Serialize;
+
+use crate::models::drives::{Drive, DriveEvent};
+use crate::models::jobs::JobStatus;
+
+/// Structure representing an event pushed to clients over the
+/// event stream. Serialized with a "type" field naming the event
+#[derive(Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    /// A drive was connected
+    DriveAdded { drive: Drive },
+    /// A drive was disconnected
+    DriveRemoved { drive: Drive },
+    /// The details of a drive changed (e.g. it was mounted)
+    DriveChanged { drive: Drive },
+    /// The result of a request to mount a drive
+    DriveMounted {
+        /// The drive path (e.g. /dev/sda1)
+        path: String,
+        /// The name of the folder the drive was mounted to
+        name: String,
+        /// The reason mounting failed. None if the drive was mounted
+        error: Option<String>,
+    },
+    /// The result of a request to unmount a drive
+    DriveUnmounted {
+        /// The drive path (e.g. /dev/sda1)
+        path: String,
+        /// The name of the folder the drive was mounted to
+        name: String,
+        /// The reason unmounting failed. None if the drive was unmounted
+        error: Option<String>,
+    },
+    /// The contents of a directory on a drive changed
+    FilesChanged {
+        /// The filesystem UUID of the drive
+        drive: String,
+        /// The path to the directory relative to the drive root
+        path: String,
+    },
+    /// A background job finished running
+    JobFinished { job: JobStatus },
+    /// The token used for the event stream will expire soon
+    TokenExpiring {
+        /// The expiry time of the token in milliseconds
+        expiry_time: u128,
+    },
+    /// The token used for the event stream has expired or was removed.
+    /// No more events are sent after this event
+    TokenExpired,
+    /// Events were missed because the client was reading them too slowly.
+    /// Clients should reload any state they are displaying
+    Resync,
+}
+
+impl From<DriveEvent> for ServerEvent {
+    fn from(value: DriveEvent) -> Self {
+        match value {
+            DriveEvent::Added(drive) => ServerEvent::DriveAdded { drive },
+            DriveEvent::Removed(drive) => ServerEvent::DriveRemoved { drive },
+            DriveEvent::Changed(drive) => ServerEvent::DriveChanged { drive },
+        }
+    }
+}
@@ -1,13 +1,190 @@
 use serde::{Deserialize, Serialize};
 
+/// The keys that the entries of a directory listing can be sorted by
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    /// Sort by name (case insensitive)
+    #[default]
+    Name,
+    /// Sort by size in bytes. Folders are sorted by name
+    Size,
+    /// Sort by last modified time
+    Modified,
+    /// Sort by creation time
+    Created,
+    /// Sort by last accessed time
+    Accessed,
+    /// Sort by MIME type. Folders are sorted by name
+    Type,
+}
+
+/// The order that the entries of a directory listing are sorted in
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Ascending order
+    #[default]
+    Asc,
+    /// Descending order
+    Desc,
+}
+
 /// Structure of a request for a list of files on a
 /// mounted drive.
 #[derive(Deserialize)]
 pub struct ListRequest {
-    /// The mount drive path
+    /// The path to the directory relative to the mount path
+    pub path: String,
+    /// The filesystem UUID of the drive
+    pub drive: String,
+    /// The key to sort the entries by. Defaults to name
+    #[serde(default)]
+    pub sort: SortKey,
+    /// The order to sort the entries in. Defaults to ascending
+    #[serde(default)]
+    pub order: SortOrder,
+    /// The number of entries to skip. Folders are always listed
+    /// before files when paging
+    #[serde(default)]
+    pub offset: usize,
+    /// The maximum number of entries to return. None for all entries
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Structure of a query for a single file stored on a
+/// mounted drive.
+#[derive(Deserialize)]
+pub struct FileRequest {
+    /// The path to the file relative to the mount path
     pub path: String,
+    /// The filesystem UUID of the drive
+    pub drive: String,
+}
+
+/// Structure of a query for uploading files into a directory
+/// on a mounted drive.
+#[derive(Deserialize)]
+pub struct UploadRequest {
     /// The path to the directory relative to the mount path
-    pub drive_path: String,
+    pub path: String,
+    /// The filesystem UUID of the drive
+    pub drive: String,
+}
+
+/// Structure of a request to create a new folder in a
+/// directory on a mounted drive.
+#[derive(Deserialize)]
+pub struct CreateFolderRequest {
+    /// The path to the parent directory relative to the mount path
+    pub path: String,
+    /// The filesystem UUID of the drive
+    pub drive: String,
+    /// The name of the new folder (e.g. Example Folder)
+    pub name: String,
+}
+
+/// Structure of a request to rename a file or folder on a
+/// mounted drive.
+#[derive(Deserialize)]
+pub struct RenameRequest {
+    /// The path to the file or folder relative to the mount path
+    pub path: String,
+    /// The filesystem UUID of the drive
+    pub drive: String,
+    /// The new name for the file or folder (e.g. example.txt)
+    pub name: String,
+}
+
+/// Structure of a request to move or copy a file or folder into
+/// a directory which may be on a different mounted drive.
+#[derive(Deserialize, Clone)]
+pub struct TransferRequest {
+    /// The path to the file or folder relative to the mount path
+    pub path: String,
+    /// The filesystem UUID of the drive
+    pub drive: String,
+    /// The path to the target directory relative to the target mount path
+    pub target_path: String,
+    /// The filesystem UUID of the drive the target directory is on
+    pub target_drive: String,
+}
+
+/// Structure of a request to delete a file or folder on a
+/// mounted drive.
+#[derive(Deserialize, Clone)]
+pub struct DeleteRequest {
+    /// The path to the file or folder relative to the mount path
+    pub path: String,
+    /// The filesystem UUID of the drive
+    pub drive: String,
+    /// Whether to permanently delete the file or folder instead
+    /// of moving it to the trash
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+/// The formats that archives of files and folders can be downloaded as
+#[derive(Deserialize, Default, Clone, Copy)]
+pub enum ArchiveFormat {
+    /// Uncompressed zip archive
+    #[default]
+    #[serde(rename = "zip")]
+    Zip,
+    /// Gzip compressed tar archive
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// The file extension for archives of this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+
+    /// The content type for archives of this format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::TarGz => "application/gzip",
+        }
+    }
+}
+
+/// Structure of a request to download files and folders on a
+/// mounted drive as a single archive.
+#[derive(Deserialize)]
+pub struct ArchiveRequest {
+    /// The paths to the files and folders relative to the mount path
+    pub paths: Vec<String>,
+    /// The filesystem UUID of the drive
+    pub drive: String,
+    /// The format of the archive. Defaults to zip
+    #[serde(default)]
+    pub format: ArchiveFormat,
+}
+
+/// Structure representing the result of uploading a single file
+#[derive(Serialize)]
+pub struct UploadResult {
+    /// The name of the uploaded file (e.g. example.txt)
+    pub name: String,
+    /// The number of bytes written to the drive
+    pub size: u64,
+    /// The reason the upload failed. None if the upload succeeded
+    pub error: Option<String>,
+}
+
+/// Structure representing a response that contains the results
+/// of each file in an upload request
+#[derive(Serialize)]
+pub struct UploadResponse {
+    /// The results for each uploaded file
+    pub files: Vec<UploadResult>,
 }
 
 /// Structure representing a file stored on a mounted drive
@@ -19,6 +196,23 @@ pub struct DriveFile {
     pub size: u64,
     /// The unix file permissions of the file
     pub permissions: u32,
+    /// The last modified time in milliseconds since the unix epoch
+    pub modified: Option<u128>,
+    /// The creation time in milliseconds since the unix epoch. None
+    /// if the filesystem doesn't record creation times
+    pub created: Option<u128>,
+    /// The last accessed time in milliseconds since the unix epoch
+    pub accessed: Option<u128>,
+    /// The user id of the owner of the file
+    pub uid: u32,
+    /// The group id of the owner of the file
+    pub gid: u32,
+    /// Whether the file is hidden (name starts with a dot)
+    pub hidden: bool,
+    /// The target path if the file is a symlink
+    pub symlink: Option<String>,
+    /// The MIME type guessed from the file extension (e.g. image/png)
+    pub mime: String,
 }
 
 /// Structure representing a folder stored on a mounted drive
@@ -28,6 +222,24 @@ pub struct DriveFolder {
     pub name: String,
     /// The unix file permissions for the folder
     pub permissions: u32,
+    /// The last modified time in milliseconds since the unix epoch
+    pub modified: Option<u128>,
+    /// The creation time in milliseconds since the unix epoch. None
+    /// if the filesystem doesn't record creation times
+    pub created: Option<u128>,
+    /// The last accessed time in milliseconds since the unix epoch
+    pub accessed: Option<u128>,
+    /// The user id of the owner of the folder
+    pub uid: u32,
+    /// The group id of the owner of the folder
+    pub gid: u32,
+    /// Whether the folder is hidden (name starts with a dot)
+    pub hidden: bool,
+    /// The target path if the folder is a symlink
+    pub symlink: Option<String>,
+    /// The number of files and folders directly inside the folder.
+    /// None if the folder could not be read
+    pub children: Option<u64>,
 }
 
 /// Structure representing a response that contains a list of
@@ -38,4 +250,8 @@ pub struct DriveList {
     pub folders: Vec<DriveFolder>,
     /// The list of files
     pub files: Vec<DriveFile>,
+    /// The total number of folders in the directory
+    pub total_folders: usize,
+    /// The total number of files in the directory
+    pub total_files: usize,
 }
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+/// The frequency band the hotspot broadcasts on
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub enum HotspotBand {
+    /// 2.4 GHz (hostapd hw_mode=g)
+    #[serde(rename = "2.4ghz")]
+    TwoPointFourGhz,
+    /// 5 GHz (hostapd hw_mode=a)
+    #[serde(rename = "5ghz")]
+    FiveGhz,
+}
+
+/// Structure representing the configuration of the hotspot
+/// access point from the hostapd config
+#[derive(Serialize, Clone, Default, PartialEq, Debug)]
+pub struct HotspotConfig {
+    /// The wireless interface the hotspot runs on (e.g. wlan0)
+    pub interface: Option<String>,
+    /// The name of the hotspot network
+    pub ssid: Option<String>,
+    /// The WPA passphrase for the hotspot network. Never included in
+    /// responses so it can't be read back by other clients
+    #[serde(skip_serializing)]
+    pub passphrase: Option<String>,
+    /// The channel the hotspot broadcasts on. 0 picks a channel automatically
+    pub channel: Option<u32>,
+    /// The frequency band the hotspot broadcasts on
+    pub band: Option<HotspotBand>,
+}
+
+/// Structure representing the current state of the hotspot
+#[derive(Serialize, PartialEq, Debug)]
+pub struct HotspotStatus {
+    /// Whether the hotspot is running
+    pub active: bool,
+    /// The frequency in MHz the hotspot is broadcasting on. None if not active
+    pub frequency: Option<u32>,
+    /// The number of connected clients. None if not active
+    pub clients: Option<u32>,
+    /// The configuration of the hotspot
+    pub config: HotspotConfig,
+}
+
+/// Structure for a request to change the hotspot configuration.
+/// Fields that are not provided are left unchanged
+#[derive(Deserialize, Default)]
+pub struct HotspotConfigRequest {
+    /// The name of the hotspot network
+    pub ssid: Option<String>,
+    /// The WPA passphrase for the hotspot network
+    pub passphrase: Option<String>,
+    /// The channel to broadcast on. 0 picks a channel automatically
+    pub channel: Option<u32>,
+    /// The frequency band to broadcast on
+    pub band: Option<HotspotBand>,
+}
+
+/// Structure representing a client connected to the hotspot
+#[derive(Serialize, PartialEq, Debug)]
+pub struct HotspotClient {
+    /// The MAC address of the client in lowercase
+    pub mac: String,
+    /// The IP address of the client
+    pub ip: Option<String>,
+    /// The hostname the client provided when leasing its address
+    pub hostname: Option<String>,
+    /// The unix time in milliseconds of when the DHCP lease expires.
+    /// None if the client has no lease or the lease never expires
+    pub expires: Option<u128>,
+    /// The neighbour state of the client from `ip neigh` (e.g. REACHABLE)
+    /// None if the client isn't in the neighbour table
+    pub state: Option<String>,
+    /// Whether the client is blocked from connecting to the hotspot
+    pub blocked: bool,
+}
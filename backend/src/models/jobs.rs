@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+/// The kinds of long running operations that run as jobs
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// Copying a file or folder into a directory
+    Copy,
+    /// Moving a file or folder into a directory
+    Move,
+    /// Checking and repairing the filesystem of a drive
+    Fsck,
+}
+
+/// The states a job can be in. Jobs only leave the running state once
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// Structure representing the current state of a job
+#[derive(Serialize, Clone)]
+pub struct JobStatus {
+    /// The unique id of the job
+    pub id: String,
+    /// The kind of operation the job is running
+    pub kind: JobKind,
+    /// Description of what the job is operating on (e.g. /dev/sda1)
+    pub target: String,
+    /// The current state of the job
+    pub state: JobState,
+    /// Percentage of the job that is complete. None if the
+    /// total amount of work isn't known
+    pub progress: Option<f64>,
+    /// The number of bytes processed so far
+    pub processed: u64,
+    /// The total number of bytes to process. None if not known
+    pub total: Option<u64>,
+    /// Details about the result of a completed job
+    pub message: Option<String>,
+    /// The reason the job failed. None unless the job failed
+    pub error: Option<String>,
+    /// Time in milliseconds for when the job was started
+    pub started: u128,
+    /// Time in milliseconds for when the job finished. None if running
+    pub finished: Option<u128>,
+}
+
+/// Structure of a request to check the filesystem of a drive
+#[derive(Deserialize)]
+pub struct FsckRequest {
+    /// The drive path (e.g. /dev/sda1)
+    pub path: String,
+}
@@ -1,4 +1,12 @@
 pub mod auth;
+pub mod automount;
 pub mod drives;
 pub mod errors;
+pub mod events;
 pub mod files;
+pub mod hotspot;
+pub mod jobs;
+pub mod samba;
+pub mod search;
+pub mod trash;
+pub mod uploads;
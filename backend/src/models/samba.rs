@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+/// Structure representing a user that can access the samba shares
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SambaUser {
+    /// The name of the user
+    pub name: String,
+    /// The unix user id of the user
+    pub uid: Option<u32>,
+    /// The full name of the user
+    pub full_name: String,
+}
+
+/// Structure for a request to create a samba user or
+/// change the password of an existing user
+#[derive(Deserialize)]
+pub struct CreateUserRequest {
+    /// The name of the user
+    pub name: String,
+    /// The password of the user
+    pub password: String,
+}
+
+/// Structure representing the access control settings for the
+/// samba share of a drive
+#[derive(Deserialize, Serialize, Clone, Default, Debug, PartialEq)]
+pub struct ShareSettings {
+    /// Whether the share is read only
+    #[serde(default)]
+    pub read_only: bool,
+    /// Whether guests can access the share without a password
+    #[serde(default)]
+    pub guest: bool,
+    /// The users allowed to access the share. Any user can
+    /// access the share when this is empty
+    #[serde(default)]
+    pub valid_users: Vec<String>,
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// The ways that a search query can be matched against names
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchMode {
+    /// Names containing the query
+    #[default]
+    Substring,
+    /// Names matching the query as a glob pattern (e.g. *.mp4)
+    Glob,
+    /// Names matching the query as a regular expression
+    Regex,
+}
+
+/// The kinds of entries that a search can be limited to
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    /// Both files and folders
+    #[default]
+    Any,
+    /// Only files
+    File,
+    /// Only folders
+    Folder,
+}
+
+/// Structure of a request to search for files and folders
+/// on one or all mounted drives
+#[derive(Deserialize)]
+pub struct SearchRequest {
+    /// The filesystem UUID of the drive to search. All mounted
+    /// drives are searched if not provided
+    pub drive: Option<String>,
+    /// The path to search in relative to the mount path
+    #[serde(default)]
+    pub path: String,
+    /// The query to match names against
+    pub query: String,
+    /// How the query is matched against names
+    #[serde(default)]
+    pub mode: SearchMode,
+    /// Whether the query is case sensitive
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// The kind of entries to include
+    #[serde(default)]
+    pub kind: SearchKind,
+    /// The minimum size in bytes of matching files
+    pub min_size: Option<u64>,
+    /// The maximum size in bytes of matching files
+    pub max_size: Option<u64>,
+    /// Time in milliseconds that entries must be modified after
+    pub modified_after: Option<u128>,
+    /// Time in milliseconds that entries must be modified before
+    pub modified_before: Option<u128>,
+    /// The maximum number of results to return
+    pub limit: Option<usize>,
+}
+
+/// Structure representing a file or folder matching a search
+#[derive(Serialize)]
+pub struct SearchResult {
+    /// The filesystem UUID of the drive the entry is stored on
+    pub drive: String,
+    /// The path to the entry relative to the mount path
+    pub path: String,
+    /// The name of the entry (e.g. example.txt)
+    pub name: String,
+    /// Whether the entry is a folder
+    pub folder: bool,
+    /// The size in bytes of the entry
+    pub size: u64,
+    /// Time in milliseconds for when the entry was last modified
+    pub modified: Option<u128>,
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+/// Structure of a query for the trash of a mounted drive
+#[derive(Deserialize)]
+pub struct TrashRequest {
+    /// The filesystem UUID of the drive
+    pub drive: String,
+}
+
+/// Structure of a request to restore an item from the trash
+/// of a mounted drive to its original path
+#[derive(Deserialize)]
+pub struct RestoreRequest {
+    /// The filesystem UUID of the drive
+    pub drive: String,
+    /// The id of the item in the trash
+    pub id: String,
+}
+
+/// Structure of a request to permanently remove items from the
+/// trash of a mounted drive
+#[derive(Deserialize)]
+pub struct EmptyTrashRequest {
+    /// The filesystem UUID of the drive
+    pub drive: String,
+    /// The id of a single item to remove. All items are
+    /// removed if this is not provided
+    pub id: Option<String>,
+}
+
+/// Structure of the sidecar record stored next to each item in the
+/// trash recording where the item was deleted from
+#[derive(Deserialize, Serialize)]
+pub struct TrashRecord {
+    /// The original path of the item relative to the drive root
+    pub path: String,
+    /// Time in milliseconds for when the item was deleted
+    pub deleted_at: u128,
+}
+
+/// Structure representing an item stored in the trash
+#[derive(Serialize)]
+pub struct TrashItem {
+    /// The id of the item in the trash
+    pub id: String,
+    /// The name of the item (e.g. example.txt)
+    pub name: String,
+    /// The original path of the item relative to the drive root
+    pub path: String,
+    /// Time in milliseconds for when the item was deleted
+    pub deleted_at: u128,
+    /// Whether the item is a folder
+    pub folder: bool,
+}
+
+/// Structure representing a response that contains the list
+/// of items in the trash
+#[derive(Serialize)]
+pub struct TrashList {
+    /// The list of items in the trash
+    pub items: Vec<TrashItem>,
+}
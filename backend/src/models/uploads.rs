@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+/// Structure of a request to create a new resumable upload
+/// into a directory on a mounted drive
+#[derive(Deserialize)]
+pub struct CreateUploadRequest {
+    /// The path to the directory relative to the mount path
+    pub path: String,
+    /// The filesystem UUID of the drive
+    pub drive: String,
+    /// The name of the file being uploaded (e.g. video.mp4)
+    pub name: String,
+    /// The total length of the file in bytes
+    pub length: u64,
+}
+
+/// Structure representing the current state of a resumable upload
+#[derive(Serialize)]
+pub struct UploadStatus {
+    /// The unique id of the upload used to send chunks
+    pub id: String,
+    /// The number of bytes that have been received so far
+    pub offset: u64,
+    /// The total length of the file in bytes
+    pub length: u64,
+    /// Time in milliseconds for when the upload will expire
+    /// if no more chunks are received
+    pub expiry_time: u128,
+}
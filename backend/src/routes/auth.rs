@@ -29,10 +29,7 @@ pub async fn auth(
         let time_elapsed = token_data.expiry_time.duration_since(UNIX_EPOCH)?;
 
         if let Some(address) = req.peer_addr() {
-            info!(
-                "Successful authentication attempt from: {}",
-                address.ip().to_string()
-            );
+            info!("Successful authentication attempt from: {}", address.ip());
         }
 
         ok_json(TokenDataResponse {
@@ -41,10 +38,7 @@ pub async fn auth(
         })
     } else {
         if let Some(address) = req.peer_addr() {
-            warn!(
-                "Invalid authentication attempt from: {}",
-                address.ip().to_string()
-            );
+            warn!("Invalid authentication attempt from: {}", address.ip());
         }
         Err(AuthError::InvalidCredentials)
     }
@@ -89,7 +83,7 @@ pub async fn delete_token(req: HttpRequest, auth_store: Data<AuthStore>) -> Auth
         info!(
             "Deleted authentication token {} for {}",
             token,
-            address.ip().to_string()
+            address.ip()
         );
     }
     ok_json_empty()
@@ -0,0 +1,33 @@
+use crate::define_routes;
+use actix_web::web::{Data, Json};
+use actix_web::{get, put, web};
+
+use crate::models::automount::AutoMountPolicy;
+use crate::models::errors::AutoMountError;
+use crate::stores::automount::AutoMountStore;
+use crate::utils::automount::check_policy;
+use crate::utils::{ok_json, ok_json_empty, JsonResult};
+
+define_routes!(get_policy, set_policy);
+
+type AutoMountResult<T> = JsonResult<T, AutoMountError>;
+type AutoMountResultEmpty = AutoMountResult<()>;
+
+/// API endpoint /api/automount for retrieving the auto-mount policy
+#[get("/automount")]
+pub async fn get_policy(store: Data<AutoMountStore>) -> AutoMountResult<AutoMountPolicy> {
+    ok_json(store.get_policy().await)
+}
+
+/// API endpoint /api/automount for replacing the auto-mount policy. The
+/// policy applies to drives connected after it is changed
+#[put("/automount")]
+pub async fn set_policy(
+    body: Json<AutoMountPolicy>,
+    store: Data<AutoMountStore>,
+) -> AutoMountResultEmpty {
+    let policy = body.into_inner();
+    check_policy(&policy)?;
+    store.set_policy(policy).await?;
+    ok_json_empty()
+}
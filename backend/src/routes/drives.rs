@@ -1,30 +1,237 @@
 use crate::define_routes;
-use crate::models::drives::{DrivesResponse, MountRequest};
+use crate::models::drives::{
+    DrivesResponse, EjectAction, EjectRequest, EjectStatus, KnownDrive, KnownDriveRequest,
+    MountRequest, UnmountRequest,
+};
 use crate::models::errors::DrivesError;
-use crate::utils::drives::{get_drive_list, mount_drive, unmount_drive};
+use crate::models::events::ServerEvent;
+use crate::models::jobs::{FsckRequest, JobKind, JobStatus};
+use crate::stores::drives::DriveRegistry;
+use crate::stores::events::EventBus;
+use crate::stores::jobs::JobStore;
+use crate::stores::known::KnownDriveStore;
+use crate::stores::locks::DeviceLocks;
+use crate::utils::automount::is_valid_name;
+use crate::utils::command::CommandRunner;
+use crate::utils::drives::{
+    check_filesystem, eject_device, find_drive_at, get_disk_path, get_drive_list, mount_drive,
+    unmount_drive,
+};
+use crate::utils::hotplug::refresh_drives;
+use crate::utils::jobs::spawn_job;
+use crate::utils::samba::update_samba_shares;
 use crate::utils::{ok_json, ok_json_empty, JsonResult};
-use actix_web::web::Json;
-use actix_web::{delete, get, post, web};
+use actix_web::web::{Data, Json, Path};
+use actix_web::{delete, get, post, put, web};
+use log::warn;
 
-define_routes!(list, unmount, mount);
+define_routes!(
+    list,
+    unmount,
+    mount,
+    eject,
+    fsck,
+    update_known,
+    forget_known
+);
 
 type DrivesResult<T> = JsonResult<T, DrivesError>;
 type DrivesResultEmpty = DrivesResult<()>;
 
 #[get("/drives")]
-pub async fn list() -> DrivesResult<DrivesResponse> {
-    let drives = get_drive_list()?;
+pub async fn list(
+    known_store: Data<KnownDriveStore>,
+    runner: Data<dyn CommandRunner>,
+) -> DrivesResult<DrivesResponse> {
+    let mut drives = web::block(move || get_drive_list(runner.as_ref()))
+        .await
+        .map_err(|_| DrivesError::IOError)??;
+    drives.known = known_store.get_drives(&drives.drives).await;
     ok_json(drives)
 }
 
 #[post("/drives")]
-pub async fn mount(body: Json<MountRequest>) -> DrivesResultEmpty {
-    mount_drive(&body.path, &body.name)?;
+pub async fn mount(
+    body: Json<MountRequest>,
+    registry: Data<DriveRegistry>,
+    event_bus: Data<EventBus>,
+    known_store: Data<KnownDriveStore>,
+    runner: Data<dyn CommandRunner>,
+    device_locks: Data<DeviceLocks>,
+) -> DrivesResultEmpty {
+    let body = body.into_inner();
+    if !is_valid_name(&body.name) {
+        return Err(DrivesError::InvalidName);
+    }
+    // Moved into the blocking task so the device stays locked until the
+    // mount finishes even if the request is dropped
+    let guard = device_locks.lock(&get_disk_path(&body.path)).await;
+    let (path, name, read_only) = (body.path.clone(), body.name.clone(), body.read_only);
+    let blocking_runner = runner.clone().into_inner();
+    let result = web::block(move || {
+        let _guard = guard;
+        mount_drive(blocking_runner.as_ref(), &path, &name, read_only)?;
+        update_samba_shares(blocking_runner.as_ref());
+        Ok::<_, DrivesError>(())
+    })
+    .await
+    .map_err(|_| DrivesError::IOError)?;
+    event_bus.publish(ServerEvent::DriveMounted {
+        path: body.path.clone(),
+        name: body.name.clone(),
+        error: result.as_ref().err().map(|err| err.to_string()),
+    });
+    result?;
+
+    // Remember how the drive was mounted so it's mounted the same way next time
+    if let Some(drive) = registry.get_drive(&body.path) {
+        if let Err(err) = known_store
+            .record_mount(&drive, &body.name, body.read_only)
+            .await
+        {
+            warn!("Failed to save known drives: {}", err);
+        }
+    }
+
+    // Mounting doesn't produce a uevent so the registry is refreshed here
+    refresh_drives(&registry, runner.into_inner()).await;
     ok_json_empty()
 }
 
 #[delete("/drives")]
-pub async fn unmount(body: Json<MountRequest>) -> DrivesResultEmpty {
-    unmount_drive(&body.path, &body.name)?;
+pub async fn unmount(
+    body: Json<UnmountRequest>,
+    registry: Data<DriveRegistry>,
+    event_bus: Data<EventBus>,
+    runner: Data<dyn CommandRunner>,
+    device_locks: Data<DeviceLocks>,
+) -> DrivesResultEmpty {
+    let body = body.into_inner();
+    if !is_valid_name(&body.name) {
+        return Err(DrivesError::InvalidName);
+    }
+    let (path, name) = (body.path.clone(), body.name.clone());
+    let guard = device_locks.lock(&get_disk_path(&path)).await;
+    let blocking_runner = runner.clone().into_inner();
+    // Terminating the processes holding a busy drive waits for them to exit
+    let result = web::block(move || {
+        let _guard = guard;
+        unmount_drive(
+            blocking_runner.as_ref(),
+            &body.path,
+            &body.name,
+            body.force,
+            body.include_samba,
+        )?;
+        update_samba_shares(blocking_runner.as_ref());
+        Ok::<_, DrivesError>(())
+    })
+    .await
+    .map_err(|_| DrivesError::IOError)?;
+    event_bus.publish(ServerEvent::DriveUnmounted {
+        path,
+        name,
+        error: result.as_ref().err().map(|err| err.to_string()),
+    });
+    result?;
+    refresh_drives(&registry, runner.into_inner()).await;
+    ok_json_empty()
+}
+
+/// API endpoint /api/drives/eject for safely ejecting the device a drive
+/// is on. Responds with the steps taken and whether the device can be
+/// unplugged
+#[post("/drives/eject")]
+pub async fn eject(
+    body: Json<EjectRequest>,
+    registry: Data<DriveRegistry>,
+    runner: Data<dyn CommandRunner>,
+    device_locks: Data<DeviceLocks>,
+) -> DrivesResult<EjectStatus> {
+    let path = body.into_inner().path;
+    let guard = device_locks.lock(&get_disk_path(&path)).await;
+    let blocking_runner = runner.clone().into_inner();
+    let status = web::block(move || {
+        let _guard = guard;
+        let status = eject_device(blocking_runner.as_ref(), &path)?;
+        let unmounted = status
+            .steps
+            .iter()
+            .any(|step| step.action == EjectAction::Unmount && step.error.is_none());
+        if unmounted {
+            update_samba_shares(blocking_runner.as_ref());
+        }
+        Ok::<_, DrivesError>(status)
+    })
+    .await
+    .map_err(|_| DrivesError::IOError)??;
+    refresh_drives(&registry, runner.into_inner()).await;
+    ok_json(status)
+}
+
+/// API endpoint /api/drives/fsck for checking and repairing the filesystem
+/// of an unmounted drive. The check runs as a background job which is
+/// responded with. The device stays locked until the check finishes
+#[post("/drives/fsck")]
+pub async fn fsck(
+    body: Json<FsckRequest>,
+    job_store: Data<JobStore>,
+    event_bus: Data<EventBus>,
+    runner: Data<dyn CommandRunner>,
+    device_locks: Data<DeviceLocks>,
+) -> DrivesResult<JobStatus> {
+    let path = body.into_inner().path;
+    let guard = device_locks.lock(&get_disk_path(&path)).await;
+
+    let runner = runner.into_inner();
+    let blocking_runner = runner.clone();
+    let drive_path = path.clone();
+    let drive = web::block(move || find_drive_at(blocking_runner.as_ref(), &drive_path))
+        .await
+        .map_err(|_| DrivesError::IOError)??
+        .ok_or(DrivesError::DeviceNotFound)?;
+    if drive.mount.is_some() {
+        return Err(DrivesError::Mounted);
+    }
+
+    let job = job_store.create_job(JobKind::Fsck, &path).await;
+    spawn_job(job.clone(), event_bus.into_inner(), move |job| {
+        let _guard = guard;
+        check_filesystem(runner.as_ref(), &path, &|| job.is_cancelled()).map(Some)
+    });
+    ok_json(job.status())
+}
+
+/// API endpoint /api/drives/known/{uuid} for updating the saved
+/// settings of a known drive
+#[put("/drives/known/{uuid}")]
+pub async fn update_known(
+    uuid: Path<String>,
+    body: Json<KnownDriveRequest>,
+    known_store: Data<KnownDriveStore>,
+) -> DrivesResult<KnownDrive> {
+    let body = body.into_inner();
+    if let Some(mount_name) = &body.mount_name {
+        if !is_valid_name(mount_name) {
+            return Err(DrivesError::InvalidName);
+        }
+    }
+    let drive = known_store
+        .update_drive(&uuid, body)
+        .await?
+        .ok_or(DrivesError::UnknownDrive)?;
+    ok_json(drive)
+}
+
+/// API endpoint /api/drives/known/{uuid} for forgetting a known drive
+/// along with its saved settings
+#[delete("/drives/known/{uuid}")]
+pub async fn forget_known(
+    uuid: Path<String>,
+    known_store: Data<KnownDriveStore>,
+) -> DrivesResultEmpty {
+    if !known_store.remove_drive(&uuid).await? {
+        return Err(DrivesError::UnknownDrive);
+    }
     ok_json_empty()
 }
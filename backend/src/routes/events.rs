@@ -0,0 +1,35 @@
+use crate::define_routes;
+use actix_web::web::Data;
+use actix_web::{get, web, HttpRequest, HttpResponse};
+
+use crate::middleware::auth::get_request_token;
+use crate::models::errors::AuthError;
+use crate::stores::auth::AuthStore;
+use crate::stores::drives::DriveRegistry;
+use crate::stores::events::EventBus;
+use crate::utils::events::stream_events;
+
+define_routes!(events);
+
+/// API endpoint /api/events for receiving server events as they happen
+/// using server sent events. Because EventSource cannot set headers the
+/// token can be provided using the token query parameter
+#[get("/events")]
+pub async fn events(
+    req: HttpRequest,
+    auth_store: Data<AuthStore>,
+    event_bus: Data<EventBus>,
+    registry: Data<DriveRegistry>,
+) -> Result<HttpResponse, AuthError> {
+    let token = get_request_token(&req)?;
+    let stream = stream_events(
+        token,
+        auth_store.into_inner(),
+        event_bus.subscribe(),
+        registry.subscribe(),
+    );
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream))
+}
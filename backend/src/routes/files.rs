@@ -1,18 +1,346 @@
 use crate::define_routes;
-use actix_web::web::Json;
-use actix_web::{post, web};
+use actix_files::NamedFile;
+use actix_multipart::Multipart;
+use actix_web::http::header::{
+    ContentDisposition, DispositionParam, DispositionType, CONTENT_LENGTH,
+};
+use actix_web::web::{Data, Json, Query};
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use futures::StreamExt;
+use log::warn;
 
 use crate::models::errors::FilesError;
-use crate::models::files::{DriveList, ListRequest};
-use crate::utils::files::get_files_at;
-use crate::utils::{ok_json, JsonResult};
+use crate::models::files::{
+    ArchiveRequest, CreateFolderRequest, DeleteRequest, DriveList, FileRequest, ListRequest,
+    RenameRequest, TransferRequest, UploadRequest, UploadResponse, UploadResult,
+};
+use crate::models::jobs::{JobKind, JobStatus};
+use crate::stores::drives::DriveRegistry;
+use crate::stores::events::EventBus;
+use crate::stores::jobs::{Job, JobStore};
+use crate::utils::archive::stream_archive;
+use crate::utils::command::CommandRunner;
+use crate::utils::drives::get_free_space;
+use crate::utils::files::{
+    copy_entry, create_folder, delete_entry, get_directory_at, get_drive_dir, get_entries_at,
+    get_files_at, get_transfer_paths, move_entry, open_file_at, rename_entry, write_file_stream,
+    DriveEntry,
+};
+use crate::utils::jobs::spawn_job;
+use crate::utils::trash::trash_entry;
+use crate::utils::{ok_json, ok_json_empty, JsonResult};
 
-define_routes!(list);
+define_routes!(list, download, archive, upload, new_folder, rename, move_to, copy_to, remove);
+
+type FilesResult<T> = JsonResult<T, FilesError>;
+type FilesResultEmpty = FilesResult<()>;
+
+/// Runs the provided file operation on the blocking thread pool so that
+/// long running operations on large folders or slow drives don't stall
+/// the worker
+async fn run_blocking<F, T>(operation: F) -> Result<T, FilesError>
+where
+    F: FnOnce() -> Result<T, FilesError> + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(operation)
+        .await
+        .map_err(|_| FilesError::IOError)?
+}
 
 /// API endpoint /api/files for listing files stored on a mounted drive
 #[post("/files")]
-pub async fn list(body: Json<ListRequest>) -> JsonResult<DriveList, FilesError> {
+pub async fn list(
+    body: Json<ListRequest>,
+    registry: Data<DriveRegistry>,
+) -> FilesResult<DriveList> {
     // Load the list of files on the drive
-    let response = get_files_at(&body.drive_path, &body.path)?;
+    let response = run_blocking(move || {
+        let drive_root = get_drive_dir(&registry, &body.drive)?;
+        get_files_at(&drive_root, &body)
+    })
+    .await?;
     ok_json(response)
 }
+
+/// API endpoint /api/files/download for downloading a file stored on a
+/// mounted drive. The file is streamed from disk and NamedFile handles
+/// the Range and If-Range headers so downloads can be seeked and resumed
+#[get("/files/download")]
+pub async fn download(
+    query: Query<FileRequest>,
+    registry: Data<DriveRegistry>,
+) -> Result<NamedFile, FilesError> {
+    let file = run_blocking(move || {
+        let drive_root = get_drive_dir(&registry, &query.drive)?;
+        let (file, path) = open_file_at(&drive_root, &query.path)?;
+        Ok(NamedFile::from_file(file, path)?)
+    })
+    .await?;
+    Ok(file.use_etag(true).use_last_modified(true))
+}
+
+/// API endpoint /api/files/archive for downloading files and folders
+/// from a mounted drive as a single archive. The archive is created and
+/// streamed to the client as it is downloaded
+#[post("/files/archive")]
+pub async fn archive(
+    body: Json<ArchiveRequest>,
+    registry: Data<DriveRegistry>,
+) -> Result<HttpResponse, FilesError> {
+    let body = body.into_inner();
+    let format = body.format;
+    let (name, entries) = run_blocking(move || {
+        let drive_root = get_drive_dir(&registry, &body.drive)?;
+        let entries = get_entries_at(&drive_root, &body.paths)?;
+
+        // Single entries are named after the entry otherwise after the drive
+        let name = match entries.as_slice() {
+            [] => return Err(FilesError::InvalidTarget),
+            [entry] => Some(entry.name.as_os_str()),
+            [_, ..] => drive_root.path.file_name(),
+        }
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+        Ok((name, entries))
+    })
+    .await?;
+
+    let disposition = ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(format!(
+            "{}.{}",
+            name,
+            format.extension()
+        ))],
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(disposition)
+        .streaming(stream_archive(entries, format)))
+}
+
+/// API endpoint /api/files/upload for uploading multipart/form-data files
+/// into a directory on a mounted drive. Each file is streamed to disk and
+/// the result of each file is reported in the response
+#[post("/files/upload")]
+pub async fn upload(
+    req: HttpRequest,
+    query: Query<UploadRequest>,
+    mut payload: Multipart,
+    event_bus: Data<EventBus>,
+    registry: Data<DriveRegistry>,
+    runner: Data<dyn CommandRunner>,
+) -> FilesResult<UploadResponse> {
+    let (query, directory, mut available) = run_blocking(move || {
+        let directory = get_directory_at(&get_drive_dir(&registry, &query.drive)?, &query.path)?;
+        let available =
+            get_free_space(runner.as_ref(), &directory.path).map_err(|_| FilesError::IOError)?;
+        Ok((query.into_inner(), directory, available))
+    })
+    .await?;
+
+    // Reject requests that are known to be too large before reading them
+    let content_length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if let Some(content_length) = content_length {
+        if content_length > available {
+            return Err(FilesError::InsufficientSpace);
+        }
+    }
+
+    let mut files = Vec::new();
+
+    while let Some(field) = payload.next().await {
+        let field = field.map_err(|_| FilesError::UploadError)?;
+
+        // Fields without a file name are not files and are ignored
+        let name = match field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+        {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+
+        let result = match write_file_stream(&directory, &name, field, available).await {
+            Ok(size) => {
+                available = available.saturating_sub(size);
+                UploadResult {
+                    name,
+                    size,
+                    error: None,
+                }
+            }
+            Err(err) => {
+                warn!("Failed to upload file {}: {}", name, err);
+                UploadResult {
+                    name,
+                    size: 0,
+                    error: Some(err.to_string()),
+                }
+            }
+        };
+        files.push(result);
+    }
+
+    if files.iter().any(|file| file.error.is_none()) {
+        event_bus.files_changed(&query.drive, &query.path);
+    }
+
+    ok_json(UploadResponse { files })
+}
+
+/// API endpoint /api/files/folder for creating a new folder in a
+/// directory on a mounted drive
+#[post("/files/folder")]
+pub async fn new_folder(
+    body: Json<CreateFolderRequest>,
+    event_bus: Data<EventBus>,
+    registry: Data<DriveRegistry>,
+) -> FilesResultEmpty {
+    let body = run_blocking(move || {
+        create_folder(
+            &get_drive_dir(&registry, &body.drive)?,
+            &body.path,
+            &body.name,
+        )?;
+        Ok(body)
+    })
+    .await?;
+    event_bus.files_changed(&body.drive, &body.path);
+    ok_json_empty()
+}
+
+/// API endpoint /api/files/rename for renaming a file or folder
+/// on a mounted drive
+#[post("/files/rename")]
+pub async fn rename(
+    body: Json<RenameRequest>,
+    event_bus: Data<EventBus>,
+    registry: Data<DriveRegistry>,
+) -> FilesResultEmpty {
+    let body = run_blocking(move || {
+        rename_entry(
+            &get_drive_dir(&registry, &body.drive)?,
+            &body.path,
+            &body.name,
+        )?;
+        Ok(body)
+    })
+    .await?;
+    event_bus.entry_changed(&body.drive, &body.path);
+    ok_json_empty()
+}
+
+/// Resolves the paths for the provided transfer request on the
+/// blocking thread pool then starts a job of the provided kind
+/// which runs the transfer in the background
+async fn start_transfer<F>(
+    body: TransferRequest,
+    kind: JobKind,
+    job_store: &JobStore,
+    event_bus: Data<EventBus>,
+    registry: Data<DriveRegistry>,
+    transfer: F,
+) -> FilesResult<JobStatus>
+where
+    F: FnOnce(&DriveEntry, &DriveEntry, &Job) -> Result<(), FilesError> + Send + 'static,
+{
+    let request = body.clone();
+    let (entry, target) = web::block(move || {
+        get_transfer_paths(
+            &get_drive_dir(&registry, &request.drive)?,
+            &request.path,
+            &get_drive_dir(&registry, &request.target_drive)?,
+            &request.target_path,
+        )
+    })
+    .await
+    .map_err(|_| FilesError::IOError)??;
+
+    let job = job_store
+        .create_job(kind, &format!("{}:{}", body.drive, body.path))
+        .await;
+    let event_bus = event_bus.into_inner();
+    spawn_job(job.clone(), event_bus.clone(), move |job| {
+        let result = transfer(&entry, &target, job);
+        // Cancelled transfers may have changed the directories
+        if kind == JobKind::Move {
+            event_bus.entry_changed(&body.drive, &body.path);
+        }
+        event_bus.files_changed(&body.target_drive, &body.target_path);
+        result.map(|_| None)
+    });
+    ok_json(job.status())
+}
+
+/// API endpoint /api/files/move for moving a file or folder into a
+/// directory which can be on a different mounted drive. The move runs
+/// as a background job which is responded with
+#[post("/files/move")]
+pub async fn move_to(
+    body: Json<TransferRequest>,
+    job_store: Data<JobStore>,
+    event_bus: Data<EventBus>,
+    registry: Data<DriveRegistry>,
+) -> FilesResult<JobStatus> {
+    start_transfer(
+        body.into_inner(),
+        JobKind::Move,
+        &job_store,
+        event_bus,
+        registry,
+        |entry, target, job| move_entry(entry, target, job),
+    )
+    .await
+}
+
+/// API endpoint /api/files/copy for copying a file or folder into a
+/// directory which can be on a different mounted drive. The copy runs
+/// as a background job which is responded with
+#[post("/files/copy")]
+pub async fn copy_to(
+    body: Json<TransferRequest>,
+    job_store: Data<JobStore>,
+    event_bus: Data<EventBus>,
+    registry: Data<DriveRegistry>,
+) -> FilesResult<JobStatus> {
+    start_transfer(
+        body.into_inner(),
+        JobKind::Copy,
+        &job_store,
+        event_bus,
+        registry,
+        |entry, target, job| copy_entry(entry, target, job),
+    )
+    .await
+}
+
+/// API endpoint /api/files for deleting a file or folder on a mounted
+/// drive. Files and folders are moved to the trash of the drive unless
+/// permanent is set in which case folders are deleted with their contents
+#[delete("/files")]
+pub async fn remove(
+    body: Json<DeleteRequest>,
+    event_bus: Data<EventBus>,
+    registry: Data<DriveRegistry>,
+) -> FilesResultEmpty {
+    let body = body.into_inner();
+    let request = body.clone();
+    run_blocking(move || {
+        let drive_root = get_drive_dir(&registry, &request.drive)?;
+        if request.permanent {
+            delete_entry(&drive_root, &request.path)
+        } else {
+            trash_entry(&drive_root, &request.path)
+        }
+    })
+    .await?;
+    event_bus.entry_changed(&body.drive, &body.path);
+    ok_json_empty()
+}
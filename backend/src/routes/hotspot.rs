@@ -0,0 +1,112 @@
+use crate::define_routes;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{delete, get, post, put, web};
+
+use crate::models::errors::HotspotError;
+use crate::models::hotspot::{HotspotClient, HotspotConfigRequest, HotspotStatus};
+use crate::utils::command::CommandRunner;
+use crate::utils::hotspot::{
+    get_blocked_clients, get_hotspot_clients, get_hotspot_status, set_client_blocked,
+    set_hotspot_config, start_hotspot, stop_hotspot, HotspotSettings,
+};
+use crate::utils::{ok_json, JsonResult};
+
+define_routes!(
+    get_status,
+    set_config,
+    start,
+    stop,
+    list_clients,
+    list_blocked,
+    block_client,
+    unblock_client
+);
+
+type HotspotResult<T = HotspotStatus> = JsonResult<T, HotspotError>;
+
+/// Runs the provided hotspot operation on the blocking thread pool
+/// as it waits for the hotspot commands to complete
+async fn run_blocking<F, T>(runner: Data<dyn CommandRunner>, operation: F) -> HotspotResult<T>
+where
+    F: FnOnce(&dyn CommandRunner, &HotspotSettings) -> Result<T, HotspotError> + Send + 'static,
+    T: Send + 'static,
+{
+    let value = web::block(move || operation(runner.as_ref(), &HotspotSettings::from_env()))
+        .await
+        .map_err(|_| HotspotError::IOError)??;
+    ok_json(value)
+}
+
+/// API endpoint /api/hotspot for retrieving the hotspot
+/// configuration and whether it is running
+#[get("/hotspot")]
+pub async fn get_status(runner: Data<dyn CommandRunner>) -> HotspotResult {
+    run_blocking(runner, get_hotspot_status).await
+}
+
+/// API endpoint /api/hotspot for changing the hotspot configuration
+#[put("/hotspot")]
+pub async fn set_config(
+    runner: Data<dyn CommandRunner>,
+    body: Json<HotspotConfigRequest>,
+) -> HotspotResult {
+    let body = body.into_inner();
+    run_blocking(runner, move |runner, settings| {
+        set_hotspot_config(runner, settings, &body)
+    })
+    .await
+}
+
+/// API endpoint /api/hotspot/start for starting the hotspot
+#[post("/hotspot/start")]
+pub async fn start(runner: Data<dyn CommandRunner>) -> HotspotResult {
+    run_blocking(runner, start_hotspot).await
+}
+
+/// API endpoint /api/hotspot/stop for stopping the hotspot
+#[post("/hotspot/stop")]
+pub async fn stop(runner: Data<dyn CommandRunner>) -> HotspotResult {
+    run_blocking(runner, stop_hotspot).await
+}
+
+/// API endpoint /api/hotspot/clients for listing the clients
+/// connected to the hotspot
+#[get("/hotspot/clients")]
+pub async fn list_clients(runner: Data<dyn CommandRunner>) -> HotspotResult<Vec<HotspotClient>> {
+    run_blocking(runner, get_hotspot_clients).await
+}
+
+/// API endpoint /api/hotspot/blocked for listing the MAC addresses
+/// blocked from connecting to the hotspot
+#[get("/hotspot/blocked")]
+pub async fn list_blocked(runner: Data<dyn CommandRunner>) -> HotspotResult<Vec<String>> {
+    run_blocking(runner, |_, settings| get_blocked_clients(settings)).await
+}
+
+/// API endpoint /api/hotspot/blocked/{mac} for blocking the client
+/// with the provided MAC address from connecting to the hotspot
+#[put("/hotspot/blocked/{mac}")]
+pub async fn block_client(
+    runner: Data<dyn CommandRunner>,
+    mac: Path<String>,
+) -> HotspotResult<Vec<String>> {
+    let mac = mac.into_inner();
+    run_blocking(runner, move |runner, settings| {
+        set_client_blocked(runner, settings, &mac, true)
+    })
+    .await
+}
+
+/// API endpoint /api/hotspot/blocked/{mac} for unblocking the client
+/// with the provided MAC address
+#[delete("/hotspot/blocked/{mac}")]
+pub async fn unblock_client(
+    runner: Data<dyn CommandRunner>,
+    mac: Path<String>,
+) -> HotspotResult<Vec<String>> {
+    let mac = mac.into_inner();
+    run_blocking(runner, move |runner, settings| {
+        set_client_blocked(runner, settings, &mac, false)
+    })
+    .await
+}
@@ -0,0 +1,40 @@
+use crate::define_routes;
+use actix_web::web::{Data, Path};
+use actix_web::{delete, get, web};
+
+use crate::models::errors::JobsError;
+use crate::models::jobs::JobStatus;
+use crate::stores::jobs::JobStore;
+use crate::utils::{ok_json, JsonResult};
+
+define_routes!(list, status, remove);
+
+type JobsResult<T> = JsonResult<T, JobsError>;
+
+/// API endpoint /api/jobs for listing the running jobs along
+/// with the recently finished jobs
+#[get("/jobs")]
+pub async fn list(job_store: Data<JobStore>) -> JobsResult<Vec<JobStatus>> {
+    ok_json(job_store.get_jobs().await)
+}
+
+/// API endpoint /api/jobs/{id} for retrieving the progress
+/// and result of a job
+#[get("/jobs/{id}")]
+pub async fn status(id: Path<String>, job_store: Data<JobStore>) -> JobsResult<JobStatus> {
+    let job = job_store.get_job(&id).await.ok_or(JobsError::NotFound)?;
+    ok_json(job.status())
+}
+
+/// API endpoint /api/jobs/{id} for cancelling a running job or removing
+/// a finished job from the history. Responds with the state of the job
+#[delete("/jobs/{id}")]
+pub async fn remove(id: Path<String>, job_store: Data<JobStore>) -> JobsResult<JobStatus> {
+    let job = job_store.get_job(&id).await.ok_or(JobsError::NotFound)?;
+    if job.is_finished() {
+        job_store.remove_job(&id).await;
+    } else {
+        job.cancel();
+    }
+    ok_json(job.status())
+}
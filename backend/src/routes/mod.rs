@@ -9,8 +9,16 @@ use crate::stores::auth::AuthStore;
 
 pub mod app;
 pub mod auth;
+pub mod automount;
 pub mod drives;
+pub mod events;
 pub mod files;
+pub mod hotspot;
+pub mod jobs;
+pub mod samba;
+pub mod search;
+pub mod trash;
+pub mod uploads;
 
 /// Creates a scope that is protected by the auth store
 /// authentication middleware
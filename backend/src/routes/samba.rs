@@ -0,0 +1,89 @@
+use crate::define_routes;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{delete, get, post, put, web};
+
+use crate::models::errors::SambaError;
+use crate::models::samba::{CreateUserRequest, SambaUser, ShareSettings};
+use crate::utils::command::CommandRunner;
+use crate::utils::samba::{
+    create_user, delete_user, get_share_settings, get_users, set_share_settings, SambaConfig,
+};
+use crate::utils::{ok_json, JsonResult};
+
+define_routes!(list_users, new_user, remove_user, get_share, set_share);
+
+type SambaResult<T> = JsonResult<T, SambaError>;
+type SambaResultEmpty = SambaResult<()>;
+
+/// Runs the provided samba operation on the blocking thread pool
+/// as it waits for the samba commands to complete
+async fn run_blocking<F, T>(runner: Data<dyn CommandRunner>, operation: F) -> SambaResult<T>
+where
+    F: FnOnce(&SambaConfig, &dyn CommandRunner) -> Result<T, SambaError> + Send + 'static,
+    T: Send + 'static,
+{
+    let value = web::block(move || operation(&SambaConfig::from_env(), runner.as_ref()))
+        .await
+        .map_err(|_| SambaError::IOError)??;
+    ok_json(value)
+}
+
+/// API endpoint /api/samba/users for listing the samba users
+#[get("/samba/users")]
+pub async fn list_users(runner: Data<dyn CommandRunner>) -> SambaResult<Vec<SambaUser>> {
+    run_blocking(runner, get_users).await
+}
+
+/// API endpoint /api/samba/users for creating a samba user or
+/// changing the password of an existing user
+#[post("/samba/users")]
+pub async fn new_user(
+    body: Json<CreateUserRequest>,
+    runner: Data<dyn CommandRunner>,
+) -> SambaResultEmpty {
+    let body = body.into_inner();
+    run_blocking(runner, move |config, runner| {
+        create_user(config, runner, &body.name, &body.password)
+    })
+    .await
+}
+
+/// API endpoint /api/samba/users/{name} for removing a samba user
+#[delete("/samba/users/{name}")]
+pub async fn remove_user(name: Path<String>, runner: Data<dyn CommandRunner>) -> SambaResultEmpty {
+    let name = name.into_inner();
+    run_blocking(runner, move |config, runner| {
+        delete_user(config, runner, &name)
+    })
+    .await
+}
+
+/// API endpoint /api/samba/shares/{drive} for retrieving the share
+/// settings of the drive with the provided UUID
+#[get("/samba/shares/{drive}")]
+pub async fn get_share(
+    drive: Path<String>,
+    runner: Data<dyn CommandRunner>,
+) -> SambaResult<ShareSettings> {
+    let drive = drive.into_inner();
+    run_blocking(runner, move |config, _| {
+        Ok(get_share_settings(config, &drive))
+    })
+    .await
+}
+
+/// API endpoint /api/samba/shares/{drive} for replacing the share
+/// settings of the drive with the provided UUID
+#[put("/samba/shares/{drive}")]
+pub async fn set_share(
+    drive: Path<String>,
+    body: Json<ShareSettings>,
+    runner: Data<dyn CommandRunner>,
+) -> SambaResultEmpty {
+    let drive = drive.into_inner();
+    let settings = body.into_inner();
+    run_blocking(runner, move |config, runner| {
+        set_share_settings(config, runner, &drive, settings)
+    })
+    .await
+}
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use crate::define_routes;
+use actix_web::web::{Data, Json};
+use actix_web::{post, web, HttpResponse};
+
+use crate::models::errors::FilesError;
+use crate::models::search::SearchRequest;
+use crate::stores::drives::DriveRegistry;
+use crate::utils::files::{get_directory_at, get_drive_dir, get_drive_roots};
+use crate::utils::search::{stream_search, SearchFilter, SearchRoot, MAX_SEARCH_RESULTS};
+
+define_routes!(search);
+
+/// API endpoint /api/search for searching for files and folders on one or
+/// all mounted drives. Results are streamed as newline delimited JSON as they
+/// are found and the search stops when the client disconnects
+#[post("/search")]
+pub async fn search(
+    body: Json<SearchRequest>,
+    registry: Data<DriveRegistry>,
+) -> Result<HttpResponse, FilesError> {
+    let body = body.into_inner();
+    let filter = SearchFilter::new(&body)?;
+    let limit = body
+        .limit
+        .unwrap_or(MAX_SEARCH_RESULTS)
+        .clamp(1, MAX_SEARCH_RESULTS);
+
+    // Opening the roots can block on slow drives
+    let roots = web::block(move || {
+        let roots = match body.drive {
+            Some(drive) => {
+                let drive_root = get_drive_dir(&registry, &drive)?;
+                let directory = get_directory_at(&drive_root, &body.path)?;
+                let path = directory
+                    .path
+                    .strip_prefix(&drive_root.path)
+                    .map_err(|_| FilesError::InvalidTarget)?
+                    .to_path_buf();
+                vec![SearchRoot {
+                    drive,
+                    directory,
+                    path,
+                }]
+            }
+            None => get_drive_roots(&registry)?
+                .into_iter()
+                .map(|(drive, directory)| SearchRoot {
+                    drive,
+                    directory,
+                    path: PathBuf::new(),
+                })
+                .collect(),
+        };
+        Ok::<_, FilesError>(roots)
+    })
+    .await
+    .map_err(|_| FilesError::IOError)??;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(stream_search(roots, filter, limit)))
+}
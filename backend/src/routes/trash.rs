@@ -0,0 +1,61 @@
+use crate::define_routes;
+use actix_web::web::{Data, Json, Query};
+use actix_web::{delete, get, post, web};
+
+use crate::models::errors::FilesError;
+use crate::models::trash::{EmptyTrashRequest, RestoreRequest, TrashList, TrashRequest};
+use crate::stores::drives::DriveRegistry;
+use crate::stores::events::EventBus;
+use crate::utils::files::get_drive_dir;
+use crate::utils::trash::{empty_trash, get_trash_items, restore_trash_item};
+use crate::utils::{ok_json, ok_json_empty, JsonResult};
+
+define_routes!(list, restore, empty);
+
+type TrashResult<T> = JsonResult<T, FilesError>;
+type TrashResultEmpty = TrashResult<()>;
+
+/// API endpoint /api/trash for listing the items in the trash
+/// of a mounted drive
+#[get("/trash")]
+pub async fn list(
+    query: Query<TrashRequest>,
+    registry: Data<DriveRegistry>,
+) -> TrashResult<TrashList> {
+    let items = web::block(move || get_trash_items(&get_drive_dir(&registry, &query.drive)?))
+        .await
+        .map_err(|_| FilesError::IOError)??;
+    ok_json(TrashList { items })
+}
+
+/// API endpoint /api/trash/restore for restoring an item in the
+/// trash of a mounted drive to its original path
+#[post("/trash/restore")]
+pub async fn restore(
+    body: Json<RestoreRequest>,
+    event_bus: Data<EventBus>,
+    registry: Data<DriveRegistry>,
+) -> TrashResultEmpty {
+    let body = body.into_inner();
+    let drive = body.drive.clone();
+    let path =
+        web::block(move || restore_trash_item(&get_drive_dir(&registry, &body.drive)?, &body.id))
+            .await
+            .map_err(|_| FilesError::IOError)??;
+    event_bus.entry_changed(&drive, &path);
+    ok_json_empty()
+}
+
+/// API endpoint /api/trash for permanently removing an item or
+/// every item from the trash of a mounted drive
+#[delete("/trash")]
+pub async fn empty(
+    body: Json<EmptyTrashRequest>,
+    registry: Data<DriveRegistry>,
+) -> TrashResultEmpty {
+    let body = body.into_inner();
+    web::block(move || empty_trash(&get_drive_dir(&registry, &body.drive)?, body.id.as_deref()))
+        .await
+        .map_err(|_| FilesError::IOError)??;
+    ok_json_empty()
+}
@@ -0,0 +1,206 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::define_routes;
+use actix_web::web::{Data, Json, Path, Payload};
+use actix_web::{delete, head, patch, post, web, HttpRequest, HttpResponse};
+use log::{info, warn};
+
+use crate::models::errors::{FilesError, UploadsError};
+use crate::models::uploads::{CreateUploadRequest, UploadStatus};
+use crate::stores::drives::DriveRegistry;
+use crate::stores::events::EventBus;
+use crate::stores::uploads::{UploadSession, UploadStore};
+use crate::utils::command::CommandRunner;
+use crate::utils::drives::get_free_space;
+use crate::utils::files::{check_file_name, get_directory_at, get_drive_dir};
+use crate::utils::uploads::{
+    append_upload, create_staging_file, finish_upload, remove_staging_file,
+};
+use crate::utils::{ok_json, ok_json_empty, JsonResult};
+
+define_routes!(create, progress, append, finish, cancel);
+
+/// Header containing the number of bytes received for an upload
+const UPLOAD_OFFSET_HEADER: &str = "Upload-Offset";
+/// Header containing the total length of an upload
+const UPLOAD_LENGTH_HEADER: &str = "Upload-Length";
+
+type UploadsResult<T> = JsonResult<T, UploadsError>;
+type UploadsResultEmpty = UploadsResult<()>;
+
+/// Runs the provided upload operation on the blocking thread pool so
+/// that opening folders and files on slow drives doesn't stall the worker
+async fn run_blocking<F, T>(operation: F) -> Result<T, UploadsError>
+where
+    F: FnOnce() -> Result<T, UploadsError> + Send + 'static,
+    T: Send + 'static,
+{
+    web::block(operation)
+        .await
+        .map_err(|_| FilesError::IOError)?
+}
+
+/// Creates the status response model for the provided upload session
+fn create_status(id: String, session: &UploadSession) -> UploadStatus {
+    let expiry_time = session
+        .expiry_time()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_millis(0))
+        .as_millis();
+    UploadStatus {
+        id,
+        offset: session.offset,
+        length: session.length,
+        expiry_time,
+    }
+}
+
+/// API endpoint /api/uploads for creating a new resumable upload session
+/// for a file that will be stored in a directory on a mounted drive
+#[post("/uploads")]
+pub async fn create(
+    body: Json<CreateUploadRequest>,
+    upload_store: Data<UploadStore>,
+    registry: Data<DriveRegistry>,
+    runner: Data<dyn CommandRunner>,
+) -> UploadsResult<UploadStatus> {
+    let body = body.into_inner();
+    check_file_name(&body.name)?;
+
+    let (body, staging_name) = run_blocking(move || {
+        let drive_root = get_drive_dir(&registry, &body.drive)?;
+        let directory = get_directory_at(&drive_root, &body.path)?;
+        let available =
+            get_free_space(runner.as_ref(), &directory.path).map_err(|_| FilesError::IOError)?;
+        if body.length > available {
+            return Err(FilesError::InsufficientSpace.into());
+        }
+
+        let staging_name = create_staging_file(&drive_root)?;
+        Ok((body, staging_name))
+    })
+    .await?;
+    let session = UploadSession {
+        staging_name,
+        drive: body.drive.clone(),
+        path: body.path.clone(),
+        name: body.name.clone(),
+        length: body.length,
+        offset: 0,
+        last_activity: SystemTime::now(),
+    };
+    let upload_status = create_status(String::new(), &session);
+    let id = upload_store.create_session(session).await;
+
+    info!("Created upload {} for {}", id, body.name);
+
+    ok_json(UploadStatus {
+        id,
+        ..upload_status
+    })
+}
+
+/// API endpoint /api/uploads/{id} for querying the progress of an upload
+/// through the Upload-Offset and Upload-Length headers
+#[head("/uploads/{id}")]
+pub async fn progress(
+    id: Path<String>,
+    upload_store: Data<UploadStore>,
+) -> Result<HttpResponse, UploadsError> {
+    let session = upload_store
+        .get_session(&id)
+        .await
+        .ok_or(UploadsError::NotFound)?;
+    let session = session.lock().await;
+    Ok(HttpResponse::Ok()
+        .insert_header((UPLOAD_OFFSET_HEADER, session.offset))
+        .insert_header((UPLOAD_LENGTH_HEADER, session.length))
+        .insert_header(("Cache-Control", "no-store"))
+        .finish())
+}
+
+/// API endpoint /api/uploads/{id} for appending a chunk to an upload. The
+/// Upload-Offset header must match the number of bytes already received
+#[patch("/uploads/{id}")]
+pub async fn append(
+    req: HttpRequest,
+    id: Path<String>,
+    payload: Payload,
+    upload_store: Data<UploadStore>,
+    registry: Data<DriveRegistry>,
+) -> Result<HttpResponse, UploadsError> {
+    let offset = req
+        .headers()
+        .get(UPLOAD_OFFSET_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .ok_or(UploadsError::InvalidOffset)?;
+
+    let session = upload_store
+        .get_session(&id)
+        .await
+        .ok_or(UploadsError::NotFound)?;
+    let mut session = session.lock().await;
+    let drive = session.drive.clone();
+    let drive_root = run_blocking(move || Ok(get_drive_dir(&registry, &drive)?)).await?;
+    append_upload(&drive_root, &mut session, offset, payload).await?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((UPLOAD_OFFSET_HEADER, session.offset))
+        .json(create_status(id.into_inner(), &session)))
+}
+
+/// API endpoint /api/uploads/{id}/finish for moving a completed upload
+/// into its target directory
+#[post("/uploads/{id}/finish")]
+pub async fn finish(
+    id: Path<String>,
+    upload_store: Data<UploadStore>,
+    event_bus: Data<EventBus>,
+    registry: Data<DriveRegistry>,
+) -> UploadsResultEmpty {
+    let session = upload_store
+        .get_session(&id)
+        .await
+        .ok_or(UploadsError::NotFound)?;
+    let session = session.lock().await;
+    let completed = session.clone();
+    let target_path = run_blocking(move || {
+        finish_upload(&get_drive_dir(&registry, &completed.drive)?, &completed)
+    })
+    .await?;
+    upload_store.remove_session(&id).await;
+    event_bus.files_changed(&session.drive, &session.path);
+
+    info!(
+        "Completed upload {} to {}",
+        id,
+        target_path.to_string_lossy()
+    );
+
+    ok_json_empty()
+}
+
+/// API endpoint /api/uploads/{id} for cancelling an upload and
+/// removing any data that has been received
+#[delete("/uploads/{id}")]
+pub async fn cancel(
+    id: Path<String>,
+    upload_store: Data<UploadStore>,
+    registry: Data<DriveRegistry>,
+) -> UploadsResultEmpty {
+    let session = upload_store
+        .remove_session(&id)
+        .await
+        .ok_or(UploadsError::NotFound)?;
+    let session = session.lock().await.clone();
+    run_blocking(move || {
+        match get_drive_dir(&registry, &session.drive) {
+            Ok(drive_root) => remove_staging_file(&drive_root, &session),
+            Err(err) => warn!("Failed to remove staging file for {}: {}", id, err),
+        }
+        Ok(())
+    })
+    .await?;
+    ok_json_empty()
+}
@@ -36,6 +36,12 @@ pub struct TokenData {
     pub expiry_time: SystemTime,
 }
 
+impl Default for AuthStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl AuthStore {
     /// Creates a new instance of the auth store using the
     /// provided username and password as the credentials.
@@ -59,10 +65,7 @@ impl AuthStore {
     /// Retrieves the expiry time for the provided token
     pub async fn get_token_expiry(&self, token: &str) -> Option<SystemTime> {
         let tokens = &*self.tokens.read().await;
-        match tokens.get(token) {
-            None => None,
-            Some(expiry_time) => Some(expiry_time.clone()),
-        }
+        tokens.get(token).copied()
     }
 
     /// Removes the provided token from the valid tokens map
@@ -115,7 +118,7 @@ impl AuthStore {
             let expiry_duration = Duration::from_secs(TOKEN_EXPIRY_TIME);
             let expiry_time = current_time.add(expiry_duration);
 
-            self.add_token(token.clone(), expiry_time.clone()).await;
+            self.add_token(token.clone(), expiry_time).await;
 
             return TokenData { token, expiry_time };
         }
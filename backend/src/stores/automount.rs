@@ -0,0 +1,40 @@
+use std::io;
+use std::path::PathBuf;
+
+use tokio::sync::RwLock;
+
+use crate::models::automount::AutoMountPolicy;
+use crate::utils::automount::{load_policy, save_policy};
+
+/// Struct for storing the auto-mount policy along with
+/// the path to the file it is persisted to
+pub struct AutoMountStore {
+    path: PathBuf,
+    policy: RwLock<AutoMountPolicy>,
+}
+
+impl AutoMountStore {
+    /// Creates a new auto-mount store loading the policy
+    /// from the file at the provided path
+    pub fn new(path: PathBuf) -> AutoMountStore {
+        let policy = load_policy(&path);
+        Self {
+            path,
+            policy: RwLock::new(policy),
+        }
+    }
+
+    /// Retrieves a copy of the current policy
+    pub async fn get_policy(&self) -> AutoMountPolicy {
+        self.policy.read().await.clone()
+    }
+
+    /// Replaces the current policy with the provided policy and
+    /// saves it to the policy file
+    pub async fn set_policy(&self, policy: AutoMountPolicy) -> io::Result<()> {
+        let stored = &mut *self.policy.write().await;
+        save_policy(&self.path, &policy)?;
+        *stored = policy;
+        Ok(())
+    }
+}
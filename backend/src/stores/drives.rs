@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockReadGuard};
+
+use log::info;
+use tokio::sync::broadcast;
+
+use crate::models::drives::{Drive, DriveEvent};
+
+/// The number of drive events that can be waiting for a slow
+/// subscriber before it starts missing events
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// Struct for storing the currently connected drives mapped to their
+/// device paths. Changes to the drives are broadcast to subscribers.
+/// The drives are behind a blocking lock so that they can also be
+/// looked up from file operations running on the blocking thread pool
+pub struct DriveRegistry {
+    drives: RwLock<HashMap<String, Drive>>,
+    events: broadcast::Sender<DriveEvent>,
+}
+
+impl Default for DriveRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DriveRegistry {
+    /// Creates a new empty drive registry
+    pub fn new() -> DriveRegistry {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            drives: RwLock::new(HashMap::new()),
+            events,
+        }
+    }
+
+    /// Creates a new receiver for the events emitted when drives
+    /// are added, removed or changed
+    pub fn subscribe(&self) -> broadcast::Receiver<DriveEvent> {
+        self.events.subscribe()
+    }
+
+    /// Acquires the lock on the stored drives. The drives are only ever
+    /// replaced as a whole so a poisoned lock still holds a valid list
+    fn read_drives(&self) -> RwLockReadGuard<'_, HashMap<String, Drive>> {
+        self.drives.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Retrieves a list of the currently connected drives
+    pub fn get_drives(&self) -> Vec<Drive> {
+        self.read_drives().values().cloned().collect()
+    }
+
+    /// Retrieves the connected drive with the provided device path
+    pub fn get_drive(&self, path: &str) -> Option<Drive> {
+        self.read_drives().get(path).cloned()
+    }
+
+    /// Retrieves the connected drive with the provided filesystem UUID
+    pub fn find_drive(&self, uuid: &str) -> Option<Drive> {
+        self.read_drives()
+            .values()
+            .find(|drive| drive.uuid == uuid)
+            .cloned()
+    }
+
+    /// Replaces the stored drives with the provided list of connected
+    /// drives emitting events for any drives that were added, removed
+    /// or changed since the last update
+    pub fn update(&self, drives: Vec<Drive>) {
+        let mut events = Vec::new();
+        {
+            let stored = &mut *self.drives.write().unwrap_or_else(|err| err.into_inner());
+            let mut previous = std::mem::take(stored);
+
+            for drive in drives {
+                match previous.remove(&drive.path) {
+                    None => events.push(DriveEvent::Added(drive.clone())),
+                    Some(old) if old != drive => events.push(DriveEvent::Changed(drive.clone())),
+                    Some(_) => {}
+                }
+                stored.insert(drive.path.clone(), drive);
+            }
+
+            events.extend(previous.into_values().map(DriveEvent::Removed));
+        }
+
+        for event in events {
+            match &event {
+                DriveEvent::Added(drive) => info!("Drive connected {}", drive.path),
+                DriveEvent::Removed(drive) => info!("Drive disconnected {}", drive.path),
+                DriveEvent::Changed(drive) => info!("Drive changed {}", drive.path),
+            }
+            // Sending only fails when there are no subscribers
+            let _ = self.events.send(event);
+        }
+    }
+}
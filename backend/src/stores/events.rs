@@ -0,0 +1,59 @@
+use std::path::Path;
+
+use tokio::sync::broadcast;
+
+use crate::models::events::ServerEvent;
+
+/// The number of events that can be waiting for a slow subscriber
+/// before it starts missing events
+const EVENT_CHANNEL_CAPACITY: usize = 128;
+
+/// Struct for broadcasting server events to the connected
+/// event stream clients
+pub struct EventBus {
+    sender: broadcast::Sender<ServerEvent>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventBus {
+    /// Creates a new event bus without any subscribers
+    pub fn new() -> EventBus {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self { sender }
+    }
+
+    /// Creates a new receiver for the published events
+    pub fn subscribe(&self) -> broadcast::Receiver<ServerEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Publishes the provided event to all of the subscribers
+    pub fn publish(&self, event: ServerEvent) {
+        // Sending only fails when there are no subscribers
+        let _ = self.sender.send(event);
+    }
+
+    /// Publishes an event for the contents of the directory at the
+    /// provided path on the drive changing
+    pub fn files_changed(&self, drive: &str, path: &str) {
+        self.publish(ServerEvent::FilesChanged {
+            drive: drive.to_string(),
+            path: path.to_string(),
+        })
+    }
+
+    /// Publishes an event for the contents of the directory containing
+    /// the entry at the provided path on the drive changing
+    pub fn entry_changed(&self, drive: &str, path: &str) {
+        let parent = Path::new(path)
+            .parent()
+            .map(|parent| parent.to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.files_changed(drive, &parent)
+    }
+}
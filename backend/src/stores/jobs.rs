@@ -0,0 +1,213 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tokio::sync::RwLock;
+
+use crate::models::jobs::{JobKind, JobState, JobStatus};
+use crate::utils::known::current_time_millis;
+use crate::utils::{create_character_set, create_random_string};
+
+/// The character length to generate the job ids with
+const JOB_ID_LENGTH: usize = 16;
+
+/// The number of finished jobs that are kept so their results can
+/// still be retrieved. The oldest finished jobs are removed first
+const MAX_JOB_HISTORY: usize = 50;
+
+/// Value of the total bytes when the total isn't known
+const UNKNOWN_TOTAL: u64 = u64::MAX;
+
+/// The result of a job that has finished
+struct JobOutcome {
+    state: JobState,
+    message: Option<String>,
+    error: Option<String>,
+    finished: u128,
+}
+
+/// Structure representing a long running operation. The progress is
+/// updated from the thread running the job while it is being read
+pub struct Job {
+    id: String,
+    kind: JobKind,
+    target: String,
+    started: u128,
+    processed: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+    outcome: Mutex<Option<JobOutcome>>,
+}
+
+/// Shared reference to a job
+pub type JobRef = Arc<Job>;
+
+impl Job {
+    /// Adds the provided number of bytes to the bytes processed
+    pub fn add_processed(&self, bytes: u64) {
+        self.processed.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Sets the total number of bytes the job will process
+    pub fn set_total(&self, total: u64) {
+        self.total.store(total, Ordering::Relaxed);
+    }
+
+    /// Requests that the job stops. Jobs check for this between
+    /// steps so they may take a moment to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Checks whether the job has been asked to stop
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Checks whether the job has finished running
+    pub fn is_finished(&self) -> bool {
+        self.lock_outcome().is_some()
+    }
+
+    /// Records the result of the job. Failures of jobs that were
+    /// asked to stop are recorded as cancelled
+    pub fn finish(&self, result: Result<Option<String>, String>) {
+        let outcome = match result {
+            Ok(message) => JobOutcome {
+                state: JobState::Completed,
+                message,
+                error: None,
+                finished: current_time_millis(),
+            },
+            Err(_) if self.is_cancelled() => JobOutcome {
+                state: JobState::Cancelled,
+                message: None,
+                error: None,
+                finished: current_time_millis(),
+            },
+            Err(err) => JobOutcome {
+                state: JobState::Failed,
+                message: None,
+                error: Some(err),
+                finished: current_time_millis(),
+            },
+        };
+        *self.lock_outcome() = Some(outcome);
+    }
+
+    /// Creates a snapshot of the current state of the job
+    pub fn status(&self) -> JobStatus {
+        let outcome = self.lock_outcome();
+        let processed = self.processed.load(Ordering::Relaxed);
+        let total =
+            Some(self.total.load(Ordering::Relaxed)).filter(|total| *total != UNKNOWN_TOTAL);
+        let state = outcome
+            .as_ref()
+            .map_or(JobState::Running, |outcome| outcome.state);
+        let progress = if state == JobState::Completed {
+            Some(100.0)
+        } else {
+            total
+                .filter(|total| *total > 0)
+                .map(|total| (processed as f64 / total as f64 * 100.0).min(100.0))
+        };
+
+        JobStatus {
+            id: self.id.clone(),
+            kind: self.kind,
+            target: self.target.clone(),
+            state,
+            progress,
+            processed,
+            total,
+            message: outcome.as_ref().and_then(|outcome| outcome.message.clone()),
+            error: outcome.as_ref().and_then(|outcome| outcome.error.clone()),
+            started: self.started,
+            finished: outcome.as_ref().map(|outcome| outcome.finished),
+        }
+    }
+
+    fn lock_outcome(&self) -> MutexGuard<'_, Option<JobOutcome>> {
+        self.outcome.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Struct for storing the running jobs along with a bounded
+/// history of finished jobs in the order they were started
+pub struct JobStore {
+    jobs: RwLock<Vec<JobRef>>,
+}
+
+impl Default for JobStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JobStore {
+    /// Creates a new empty job store
+    pub fn new() -> JobStore {
+        Self {
+            jobs: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Creates a new running job with a unique id for the provided
+    /// kind of operation on the provided target
+    pub async fn create_job(&self, kind: JobKind, target: &str) -> JobRef {
+        let character_set = create_character_set();
+        let jobs = &mut *self.jobs.write().await;
+        let id = loop {
+            let id = create_random_string(&character_set, JOB_ID_LENGTH);
+            // If the id exists continue attempting to create ids
+            if !jobs.iter().any(|job| job.id == id) {
+                break id;
+            }
+        };
+
+        // Remove the oldest finished jobs once the history is full
+        let finished = jobs.iter().filter(|job| job.is_finished()).count();
+        let mut excess = finished.saturating_sub(MAX_JOB_HISTORY - 1);
+        jobs.retain(|job| {
+            if excess > 0 && job.is_finished() {
+                excess -= 1;
+                false
+            } else {
+                true
+            }
+        });
+
+        let job = Arc::new(Job {
+            id,
+            kind,
+            target: target.to_string(),
+            started: current_time_millis(),
+            processed: AtomicU64::new(0),
+            total: AtomicU64::new(UNKNOWN_TOTAL),
+            cancelled: AtomicBool::new(false),
+            outcome: Mutex::new(None),
+        });
+        jobs.push(job.clone());
+        job
+    }
+
+    /// Retrieves the current state of every job
+    pub async fn get_jobs(&self) -> Vec<JobStatus> {
+        let jobs = &*self.jobs.read().await;
+        jobs.iter().map(|job| job.status()).collect()
+    }
+
+    /// Retrieves the job with the provided id
+    pub async fn get_job(&self, id: &str) -> Option<JobRef> {
+        let jobs = &*self.jobs.read().await;
+        jobs.iter().find(|job| job.id == id).cloned()
+    }
+
+    /// Removes the job with the provided id from the history. Jobs that
+    /// are still running are not removed. Returns whether it was removed
+    pub async fn remove_job(&self, id: &str) -> bool {
+        let jobs = &mut *self.jobs.write().await;
+        let length = jobs.len();
+        jobs.retain(|job| job.id != id || !job.is_finished());
+        jobs.len() != length
+    }
+}
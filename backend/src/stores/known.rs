@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+
+use log::warn;
+use tokio::sync::RwLock;
+
+use crate::models::drives::{Drive, KnownDrive, KnownDriveRequest, KnownDriveStatus};
+use crate::utils::automount::is_valid_name;
+use crate::utils::known::{current_time_millis, load_known_drives, save_known_drives};
+
+/// Struct for storing every drive that has been seen mapped to its
+/// filesystem UUID along with the path to the file it is persisted to
+pub struct KnownDriveStore {
+    path: PathBuf,
+    drives: RwLock<HashMap<String, KnownDrive>>,
+}
+
+impl KnownDriveStore {
+    /// Creates a new known drive store loading the drives from the file
+    /// at the provided path. Mount folder names that were edited into
+    /// the file and aren't a single folder name are dropped
+    pub fn new(path: PathBuf) -> KnownDriveStore {
+        let drives = load_known_drives(&path)
+            .into_iter()
+            .map(|mut drive| {
+                if drive
+                    .mount_name
+                    .as_deref()
+                    .is_some_and(|name| !is_valid_name(name))
+                {
+                    warn!(
+                        "Ignoring invalid mount folder name of known drive {}",
+                        drive.uuid
+                    );
+                    drive.mount_name = None;
+                }
+                (drive.uuid.clone(), drive)
+            })
+            .collect();
+        Self {
+            path,
+            drives: RwLock::new(drives),
+        }
+    }
+
+    /// Retrieves the known drive with the provided UUID
+    pub async fn get_drive(&self, uuid: &str) -> Option<KnownDrive> {
+        self.drives.read().await.get(uuid).cloned()
+    }
+
+    /// Retrieves every known drive marking the drives that are
+    /// present in the provided list of connected drives
+    pub async fn get_drives(&self, connected: &[Drive]) -> Vec<KnownDriveStatus> {
+        let drives = &*self.drives.read().await;
+        let mut drives: Vec<KnownDriveStatus> = drives
+            .values()
+            .map(|drive| KnownDriveStatus {
+                connected: connected.iter().any(|value| value.uuid == drive.uuid),
+                drive: drive.clone(),
+            })
+            .collect();
+        drives.sort_by(|a, b| a.drive.uuid.cmp(&b.drive.uuid));
+        drives
+    }
+
+    /// Records that the provided drive was seen now updating its
+    /// label and filesystem type
+    pub async fn record_seen(&self, drive: &Drive) -> io::Result<()> {
+        let drives = &mut *self.drives.write().await;
+        upsert_drive(drives, drive);
+        save_known_drives(&self.path, drives)
+    }
+
+    /// Saves the mount folder name and mode used to mount the provided drive
+    /// so they can be reapplied when it is connected again
+    pub async fn record_mount(&self, drive: &Drive, name: &str, read_only: bool) -> io::Result<()> {
+        if !is_valid_name(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid mount folder name",
+            ));
+        }
+        let drives = &mut *self.drives.write().await;
+        let known = upsert_drive(drives, drive);
+        known.mount_name = Some(name.to_string());
+        known.read_only = Some(read_only);
+        save_known_drives(&self.path, drives)
+    }
+
+    /// Replaces the saved settings of the known drive with the provided
+    /// UUID. Returns the updated drive or None if it is not known
+    pub async fn update_drive(
+        &self,
+        uuid: &str,
+        request: KnownDriveRequest,
+    ) -> io::Result<Option<KnownDrive>> {
+        let drives = &mut *self.drives.write().await;
+        let known = match drives.get_mut(uuid) {
+            Some(known) => known,
+            None => return Ok(None),
+        };
+        known.name = request.name;
+        known.mount_name = request.mount_name;
+        known.read_only = request.read_only;
+        known.notes = request.notes;
+        let known = known.clone();
+        save_known_drives(&self.path, drives)?;
+        Ok(Some(known))
+    }
+
+    /// Removes the known drive with the provided UUID. Returns
+    /// whether the drive was known
+    pub async fn remove_drive(&self, uuid: &str) -> io::Result<bool> {
+        let drives = &mut *self.drives.write().await;
+        if drives.remove(uuid).is_none() {
+            return Ok(false);
+        }
+        save_known_drives(&self.path, drives)?;
+        Ok(true)
+    }
+}
+
+/// Updates the known drive for the provided drive creating it if the
+/// drive hasn't been seen before. Returns the known drive
+fn upsert_drive<'a>(
+    drives: &'a mut HashMap<String, KnownDrive>,
+    drive: &Drive,
+) -> &'a mut KnownDrive {
+    let known = drives
+        .entry(drive.uuid.clone())
+        .or_insert_with(|| KnownDrive {
+            uuid: drive.uuid.clone(),
+            label: drive.label.clone(),
+            fstype: drive.fstype.clone(),
+            name: None,
+            mount_name: None,
+            read_only: None,
+            notes: None,
+            last_seen: 0,
+        });
+    known.label = drive.label.clone();
+    known.fstype = drive.fstype.clone();
+    known.last_seen = current_time_millis();
+    known
+}
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// Guard giving exclusive access to a device until it is dropped
+pub type DeviceGuard = OwnedMutexGuard<()>;
+
+/// Struct for serializing operations on devices so that concurrent
+/// requests (e.g. a mount and an unmount from two clicks) for the
+/// same device run one after the other instead of racing
+#[derive(Default)]
+pub struct DeviceLocks {
+    locks: Mutex<HashMap<String, Arc<AsyncMutex<()>>>>,
+}
+
+impl DeviceLocks {
+    /// Creates a new store without any locked devices
+    pub fn new() -> DeviceLocks {
+        Self::default()
+    }
+
+    /// Waits until no other operation is running on the device with
+    /// the provided path then locks it until the guard is dropped
+    pub async fn lock(&self, path: &str) -> DeviceGuard {
+        let lock = {
+            let locks = &mut *self.locks.lock().unwrap_or_else(|err| err.into_inner());
+            // Locks that aren't held or waited on by anyone are no longer needed
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(path.to_string()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
@@ -1 +1,8 @@
 pub mod auth;
+pub mod automount;
+pub mod drives;
+pub mod events;
+pub mod jobs;
+pub mod known;
+pub mod locks;
+pub mod uploads;
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::{Mutex, RwLock};
+
+use crate::utils::{create_character_set, create_random_string};
+
+/// The time in seconds that an upload session can go without any
+/// activity before it is considered expired. (In this case 24 Hours)
+pub const UPLOAD_EXPIRY_TIME: u64 = 60 * 60 * 24;
+
+/// The character length to generate the upload ids with
+const UPLOAD_ID_LENGTH: usize = 32;
+
+/// Structure representing an in progress resumable upload
+#[derive(Clone)]
+pub struct UploadSession {
+    /// The name of the staging file the chunks are written to in
+    /// the staging directory of the drive
+    pub staging_name: String,
+    /// The filesystem UUID of the drive the file is uploaded to
+    pub drive: String,
+    /// The path to the directory relative to the drive root
+    pub path: String,
+    /// The name of the file being uploaded
+    pub name: String,
+    /// The total length of the file in bytes
+    pub length: u64,
+    /// The number of bytes that have been written so far
+    pub offset: u64,
+    /// The last time a chunk was written to this session
+    pub last_activity: SystemTime,
+}
+
+impl UploadSession {
+    /// Returns the time that this session will expire at
+    pub fn expiry_time(&self) -> SystemTime {
+        self.last_activity + Duration::from_secs(UPLOAD_EXPIRY_TIME)
+    }
+}
+
+/// Shared reference to an upload session. Each session has its own lock
+/// so chunks for the same upload cannot be written concurrently
+pub type UploadSessionRef = Arc<Mutex<UploadSession>>;
+
+/// Struct for storing the resumable upload sessions mapped
+/// to their upload ids.
+pub struct UploadStore {
+    sessions: RwLock<HashMap<String, UploadSessionRef>>,
+}
+
+impl Default for UploadStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UploadStore {
+    /// Creates a new empty upload store
+    pub fn new() -> UploadStore {
+        Self {
+            sessions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Creates a new unique upload id and stores the provided
+    /// session under that id
+    pub async fn create_session(&self, session: UploadSession) -> String {
+        let character_set = create_character_set();
+        let sessions = &mut *self.sessions.write().await;
+        loop {
+            let id = create_random_string(&character_set, UPLOAD_ID_LENGTH);
+
+            // If the id exists continue attempting to create ids
+            if sessions.contains_key(&id) {
+                continue;
+            }
+
+            sessions.insert(id.clone(), Arc::new(Mutex::new(session)));
+            return id;
+        }
+    }
+
+    /// Retrieves the session with the provided upload id
+    pub async fn get_session(&self, id: &str) -> Option<UploadSessionRef> {
+        let sessions = &*self.sessions.read().await;
+        sessions.get(id).cloned()
+    }
+
+    /// Removes the session with the provided upload id returning it
+    pub async fn remove_session(&self, id: &str) -> Option<UploadSessionRef> {
+        let sessions = &mut *self.sessions.write().await;
+        sessions.remove(id)
+    }
+
+    /// Removes all the sessions that have expired returning the
+    /// removed sessions so that their staging files can be removed.
+    /// Sessions that are currently being written to are skipped
+    pub async fn remove_expired(&self) -> Vec<UploadSessionRef> {
+        let sessions = &mut *self.sessions.write().await;
+        let current_time = SystemTime::now();
+        let expired: Vec<String> = sessions
+            .iter()
+            .filter(|(_, session)| match session.try_lock() {
+                Ok(session) => current_time >= session.expiry_time(),
+                Err(_) => false,
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|id| sessions.remove(id))
+            .collect()
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs::Metadata;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::web::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use futures::{stream, Stream};
+use log::warn;
+use tokio::sync::mpsc;
+use zip::write::{SimpleFileOptions, StreamWriter};
+use zip::{CompressionMethod, DateTime, ZipWriter};
+
+use crate::models::files::ArchiveFormat;
+use crate::utils::files::DriveEntry;
+use crate::utils::resolve::{metadata_at, open_entry_at, read_dir_at, read_link_at};
+use crate::utils::trash::TRASH_DIR;
+use crate::utils::uploads::STAGING_DIR;
+
+/// The size of the chunks that archives are sent to the client in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The number of chunks that can be waiting to be sent to the client
+/// before the archive writer waits. This keeps memory usage flat
+/// regardless of the size of the archive
+const CHANNEL_CAPACITY: usize = 8;
+
+type ChunkSender = mpsc::Sender<io::Result<Bytes>>;
+
+/// Writer which buffers the bytes written to it and sends them over a
+/// channel in chunks. Writes fail once the receiver is dropped which
+/// stops the archive from being written if the client disconnects
+struct ChannelWriter {
+    sender: ChunkSender,
+    buffer: Vec<u8>,
+}
+
+impl ChannelWriter {
+    fn new(sender: ChunkSender) -> Self {
+        Self {
+            sender,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    /// Sends the buffered bytes over the channel
+    fn send_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::replace(
+            &mut self.buffer,
+            Vec::with_capacity(CHUNK_SIZE),
+        ));
+        self.sender
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "archive receiver closed"))
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.send_buffer()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buffer()
+    }
+}
+
+/// Creates a stream of the archive containing the provided entries in the
+/// provided format. The archive is written on the blocking thread pool as
+/// the stream is read so that nothing is staged on disk or in memory
+pub fn stream_archive(
+    entries: Vec<DriveEntry>,
+    format: ArchiveFormat,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+
+    tokio::task::spawn_blocking(move || {
+        let entries = get_archive_entries(entries);
+        let writer = ChannelWriter::new(sender.clone());
+        let result = match format {
+            ArchiveFormat::Zip => write_zip(writer, &entries),
+            ArchiveFormat::TarGz => write_tar_gz(writer, &entries),
+        };
+        if let Err(err) = result {
+            warn!("Failed to write archive: {}", err);
+            // Send the error so the response is aborted rather than
+            // appearing to the client as a complete archive
+            let _ = sender.blocking_send(Err(err));
+        }
+    });
+
+    stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    })
+}
+
+/// Entry selected to be added to an archive along with the
+/// name it is stored under at the root of the archive
+struct ArchiveEntry {
+    entry: DriveEntry,
+    name: String,
+}
+
+/// Prepares the selected entries for archiving. Duplicate selections and
+/// entries inside of other selected folders are dropped as they are
+/// already included and entries with the same name are given unique
+/// names so that they don't collide at the root of the archive
+fn get_archive_entries(selected: Vec<DriveEntry>) -> Vec<ArchiveEntry> {
+    let paths: Vec<PathBuf> = selected.iter().map(DriveEntry::path).collect();
+    let mut used_names = HashSet::new();
+    let mut entries = Vec::new();
+
+    for (entry, (index, path)) in selected.into_iter().zip(paths.iter().enumerate()) {
+        let is_included = paths.iter().enumerate().any(|(other_index, other)| {
+            if other == path {
+                other_index < index
+            } else {
+                path.starts_with(other)
+            }
+        });
+        if is_included {
+            continue;
+        }
+
+        let name = entry.name.to_string_lossy().to_string();
+        let name = create_unique_name(&name, &used_names);
+        used_names.insert(name.clone());
+        entries.push(ArchiveEntry { entry, name });
+    }
+    entries
+}
+
+/// Creates a name that isn't in the provided set of used names by adding
+/// a number before the extension of the name if it is already used
+fn create_unique_name(name: &str, used_names: &HashSet<String>) -> String {
+    if !used_names.contains(name) {
+        return name.to_string();
+    }
+    let path = Path::new(name);
+    let (stem, extension) = match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => (
+            stem.to_string_lossy().to_string(),
+            format!(".{}", extension.to_string_lossy()),
+        ),
+        _ => (name.to_string(), String::new()),
+    };
+    (1..)
+        .map(|number| format!("{} ({}){}", stem, number, extension))
+        .find(|name| !used_names.contains(name))
+        .unwrap_or_default()
+}
+
+/// Walks the provided entry and all of its children calling the provided
+/// function with the archive name, parent folder, name and metadata of
+/// each. Entries are opened relative to the folder they were resolved in
+/// rather than by path so that a folder being swapped for a symlink can't
+/// lead outside of the drive. Symlinks are not followed and internal
+/// Drive-PI folders are skipped
+fn walk_entry<F>(entry: &ArchiveEntry, visit: &mut F) -> io::Result<()>
+where
+    F: FnMut(&str, BorrowedFd<'_>, &OsStr, &Metadata) -> io::Result<()>,
+{
+    walk_recursive(
+        entry.entry.parent.fd.as_fd(),
+        &entry.entry.name,
+        &entry.name,
+        visit,
+    )
+}
+
+/// Visits the entry with the provided name inside the directory and then
+/// the contents of it if it is a folder
+fn walk_recursive<F>(
+    directory: BorrowedFd<'_>,
+    name: &OsStr,
+    archive_name: &str,
+    visit: &mut F,
+) -> io::Result<()>
+where
+    F: FnMut(&str, BorrowedFd<'_>, &OsStr, &Metadata) -> io::Result<()>,
+{
+    let metadata = metadata_at(directory, name)?;
+    visit(archive_name, directory, name, &metadata)?;
+
+    if metadata.is_dir() {
+        let folder = open_entry_at(directory, name, libc::O_RDONLY | libc::O_DIRECTORY)?;
+        for child in read_dir_at(&folder)? {
+            let child_name = child?.file_name();
+            if child_name == STAGING_DIR || child_name == TRASH_DIR {
+                continue;
+            }
+            let child_archive_name = format!("{}/{}", archive_name, child_name.to_string_lossy());
+            walk_recursive(folder.as_fd(), &child_name, &child_archive_name, visit)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes an uncompressed zip archive of the provided entries to the
+/// writer. Files are stored rather than compressed as most files on
+/// drives are already compressed media and deflate is slow on a Pi
+fn write_zip<W: Write>(writer: W, entries: &[ArchiveEntry]) -> io::Result<()> {
+    let mut zip = ZipWriter::new_stream(writer);
+
+    for entry in entries {
+        walk_entry(entry, &mut |name, directory, file_name, metadata| {
+            add_zip_entry(&mut zip, name, directory, file_name, metadata)
+        })?;
+    }
+
+    let mut writer = zip.finish()?.into_inner();
+    writer.flush()
+}
+
+/// Adds a single file, folder or symlink to the zip archive
+fn add_zip_entry<W: Write>(
+    zip: &mut ZipWriter<StreamWriter<W>>,
+    name: &str,
+    directory: BorrowedFd<'_>,
+    file_name: &OsStr,
+    metadata: &Metadata,
+) -> io::Result<()> {
+    let mut options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .unix_permissions(metadata.permissions().mode() & 0o777)
+        .large_file(metadata.len() >= u32::MAX as u64);
+    if let Some(date_time) = metadata.modified().ok().and_then(create_zip_date_time) {
+        options = options.last_modified_time(date_time);
+    }
+
+    if metadata.is_symlink() {
+        let target = read_link_at(directory, file_name)?;
+        zip.add_symlink(name, target.to_string_lossy(), options)?;
+    } else if metadata.is_dir() {
+        zip.add_directory(name, options)?;
+    } else {
+        zip.start_file(name, options)?;
+        io::copy(
+            &mut open_entry_at(directory, file_name, libc::O_RDONLY)?,
+            zip,
+        )?;
+    }
+    Ok(())
+}
+
+/// Converts the provided system time into a zip date time. Returns
+/// None for times that cannot be represented in a zip archive
+fn create_zip_date_time(time: SystemTime) -> Option<DateTime> {
+    let seconds = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let days = (seconds / 86400) as i64;
+    let day_seconds = seconds % 86400;
+
+    // Converts days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    DateTime::from_date_and_time(
+        u16::try_from(year).ok()?,
+        month as u8,
+        day as u8,
+        (day_seconds / 3600) as u8,
+        (day_seconds % 3600 / 60) as u8,
+        (day_seconds % 60) as u8,
+    )
+    .ok()
+}
+
+/// Writes a gzip compressed tar archive of the provided entries to the writer
+fn write_tar_gz<W: Write>(writer: W, entries: &[ArchiveEntry]) -> io::Result<()> {
+    let encoder = GzEncoder::new(writer, Compression::fast());
+    let mut builder = tar::Builder::new(encoder);
+
+    for entry in entries {
+        walk_entry(entry, &mut |name, directory, file_name, metadata| {
+            add_tar_entry(&mut builder, name, directory, file_name, metadata)
+        })?;
+    }
+
+    let mut writer = builder.into_inner()?.finish()?;
+    writer.flush()
+}
+
+/// Adds a single file, folder or symlink to the tar archive
+fn add_tar_entry<W: Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    directory: BorrowedFd<'_>,
+    file_name: &OsStr,
+    metadata: &Metadata,
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+
+    if metadata.is_symlink() {
+        header.set_metadata(metadata);
+        builder.append_link(&mut header, name, read_link_at(directory, file_name)?)
+    } else if metadata.is_dir() {
+        header.set_metadata(metadata);
+        builder.append_data(&mut header, name, io::empty())
+    } else {
+        // The header is filled from the opened file and only that many bytes
+        // are read so the archive stays valid if the file changes size
+        let file = open_entry_at(directory, file_name, libc::O_RDONLY)?;
+        let metadata = file.metadata()?;
+        header.set_metadata(&metadata);
+        builder.append_data(&mut header, name, file.take(metadata.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::utils::files::DriveDir;
+
+    /// Opens the entry at the provided path inside the root folder
+    fn open_entry(root: &Path, path: &Path) -> DriveEntry {
+        let parent = path.parent().unwrap_or(Path::new(""));
+        DriveEntry {
+            parent: DriveDir::open(&root.join(parent)).unwrap(),
+            name: path.file_name().unwrap().to_os_string(),
+        }
+    }
+
+    #[test]
+    fn creates_unique_names() {
+        let used: HashSet<String> = ["file.txt", "file (1).txt", "folder", ".hidden"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(create_unique_name("other.txt", &used), "other.txt");
+        assert_eq!(create_unique_name("file.txt", &used), "file (2).txt");
+        assert_eq!(create_unique_name("folder", &used), "folder (1)");
+        assert_eq!(create_unique_name(".hidden", &used), ".hidden (1)");
+    }
+
+    #[test]
+    fn zips_overlapping_and_colliding_selections() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path();
+        for folder in ["a", "b", "c/sub"] {
+            fs::create_dir_all(path.join(folder)).unwrap();
+        }
+        fs::write(path.join("a/x.txt"), "a").unwrap();
+        fs::write(path.join("b/x.txt"), "b").unwrap();
+        fs::write(path.join("c/sub/y.txt"), "y").unwrap();
+
+        let selected = ["a/x.txt", "b/x.txt", "c", "c/sub", "c/sub/y.txt", "c"];
+        let entries = get_archive_entries(
+            selected
+                .iter()
+                .map(|name| open_entry(path, Path::new(name)))
+                .collect(),
+        );
+        let mut output = Vec::new();
+        write_zip(&mut output, &entries).unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, ["c/", "c/sub/", "c/sub/y.txt", "x (1).txt", "x.txt"]);
+        let mut contents = String::new();
+        io::Read::read_to_string(&mut archive.by_name("x (1).txt").unwrap(), &mut contents)
+            .unwrap();
+        assert_eq!(contents, "b");
+    }
+
+    #[test]
+    fn does_not_follow_folders_swapped_for_symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let path = root.path();
+        for folder in ["a/sub", "b"] {
+            fs::create_dir_all(path.join(folder)).unwrap();
+        }
+        fs::write(path.join("a/sub/y.txt"), "inside").unwrap();
+        fs::write(path.join("b/x.txt"), "inside").unwrap();
+        fs::write(outside.path().join("x.txt"), "outside").unwrap();
+        fs::write(outside.path().join("secret.txt"), "outside").unwrap();
+
+        // Resolve the selection and then swap the folders for symlinks
+        // leading outside of the drive before the archive is written
+        let entries = get_archive_entries(vec![
+            open_entry(path, Path::new("a")),
+            open_entry(path, Path::new("b/x.txt")),
+        ]);
+        for folder in ["a/sub", "b"] {
+            fs::rename(path.join(folder), path.join(format!("{}-moved", folder))).unwrap();
+            std::os::unix::fs::symlink(outside.path(), path.join(folder)).unwrap();
+        }
+        let mut output = Vec::new();
+        write_zip(&mut output, &entries).unwrap();
+
+        let mut archive = ZipArchive::new(Cursor::new(output)).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["a/", "a/sub", "a/sub-moved/", "a/sub-moved/y.txt", "x.txt"]
+        );
+        assert!(archive.by_name("a/sub").unwrap().is_symlink());
+        let mut contents = String::new();
+        io::Read::read_to_string(&mut archive.by_name("x.txt").unwrap(), &mut contents).unwrap();
+        assert_eq!(contents, "inside");
+    }
+}
@@ -0,0 +1,278 @@
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use glob::Pattern;
+use log::{info, warn};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+
+use crate::models::automount::{AutoMountAction, AutoMountPolicy, AutoMountRule};
+use crate::models::drives::{Drive, DriveEvent};
+use crate::models::errors::{AutoMountError, DrivesError};
+use crate::models::events::ServerEvent;
+use crate::stores::automount::AutoMountStore;
+use crate::stores::drives::DriveRegistry;
+use crate::stores::events::EventBus;
+use crate::stores::known::KnownDriveStore;
+use crate::stores::locks::DeviceLocks;
+use crate::utils::command::CommandRunner;
+use crate::utils::drives::{get_disk_path, get_mount_root, mount_drive};
+use crate::utils::hotplug::refresh_drives;
+use crate::utils::samba::update_samba_shares;
+use crate::utils::write_json_file;
+
+/// Environment variable for the path to the auto-mount policy file
+const ENV_AUTOMOUNT_CONFIG_KEY: &str = "DRIVEPI_AUTOMOUNT_CONFIG";
+
+/// The default path to the auto-mount policy file
+const DEFAULT_AUTOMOUNT_CONFIG: &str = "automount.json";
+
+/// Retrieves the path to the file the auto-mount policy is stored in
+pub fn get_env_policy_path() -> PathBuf {
+    let path = std::env::var(ENV_AUTOMOUNT_CONFIG_KEY)
+        .unwrap_or_else(|_| DEFAULT_AUTOMOUNT_CONFIG.to_string());
+    PathBuf::from(path)
+}
+
+/// Loads the auto-mount policy from the file at the provided path. The
+/// default policy is used if the file doesn't exist or is invalid
+pub fn load_policy(path: &Path) -> AutoMountPolicy {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return AutoMountPolicy::default(),
+        Err(err) => {
+            warn!("Failed to read auto-mount policy using default: {}", err);
+            return AutoMountPolicy::default();
+        }
+    };
+    let policy = match serde_json::from_slice::<AutoMountPolicy>(&contents) {
+        Ok(policy) => policy,
+        Err(err) => {
+            warn!("Failed to parse auto-mount policy using default: {}", err);
+            return AutoMountPolicy::default();
+        }
+    };
+    if let Err(err) = check_policy(&policy) {
+        warn!("Auto-mount policy is invalid ({}) using default", err);
+        return AutoMountPolicy::default();
+    }
+    policy
+}
+
+/// Saves the provided auto-mount policy to the file at the provided path
+pub fn save_policy(path: &Path, policy: &AutoMountPolicy) -> io::Result<()> {
+    write_json_file(path, policy)
+}
+
+/// Checks that the patterns and mount folder names of the rules
+/// in the provided policy are valid
+pub fn check_policy(policy: &AutoMountPolicy) -> Result<(), AutoMountError> {
+    for rule in &policy.rules {
+        for pattern in [&rule.label, &rule.path].into_iter().flatten() {
+            Pattern::new(pattern).map_err(|_| AutoMountError::InvalidPattern)?;
+        }
+        if let Some(name) = &rule.name {
+            if !is_valid_name(name) {
+                return Err(AutoMountError::InvalidName);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks whether the provided name is a single normal path component
+/// so that drives can't be mounted outside the mount root
+pub fn is_valid_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+/// Checks whether every criteria present in the provided rule matches the drive
+fn rule_matches(rule: &AutoMountRule, drive: &Drive) -> bool {
+    let matches_pattern = |pattern: &str, value: &str| {
+        Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(value))
+    };
+
+    rule.uuid
+        .as_ref()
+        .is_none_or(|uuid| uuid.eq_ignore_ascii_case(&drive.uuid))
+        && rule
+            .label
+            .as_ref()
+            .is_none_or(|label| matches_pattern(label, &drive.label))
+        && rule.fstype.as_ref().is_none_or(|fstype| {
+            drive
+                .fstype
+                .as_ref()
+                .is_some_and(|value| fstype.eq_ignore_ascii_case(value))
+        })
+        && rule
+            .path
+            .as_ref()
+            .is_none_or(|path| matches_pattern(path, &drive.path))
+}
+
+/// Decides the action to take for the provided drive using the first
+/// matching rule of the policy along with the mount folder name from
+/// the rule if one was provided
+pub fn get_mount_action<'a>(
+    policy: &'a AutoMountPolicy,
+    drive: &Drive,
+) -> (AutoMountAction, Option<&'a str>) {
+    policy
+        .rules
+        .iter()
+        .find(|rule| rule_matches(rule, drive))
+        .map(|rule| (rule.action, rule.name.as_deref()))
+        .unwrap_or((policy.default_action, None))
+}
+
+/// Determines the name of the folder to mount the provided drive to. Uses
+/// the provided name falling back to the filesystem label then the UUID.
+/// A number is appended if a folder with the name is already in use
+fn get_mount_name(mount_root: &Path, drive: &Drive, name: Option<&str>) -> String {
+    let label = drive.label.replace(['/', '\0'], "_");
+    let label = label.trim();
+    let base = match name {
+        Some(name) => name,
+        None if is_valid_name(label) => label,
+        None => &drive.uuid,
+    };
+
+    // Folders that are empty are left over and can be reused
+    let is_available = |name: &str| match fs::read_dir(mount_root.join(name)) {
+        Ok(mut entries) => entries.next().is_none(),
+        Err(err) => err.kind() == ErrorKind::NotFound,
+    };
+
+    if is_available(base) {
+        return base.to_string();
+    }
+    (2..)
+        .map(|index| format!("{}-{}", base, index))
+        .find(|name| is_available(name))
+        .unwrap_or_default()
+}
+
+/// Background task which applies the auto-mount policy to drives as they
+/// are connected. The receiver should be subscribed before the hotplug
+/// monitor is started so drives present at startup are included
+pub async fn automount_drives(
+    mut receiver: Receiver<DriveEvent>,
+    registry: Arc<DriveRegistry>,
+    event_bus: Arc<EventBus>,
+    store: Arc<AutoMountStore>,
+    known_store: Arc<KnownDriveStore>,
+    runner: Arc<dyn CommandRunner>,
+    device_locks: Arc<DeviceLocks>,
+) {
+    loop {
+        match receiver.recv().await {
+            Ok(DriveEvent::Added(drive)) => {
+                apply_policy(
+                    drive,
+                    &registry,
+                    &event_bus,
+                    &store,
+                    &known_store,
+                    &runner,
+                    &device_locks,
+                )
+                .await
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Auto-mount missed {} drive events", missed)
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Mounts the provided newly connected drive if the policy allows it. The
+/// mount folder name and mode saved for known drives take priority over
+/// the ones from the policy
+async fn apply_policy(
+    drive: Drive,
+    registry: &DriveRegistry,
+    event_bus: &EventBus,
+    store: &AutoMountStore,
+    known_store: &KnownDriveStore,
+    runner: &Arc<dyn CommandRunner>,
+    device_locks: &DeviceLocks,
+) {
+    // Drives without a filesystem can't be mounted
+    if drive.mount.is_some() || drive.fstype.is_none() {
+        return;
+    }
+
+    let policy = store.get_policy().await;
+    if !policy.enabled {
+        return;
+    }
+
+    let (action, name) = get_mount_action(&policy, &drive);
+    let mut read_only = match action {
+        AutoMountAction::Ignore => {
+            info!("Auto-mount ignoring drive {}", drive.path);
+            return;
+        }
+        AutoMountAction::Mount => false,
+        AutoMountAction::ReadOnly => true,
+    };
+    let mut name = name.map(str::to_string);
+
+    if let Some(known) = known_store.get_drive(&drive.uuid).await {
+        name = known.mount_name.or(name);
+        read_only = known.read_only.unwrap_or(read_only);
+    }
+
+    let path = drive.path.clone();
+    // Held until the mount finishes so it can't race requests for the same device
+    let guard = device_locks.lock(&get_disk_path(&path)).await;
+    let blocking_runner = runner.clone();
+    let result = tokio::task::spawn_blocking(move || -> io::Result<_> {
+        let _guard = guard;
+        let mount_root = get_mount_root()?;
+        let name = get_mount_name(&mount_root, &drive, name.as_deref());
+        let result = mount_drive(blocking_runner.as_ref(), &drive.path, &name, read_only);
+        if result.is_ok() {
+            update_samba_shares(blocking_runner.as_ref());
+        }
+        Ok((name, result))
+    })
+    .await;
+
+    let (name, result) = match result {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => {
+            warn!("Failed to auto-mount drive {}: {}", path, err);
+            return;
+        }
+        Err(err) => {
+            warn!("Auto-mount task failed: {}", err);
+            return;
+        }
+    };
+
+    match &result {
+        Ok(()) => info!("Auto-mounted drive {} to {}", path, name),
+        Err(err) => warn!("Failed to auto-mount drive {}: {}", path, err),
+    }
+    event_bus.publish(ServerEvent::DriveMounted {
+        path,
+        name,
+        error: result.as_ref().err().map(DrivesError::to_string),
+    });
+
+    if result.is_ok() {
+        // Mounting doesn't produce a uevent so the registry is refreshed here
+        refresh_drives(registry, runner.clone()).await;
+    }
+}
@@ -0,0 +1,326 @@
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+
+/// The longest time a command is allowed to run when it
+/// doesn't have its own timeout
+const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The interval between checking whether a command has finished
+const COMMAND_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Retrieves the longest time the provided program is allowed to run
+/// before it is killed. Programs that touch every file on a drive or
+/// wait for slow devices get longer timeouts
+fn get_command_timeout(program: &str) -> Duration {
+    match program {
+        "fsck" => Duration::from_secs(6 * 60 * 60),
+        "sync" => Duration::from_secs(10 * 60),
+        "mount" | "umount" => Duration::from_secs(2 * 60),
+        "udisksctl" => Duration::from_secs(60),
+        "lsblk" => Duration::from_secs(10),
+        _ => DEFAULT_COMMAND_TIMEOUT,
+    }
+}
+
+/// The output of a command that has finished running
+#[derive(Debug, Clone)]
+pub struct CommandOutput {
+    /// Whether the command exited successfully
+    pub success: bool,
+    /// The exit code of the command. None if it was killed by a signal
+    pub code: Option<i32>,
+    /// The standard output of the command
+    pub stdout: String,
+    /// The standard error of the command
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// Creates a result from the output which is an error created
+    /// using the standard error of the command if it failed
+    pub fn status_result<F, O: FnOnce(&str) -> F>(&self, op: O) -> Result<(), F> {
+        if self.success {
+            Ok(())
+        } else {
+            Err(op(&self.stderr))
+        }
+    }
+}
+
+/// Abstraction over running external commands so that the code
+/// driving system tools can be tested without those tools
+pub trait CommandRunner: Send + Sync {
+    /// Runs the provided program with the provided arguments
+    /// and waits for it to finish
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput>;
+
+    /// Runs the provided program like [CommandRunner::run] stopping
+    /// it early when the provided function returns true
+    fn run_cancellable(
+        &self,
+        program: &str,
+        args: &[&str],
+        _cancelled: &dyn Fn() -> bool,
+    ) -> io::Result<CommandOutput> {
+        self.run(program, args)
+    }
+
+    /// Runs the provided program like [CommandRunner::run] writing the
+    /// provided input to its standard input
+    fn run_with_input(
+        &self,
+        program: &str,
+        args: &[&str],
+        input: &str,
+    ) -> io::Result<CommandOutput>;
+}
+
+/// Command runner which runs the commands on the system. Commands
+/// that run longer than their timeout are killed
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput> {
+        run_system_command(program, args, None, &|| false)
+    }
+
+    fn run_cancellable(
+        &self,
+        program: &str,
+        args: &[&str],
+        cancelled: &dyn Fn() -> bool,
+    ) -> io::Result<CommandOutput> {
+        run_system_command(program, args, None, cancelled)
+    }
+
+    fn run_with_input(
+        &self,
+        program: &str,
+        args: &[&str],
+        input: &str,
+    ) -> io::Result<CommandOutput> {
+        run_system_command(program, args, Some(input), &|| false)
+    }
+}
+
+/// Runs the provided program on the system with the optional input written
+/// to its standard input. The program is killed once it has run longer than
+/// its timeout or has been cancelled
+fn run_system_command(
+    program: &str,
+    args: &[&str],
+    input: Option<&str>,
+    cancelled: &dyn Fn() -> bool,
+) -> io::Result<CommandOutput> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            error!("Failed to execute {} command: {}", program, err);
+            err
+        })?;
+
+    // The input is written from a separate thread so a command that doesn't
+    // read it can't block before the timeout. Write errors are ignored as
+    // the command exiting early is reported by its exit status
+    let writer = child.stdin.take().zip(input).map(|(mut stdin, input)| {
+        let input = input.to_string();
+        thread::spawn(move || {
+            let _ = stdin.write_all(input.as_bytes());
+        })
+    });
+
+    // The output is read while waiting so commands don't block on full pipes
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let timeout = get_command_timeout(program);
+    let Some(status) = wait_timeout(&mut child, timeout, cancelled)? else {
+        let _ = child.kill();
+        let _ = child.wait();
+        if cancelled() {
+            info!("Stopped {} command as it was cancelled", program);
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                format!("{} command cancelled", program),
+            ));
+        }
+        warn!(
+            "Killed {} command after it ran longer than {}s",
+            program,
+            timeout.as_secs()
+        );
+        return Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("{} command timed out", program),
+        ));
+    };
+
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    Ok(CommandOutput {
+        success: status.success(),
+        code: status.code(),
+        stdout: join_pipe(stdout),
+        stderr: join_pipe(stderr),
+    })
+}
+
+/// Reads the provided pipe to the end on a separate thread
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> Option<JoinHandle<Vec<u8>>> {
+    let mut pipe = pipe?;
+    Some(thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = pipe.read_to_end(&mut buffer);
+        buffer
+    }))
+}
+
+/// Waits for the contents of a pipe being read by [read_pipe]
+fn join_pipe(handle: Option<JoinHandle<Vec<u8>>>) -> String {
+    let buffer = handle
+        .and_then(|handle| handle.join().ok())
+        .unwrap_or_default();
+    String::from_utf8_lossy(&buffer).to_string()
+}
+
+/// Waits for the provided child to exit. Returns None if it is still
+/// running once the timeout has passed or it has been cancelled
+fn wait_timeout(
+    child: &mut Child,
+    timeout: Duration,
+    cancelled: &dyn Fn() -> bool,
+) -> io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if start.elapsed() >= timeout || cancelled() {
+            return Ok(None);
+        }
+        thread::sleep(COMMAND_POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+pub use scripted::ScriptedRunner;
+
+#[cfg(test)]
+mod scripted {
+    use std::collections::VecDeque;
+    use std::io;
+    use std::sync::Mutex;
+
+    use super::{CommandOutput, CommandRunner};
+
+    impl CommandOutput {
+        /// Creates the output of a command that succeeded
+        pub fn success(stdout: &str) -> CommandOutput {
+            CommandOutput {
+                success: true,
+                code: Some(0),
+                stdout: stdout.to_string(),
+                stderr: String::new(),
+            }
+        }
+
+        /// Creates the output of a command that failed
+        pub fn failure(code: i32, stderr: &str) -> CommandOutput {
+            CommandOutput {
+                success: false,
+                code: Some(code),
+                stdout: String::new(),
+                stderr: stderr.to_string(),
+            }
+        }
+    }
+
+    /// Command runner which expects a scripted sequence of commands
+    /// and responds to each one with its scripted output. Panics when
+    /// a command is run that doesn't match the next expected command
+    #[derive(Default)]
+    pub struct ScriptedRunner {
+        expected: Mutex<VecDeque<(String, io::Result<CommandOutput>)>>,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl ScriptedRunner {
+        /// Creates a runner which doesn't expect any commands
+        pub fn new() -> ScriptedRunner {
+            Self::default()
+        }
+
+        /// Expects the provided command (program and arguments
+        /// separated by spaces) responding with the provided output
+        pub fn expect(self, command: &str, output: CommandOutput) -> ScriptedRunner {
+            self.push(command, Ok(output))
+        }
+
+        /// Expects the provided command failing to run
+        pub fn expect_missing(self, command: &str) -> ScriptedRunner {
+            let err = io::Error::new(io::ErrorKind::NotFound, "command not found");
+            self.push(command, Err(err))
+        }
+
+        fn push(self, command: &str, output: io::Result<CommandOutput>) -> ScriptedRunner {
+            self.expected
+                .lock()
+                .unwrap()
+                .push_back((command.to_string(), output));
+            self
+        }
+
+        /// Retrieves the commands that have been run
+        pub fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        /// Asserts that every expected command was run
+        pub fn assert_done(&self) {
+            let expected = self.expected.lock().unwrap();
+            let remaining: Vec<&String> = expected.iter().map(|(command, _)| command).collect();
+            assert!(remaining.is_empty(), "commands not run: {:?}", remaining);
+        }
+    }
+
+    impl CommandRunner for ScriptedRunner {
+        fn run(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput> {
+            let command = std::iter::once(program)
+                .chain(args.iter().copied())
+                .collect::<Vec<_>>()
+                .join(" ");
+            self.calls.lock().unwrap().push(command.clone());
+
+            let (expected, output) = self
+                .expected
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or_else(|| panic!("unexpected command: {}", command));
+            assert_eq!(command, expected, "unexpected command");
+            output
+        }
+
+        fn run_with_input(
+            &self,
+            program: &str,
+            args: &[&str],
+            _input: &str,
+        ) -> io::Result<CommandOutput> {
+            self.run(program, args)
+        }
+    }
+}
@@ -1,75 +1,350 @@
-use crate::models::drives::{Drive, DrivesResponse};
+use crate::models::drives::{
+    Drive, DrivesResponse, EjectAction, EjectStatus, EjectStep, ForceUnmount,
+};
 use crate::models::errors::DrivesError;
-use crate::utils::status_result;
+use crate::utils::automount::is_valid_name;
+use crate::utils::command::CommandRunner;
+use crate::utils::holders::{find_mount_holders, terminate_holders};
 use log::{error, info, warn};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
-use serde_with::{serde_as, VecSkipError};
+use serde_with::{serde_as, DisplayFromStr, PickFirst, VecSkipError};
 use std::fs::{create_dir, remove_dir};
+use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::{fs, io};
 
 pub const MOUNT_DIR: &str = "mount";
-const LSBLK_OUTPUT_CONTENTS: &str = "UUID,NAME,LABEL,PATH,MOUNTPOINT,FSSIZE,FSUSED,MODE";
+const LSBLK_OUTPUT_CONTENTS: &str = "UUID,NAME,LABEL,PATH,FSTYPE,MOUNTPOINT,FSSIZE,FSUSED,MODE";
+
+/// Environment variable for the group (name or ID) mounted drives belong to
+pub const ENV_MOUNT_GROUP_KEY: &str = "DRIVEPI_MOUNT_GROUP";
+/// Environment variable for the octal umask applied to mounted drives
+const ENV_MOUNT_UMASK_KEY: &str = "DRIVEPI_MOUNT_UMASK";
+
+/// The default umask which lets the mount group read and write the drives
+/// and every other user read them. Samba users are added to the group
+const DEFAULT_MOUNT_UMASK: u32 = 0o002;
+
+/// The file containing the system groups
+const GROUP_FILE: &str = "/etc/group";
+
+/// Filesystems without unix permissions. Their files are given an owner
+/// and permissions through mount options when they are mounted
+const OWNERLESS_FILESYSTEMS: &[&str] = &["vfat", "msdos", "exfat", "ntfs", "ntfs3"];
+
+/// The ownership and permissions that mounted drives are given
+pub struct MountOwner {
+    /// The user ID that owns the files of filesystems without permissions
+    pub uid: u32,
+    /// The group ID that drives belong to
+    pub gid: u32,
+    /// The permission bits removed from the files of filesystems without
+    /// permissions and from the root folder of other filesystems
+    pub umask: u32,
+}
+
+impl MountOwner {
+    /// Creates the mount owner from the environment variables. Drives are
+    /// owned by the user and group of the server unless a group is provided
+    pub fn from_env() -> MountOwner {
+        // SAFETY: getuid and getgid have no memory safety requirements and can't fail
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let gid = match std::env::var(ENV_MOUNT_GROUP_KEY) {
+            Ok(group) => get_group_id(&group).unwrap_or_else(|| {
+                warn!(
+                    "Mount group provided as {} is not a known group defaulting to {}",
+                    group, gid
+                );
+                gid
+            }),
+            Err(_) => gid,
+        };
+        let umask = match std::env::var(ENV_MOUNT_UMASK_KEY) {
+            Ok(value) => match u32::from_str_radix(&value, 8) {
+                Ok(umask) if umask <= 0o777 => umask,
+                _ => {
+                    warn!(
+                        "Mount umask provided as {} is not a valid octal umask defaulting to {:03o}",
+                        value, DEFAULT_MOUNT_UMASK
+                    );
+                    DEFAULT_MOUNT_UMASK
+                }
+            },
+            Err(_) => DEFAULT_MOUNT_UMASK,
+        };
+        MountOwner { uid, gid, umask }
+    }
+}
+
+/// Retrieves the ID of the provided group which can be a
+/// group name or a numeric group ID
+pub fn get_group_id(group: &str) -> Option<u32> {
+    if let Ok(gid) = group.parse() {
+        return Some(gid);
+    }
+    let contents = fs::read_to_string(GROUP_FILE).ok()?;
+    parse_group_id(&contents, group)
+}
+
+/// Retrieves the name of the group with the provided ID
+pub fn get_group_name(gid: u32) -> Option<String> {
+    let contents = fs::read_to_string(GROUP_FILE).ok()?;
+    parse_group_name(&contents, gid)
+}
+
+/// Finds the name of the group with the provided ID in
+/// the contents of the group file
+fn parse_group_name(contents: &str, gid: u32) -> Option<String> {
+    contents.lines().find_map(|line| {
+        // {NAME}:{PASSWORD}:{GID}:{MEMBERS}
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let id = fields.nth(1)?;
+        (id.parse() == Ok(gid)).then(|| name.to_string())
+    })
+}
+
+/// Finds the ID of the group with the provided name in
+/// the contents of the group file
+fn parse_group_id(contents: &str, group: &str) -> Option<u32> {
+    contents.lines().find_map(|line| {
+        // {NAME}:{PASSWORD}:{GID}:{MEMBERS}
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let gid = fields.nth(1)?;
+        (name == group).then(|| gid.parse().ok()).flatten()
+    })
+}
 
 #[serde_as]
 #[derive(Deserialize)]
-pub struct BlockDevice {
+#[serde(bound = "T: Deserialize<'de>")]
+pub struct BlockDevice<T = Drive> {
     name: String,
     #[serde_as(as = "Option<VecSkipError<_>>")]
-    children: Option<Vec<Drive>>,
+    children: Option<Vec<T>>,
 }
 
 #[derive(Deserialize)]
-pub struct LSBLKOutput {
+#[serde(bound = "T: Deserialize<'de>")]
+pub struct LSBLKOutput<T = Drive> {
     #[serde(rename = "blockdevices")]
-    devices: Vec<BlockDevice>,
+    devices: Vec<BlockDevice<T>>,
+}
+
+/// Structure representing a partition of a block device with only the
+/// fields needed to unmount it. Partitions that aren't valid drives (e.g.
+/// without a label) are kept so they aren't left mounted when ejecting
+#[derive(Deserialize)]
+struct Partition {
+    path: String,
+    #[serde(rename = "mountpoint")]
+    mount: Option<String>,
+}
+
+/// Structure representing the filesystem usage of a block device
+/// from lsblk output using byte sizes
+#[serde_as]
+#[derive(Deserialize)]
+struct FsUsage {
+    #[serde(rename = "mountpoint")]
+    mount: Option<String>,
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    #[serde(rename = "fssize")]
+    size: Option<u64>,
+    #[serde_as(as = "Option<PickFirst<(_, DisplayFromStr)>>")]
+    #[serde(rename = "fsused")]
+    used: Option<u64>,
+    children: Option<Vec<FsUsage>>,
+}
+
+#[derive(Deserialize)]
+struct FsUsageOutput {
+    #[serde(rename = "blockdevices")]
+    devices: Vec<FsUsage>,
 }
 
 type DrivesResult<T> = Result<T, DrivesError>;
 type DrivesResultEmpty = DrivesResult<()>;
 
-/// Retrieves a list of mounted and unmounted drives using the lsblk command
-/// and returns the result.
-pub fn get_drive_list() -> DrivesResult<DrivesResponse> {
-    let output = Command::new("lsblk")
-        .args([
+/// Retrieves the block devices along with their partitions using the
+/// lsblk command. Loop devices and partitions that can't be parsed
+/// into the provided type are excluded
+fn get_block_devices<T: DeserializeOwned>(
+    runner: &dyn CommandRunner,
+) -> DrivesResult<Vec<BlockDevice<T>>> {
+    let output = runner.run(
+        "lsblk",
+        &[
             "-J",                  // Output the results as JSON
             "-o",                  // Specify output columns
             LSBLK_OUTPUT_CONTENTS, // List of columns to add to output
-        ])
-        .output()
-        .map_err(|err| {
-            error!("Failed to execute lsblk command: {}", err);
-            DrivesError::IOError
-        })?;
-    let parsed = serde_json::from_slice::<LSBLKOutput>(&output.stdout).map_err(|err| {
+        ],
+    )?;
+    let parsed = serde_json::from_str::<LSBLKOutput<T>>(&output.stdout).map_err(|err| {
         error!("Failed to parse lsblk output: {}", err);
         DrivesError::ParseError
     })?;
-    let devices = parsed.devices;
+    let devices = parsed
+        .devices
+        .into_iter()
+        .filter(|device| !device.name.starts_with("loop"))
+        .collect();
+    Ok(devices)
+}
+
+/// Checks whether the provided mount point belongs to the system
+fn is_system_mount(mount: &str) -> bool {
+    mount == "/" || mount.starts_with("/boot")
+}
+
+/// Retrieves the mounted and unmounted drives excluding system parts
+fn get_drives(runner: &dyn CommandRunner) -> DrivesResult<Vec<Drive>> {
     let mut drives = Vec::new();
-    for device in devices {
-        if device.name.starts_with("loop") {
-            continue;
-        }
+    for device in get_block_devices::<Drive>(runner)? {
         if let Some(children) = device.children {
             for drive in children {
-                if let Some(mount) = &drive.mount {
-                    // Exclude system parts
-                    if mount == "/" || mount.starts_with("/boot") {
-                        continue;
-                    }
+                // Exclude system parts
+                if drive.mount.as_deref().is_some_and(is_system_mount) {
+                    continue;
                 }
 
                 drives.push(drive)
             }
         }
     }
+    Ok(drives)
+}
+
+/// Retrieves a list of mounted and unmounted drives using the lsblk command
+/// and returns the result.
+pub fn get_drive_list(runner: &dyn CommandRunner) -> DrivesResult<DrivesResponse> {
+    let drives = get_drives(runner)?;
     let mount_dir = get_mount_root()?;
     let mount_root = mount_dir.to_string_lossy().to_string();
 
-    return Ok(DrivesResponse { drives, mount_root });
+    Ok(DrivesResponse {
+        drives,
+        mount_root,
+        // Known drives are stored separately and added by the caller
+        known: Vec::new(),
+    })
+}
+
+/// Retrieves the drive with the provided filesystem UUID. Returns
+/// None if no connected drive has the UUID
+pub fn find_drive(runner: &dyn CommandRunner, uuid: &str) -> DrivesResult<Option<Drive>> {
+    let drives = get_drives(runner)?;
+    Ok(drives.into_iter().find(|drive| drive.uuid == uuid))
+}
+
+/// Retrieves the drive with the provided device path. Returns
+/// None if no connected drive has the path
+pub fn find_drive_at(runner: &dyn CommandRunner, path: &str) -> DrivesResult<Option<Drive>> {
+    let drives = get_drives(runner)?;
+    Ok(drives.into_iter().find(|drive| drive.path == path))
+}
+
+/// Retrieves the free space in bytes of the drive that the provided path
+/// is stored on using the fssize and fsused values from lsblk
+pub fn get_free_space(runner: &dyn CommandRunner, path: &Path) -> DrivesResult<u64> {
+    let output = runner.run(
+        "lsblk",
+        &[
+            "-J",                       // Output the results as JSON
+            "-b",                       // Output sizes in bytes
+            "-o",                       // Specify output columns
+            "MOUNTPOINT,FSSIZE,FSUSED", // List of columns to add to output
+        ],
+    )?;
+    let parsed = serde_json::from_str::<FsUsageOutput>(&output.stdout).map_err(|err| {
+        error!("Failed to parse lsblk output: {}", err);
+        DrivesError::ParseError
+    })?;
+
+    // The deepest mount point containing the path is the drive it's stored on
+    let mut free_space: Option<(usize, u64)> = None;
+    let mut stack = parsed.devices;
+    while let Some(device) = stack.pop() {
+        if let (Some(mount), Some(size), Some(used)) = (&device.mount, device.size, device.used) {
+            let mount = Path::new(mount);
+            let depth = mount.components().count();
+            let is_deeper = free_space.is_none_or(|(best, _)| depth > best);
+            if path.starts_with(mount) && is_deeper {
+                free_space = Some((depth, size.saturating_sub(used)));
+            }
+        }
+        if let Some(children) = device.children {
+            stack.extend(children);
+        }
+    }
+
+    free_space
+        .map(|(_, free)| free)
+        .ok_or(DrivesError::NotMounted)
+}
+
+/// Retrieves the mount points that are mounted as read only
+/// from the mount table of the current process
+pub fn get_read_only_mounts() -> io::Result<Vec<PathBuf>> {
+    let contents = fs::read_to_string("/proc/self/mounts")?;
+    let mounts = contents
+        .lines()
+        .filter_map(|line| {
+            // {DEVICE} {MOUNT_POINT} {FSTYPE} {OPTIONS} {DUMP} {PASS}
+            let mut fields = line.split(' ');
+            let mount = fields.nth(1)?;
+            let options = fields.nth(1)?;
+            let read_only = options.split(',').any(|option| option == "ro");
+            read_only.then(|| PathBuf::from(unescape_mount_path(mount)))
+        })
+        .collect();
+    Ok(mounts)
+}
+
+/// Retrieves the mount point the provided drive path (e.g. /dev/sda1)
+/// is mounted to from the mount table of the current process. None if
+/// the drive isn't mounted
+fn get_mount_point(path: &str) -> io::Result<Option<PathBuf>> {
+    let contents = fs::read_to_string("/proc/self/mounts")?;
+    Ok(find_mount_point(&contents, path))
+}
+
+/// Finds the mount point of the provided drive path in the provided mount
+/// table. The latest mount is used when the drive is mounted more than
+/// once as that is the one umount detaches
+fn find_mount_point(contents: &str, path: &str) -> Option<PathBuf> {
+    contents.lines().rev().find_map(|line| {
+        // {DEVICE} {MOUNT_POINT} {FSTYPE} {OPTIONS} {DUMP} {PASS}
+        let mut fields = line.split(' ');
+        let device = fields.next()?;
+        let mount = fields.next()?;
+        (unescape_mount_path(device) == path).then(|| PathBuf::from(unescape_mount_path(mount)))
+    })
+}
+
+/// Replaces the octal escapes (e.g. \040 for spaces) used
+/// for whitespace in mount table paths
+fn unescape_mount_path(path: &str) -> String {
+    let mut output = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(index) = rest.find('\\') {
+        output.push_str(&rest[..index]);
+        let escape = rest.get(index + 1..index + 4);
+        match escape.and_then(|value| u8::from_str_radix(value, 8).ok()) {
+            Some(value) => {
+                output.push(value as char);
+                rest = &rest[index + 4..];
+            }
+            None => {
+                output.push('\\');
+                rest = &rest[index + 1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
 }
 
 pub fn get_mount_root() -> io::Result<PathBuf> {
@@ -80,105 +355,911 @@ pub fn get_mount_root() -> io::Result<PathBuf> {
     mount_path.canonicalize()
 }
 
+/// Retrieves the path of the disk that the provided partition path is on
+/// (e.g. /dev/sda for /dev/sda1) using the sysfs block device tree. Paths
+/// that aren't partitions are returned unchanged
+pub fn get_disk_path(path: &str) -> String {
+    let Some(name) = path.strip_prefix("/dev/") else {
+        return path.to_string();
+    };
+    // Partitions are nested inside the directory of their disk
+    let block_path = Path::new("/sys/class/block").join(name);
+    if !block_path.join("partition").exists() {
+        return path.to_string();
+    }
+    fs::canonicalize(block_path)
+        .ok()
+        .and_then(|device| {
+            let disk = device.parent()?.file_name()?;
+            Some(format!("/dev/{}", disk.to_string_lossy()))
+        })
+        .unwrap_or_else(|| path.to_string())
+}
+
 /// Handles mounting drives to local paths relative to the executable
 /// drives will be mounted to ./mount/{DRIVE_NAME} this is to avoid
-/// permission issues. Mounts drive as Read/Write
-pub fn mount_drive(path: &String, name: &String) -> DrivesResultEmpty {
+/// permission issues. Mounts drive as Read/Write unless read only
+pub fn mount_drive(
+    runner: &dyn CommandRunner,
+    path: &str,
+    name: &str,
+    read_only: bool,
+) -> DrivesResultEmpty {
     // Ensure the local mounting root point exists or create it
-    let mount_dir = Path::new(MOUNT_DIR);
-    if !mount_dir.exists() {
-        create_dir(mount_dir).map_err(|err| {
-            error!("Failed to create mount target parent directory: {}", err);
-            DrivesError::MountError
-        })?;
+    let mount_dir = get_mount_root().map_err(|err| {
+        error!("Failed to create mount target parent directory: {}", err);
+        DrivesError::MountError
+    })?;
+    let fstype = find_drive_at(runner, path)?.and_then(|drive| drive.fstype);
+    let owner = MountOwner::from_env();
+    mount_drive_in(
+        runner,
+        &mount_dir,
+        path,
+        name,
+        fstype.as_deref(),
+        read_only,
+        &owner,
+    )
+}
+
+/// Creates the mount options for a drive with the provided filesystem
+/// type. Filesystems without permissions are given the owner and
+/// permissions of the provided mount owner
+fn get_mount_options(fstype: Option<&str>, read_only: bool, owner: &MountOwner) -> String {
+    let mode = if read_only { "ro" } else { "rw" };
+    match fstype {
+        Some(fstype) if OWNERLESS_FILESYSTEMS.contains(&fstype) => format!(
+            "{},uid={},gid={},umask={:03o}",
+            mode, owner.uid, owner.gid, owner.umask
+        ),
+        _ => mode.to_string(),
+    }
+}
+
+/// Gives the root folder of a mounted drive with unix permissions to the
+/// group of the provided mount owner. The setgid bit makes new files and
+/// folders inherit the group so the files already on the drive keep the
+/// permissions they were given without changing every file
+fn share_mounted_root(mount_path: &Path, owner: &MountOwner) -> DrivesResultEmpty {
+    std::os::unix::fs::chown(mount_path, None, Some(owner.gid)).map_err(|err| {
+        warn!("Failed to change group of mounted drive: {}", err);
+        DrivesError::MountError
+    })?;
+    let mode = (0o777 & !owner.umask) | libc::S_ISGID;
+    fs::set_permissions(mount_path, fs::Permissions::from_mode(mode)).map_err(|err| {
+        warn!("Failed to change permissions of mounted drive: {}", err);
+        DrivesError::MountError
+    })
+}
+
+/// Mounts the provided drive to the folder with the provided
+/// name inside the provided mount root
+fn mount_drive_in(
+    runner: &dyn CommandRunner,
+    mount_dir: &Path,
+    path: &str,
+    name: &str,
+    fstype: Option<&str>,
+    read_only: bool,
+    owner: &MountOwner,
+) -> DrivesResultEmpty {
+    // Names with separators or parent components would mount outside the mount root
+    if !is_valid_name(name) {
+        return Err(DrivesError::InvalidName);
     }
 
     // Ensure the local mounting point exists or create it
-    let mount_path = mount_dir.join(name).canonicalize()?;
-    if !mount_path.exists() {
+    let mount_path = mount_dir.join(name);
+    let is_created = !mount_path.exists();
+    if is_created {
         create_dir(&mount_path).map_err(|err| {
             error!("Failed to create mount target directory: {}", err);
             DrivesError::MountError
         })?;
     }
 
-    let mount_path = mount_path.to_string_lossy();
-
-    // Wrapping mount path in quotations to prevent errors
-    let mount_path = format!("\"{}\"", mount_path);
+    let options = get_mount_options(fstype, read_only, owner);
+    let result = runner
+        .run(
+            "mount",
+            &[
+                "-o",                          // Specify mount options
+                &options,                      // Read Only or Read Write mode along with the owner
+                path,                          // Drive path (e.g. /dev/sda1)
+                &mount_path.to_string_lossy(), // Path to mount the drive to
+            ],
+        )
+        .map_err(DrivesError::from)
+        .and_then(|output| {
+            output.status_result(|err| {
+                // mount: {MOUNT_POINT}: special device /dev/sda1 does not exist.
+                warn!("Failed to mount drive: {}", err);
+                DrivesError::MountError
+            })
+        });
+    if let Err(err) = result {
+        // Don't leave behind the empty mount folder this mount created
+        if is_created {
+            if let Err(err) = remove_dir(&mount_path) {
+                warn!("Failed to remove mount target directory: {}", err);
+            }
+        }
+        return Err(err);
+    }
 
-    let output = Command::new("mount")
-        .args([
-            "-o",        // Specify mount options
-            "rw",        // Mount in Read Write mode
-            path,        // Drive path (e.g. /dev/sda1)
-            &mount_path, // Path to mount the drive to
-        ])
-        .output()
-        .map_err(|err| {
-            error!("Failed to execute mount command: {}", err);
-            DrivesError::IOError
-        })?;
-    status_result(output, |err| {
-        // mount: {MOUNT_POINT}: special device /dev/sda1 does not exist.
-        warn!("Failed to mount drive: {}", err);
-        DrivesError::MountError
-    })?;
-    chown_mounted_drive(&mount_path)?;
+    let is_ownerless = fstype.is_some_and(|fstype| OWNERLESS_FILESYSTEMS.contains(&fstype));
+    if !read_only && !is_ownerless {
+        share_mounted_root(&mount_path, owner)?;
+    }
     Ok(())
 }
 
-fn chown_mounted_drive(path: &str) -> Result<(), DrivesError> {
-    let output = Command::new("chmod")
-        .args([
-            "-R",   // Apply the changes recursively
-            "a+rw", // Add Read+Write permissions to all users
-            path,   // Path on disk to chown should be wrapped in double quotes
-        ])
-        .output()
-        .map_err(|err| {
-            error!("Failed to execute chmod: {}", err);
-            DrivesError::IOError
-        })?;
+/// Runs the umount command for the provided drive path. Lazy unmounts
+/// detach the mount even when it is busy. The processes holding the
+/// provided mount point are included when it is busy
+fn run_umount(
+    runner: &dyn CommandRunner,
+    path: &str,
+    mount: &Path,
+    lazy: bool,
+) -> DrivesResultEmpty {
+    let output = if lazy {
+        // Detach now and clean up once no longer busy
+        runner.run("umount", &["-l", path])?
+    } else {
+        runner.run("umount", &[path])?
+    };
 
-    status_result(output, |err| {
-        // [Directory doesn't exist] chmod: cannot access '{}': No such file or directory
+    output.status_result(|err| {
+        // [Part of device doesnt exist] umount: /dev/sda1: no mount point specified.
+        // [Device doesnt exist] umount: /dev/sdb: not mounted.
 
-        warn!("Failed to chown mount directory: {}", err);
-        DrivesError::MountError
+        if err.contains("target is busy") {
+            DrivesError::TargetBusy(find_mount_holders(mount))
+        } else {
+            DrivesError::UnmountError
+        }
     })
 }
 
-/// Unmounts the provided drive and removes it from the samba share
-pub fn unmount_drive(path: &String, name: &String) -> Result<(), DrivesError> {
-    let output = Command::new("umount")
-        .args([path])
-        .output()
-        .map_err(|err| {
-            error!("Failed to execute unmount on {} command: {}", path, err);
-            DrivesError::IOError
-        })?;
-
-    let mount_dir = get_mount_root()?;
-    let mount_path = mount_dir.join(name);
+/// Removes the provided mount folder if it is empty
+fn remove_mount_folder(mount_path: &Path) -> DrivesResultEmpty {
     if mount_path.exists() {
-        let is_empty = fs::read_dir(&mount_path)?.next().is_none();
+        let is_empty = fs::read_dir(mount_path)?.next().is_none();
 
         if is_empty {
-            remove_dir(&mount_path)?;
-            let mount_path_str = mount_path.to_str().ok_or_else(|| DrivesError::IOError)?;
-            info!("Removed directory of unmounted drive: {}", mount_path_str)
+            remove_dir(mount_path)?;
+            info!(
+                "Removed directory of unmounted drive: {}",
+                mount_path.display()
+            )
         }
     }
+    Ok(())
+}
 
-    status_result(output, |err| {
-        // [Part of device doesnt exist] umount: /dev/sda1: no mount point specified.
-        // [Device doesnt exist] umount: /dev/sdb: not mounted.
+/// Unmounts the provided drive and removes its mount folder. When the
+/// drive is busy and a force mode is provided the drive is either
+/// lazily unmounted or the processes holding it are terminated (samba
+/// processes only if include_samba is true) before trying again. The
+/// folder with the provided name is only used when the drive isn't in
+/// the mount table
+pub fn unmount_drive(
+    runner: &dyn CommandRunner,
+    path: &str,
+    name: &str,
+    force: Option<ForceUnmount>,
+    include_samba: bool,
+) -> Result<(), DrivesError> {
+    if !is_valid_name(name) {
+        return Err(DrivesError::InvalidName);
+    }
+    let mount_dir = get_mount_root()?;
+    let mount_path = get_mount_point(path)?.unwrap_or_else(|| mount_dir.join(name));
+    unmount_drive_in(runner, &mount_dir, path, &mount_path, force, include_samba)
+}
 
-        if err.contains("target is busy") {
-            DrivesError::TargetBusy
-        } else {
-            DrivesError::UnmountError
+/// Unmounts the provided drive from the provided mount point removing
+/// the mount point when it is a folder inside the provided mount root
+fn unmount_drive_in(
+    runner: &dyn CommandRunner,
+    mount_dir: &Path,
+    path: &str,
+    mount_path: &Path,
+    force: Option<ForceUnmount>,
+    include_samba: bool,
+) -> Result<(), DrivesError> {
+    let mut result = run_umount(runner, path, mount_path, false);
+    if let (Err(DrivesError::TargetBusy(holders)), Some(force)) = (&result, force) {
+        result = match force {
+            ForceUnmount::Lazy => {
+                warn!("Drive {} is busy lazily unmounting it", path);
+                run_umount(runner, path, mount_path, true)
+            }
+            ForceUnmount::Terminate => {
+                let pids = terminate_holders(holders, include_samba);
+                info!("Terminated {} processes holding {}", pids.len(), path);
+                run_umount(runner, path, mount_path, false)
+            }
+        };
+    }
+
+    // Busy drives are still mounted so their folder is left in place and
+    // only folders created for mounting drives are removed
+    let is_busy = matches!(result, Err(DrivesError::TargetBusy(_)));
+    if !is_busy && mount_path.parent() == Some(mount_dir) {
+        remove_mount_folder(mount_path)?;
+    }
+
+    result
+}
+
+/// Flushes pending writes to every device
+fn sync_devices(runner: &dyn CommandRunner) -> DrivesResultEmpty {
+    let output = runner.run("sync", &[])?;
+    output.status_result(|err| {
+        warn!("Failed to sync devices: {}", err);
+        DrivesError::SyncError
+    })
+}
+
+/// Powers off the provided device (e.g. sda) using udisksctl falling
+/// back to sysfs when udisks isn't available
+fn power_off_device(runner: &dyn CommandRunner, name: &str) -> DrivesResultEmpty {
+    let device_path = format!("/dev/{}", name);
+    let output = runner.run(
+        "udisksctl",
+        &[
+            "power-off",
+            "--no-user-interaction", // Fail instead of prompting for authentication
+            "-b",                    // Block device to power off
+            &device_path,
+        ],
+    );
+    match output {
+        Ok(output) if output.success => return Ok(()),
+        Ok(output) => warn!(
+            "udisksctl failed to power off {}: {}",
+            device_path,
+            output.stderr.trim_end()
+        ),
+        Err(err) => info!("udisksctl unavailable ({}) using sysfs", err),
+    }
+
+    power_off_sysfs(name).map_err(|err| {
+        error!("Failed to power off {} through sysfs: {}", device_path, err);
+        DrivesError::PowerOffError
+    })
+}
+
+/// Powers off the provided device through sysfs. Removing the USB device
+/// the disk belongs to disables its port. Devices that aren't on USB are
+/// detached from the SCSI layer which stops the disk instead
+fn power_off_sysfs(name: &str) -> io::Result<()> {
+    let device = fs::canonicalize(Path::new("/sys/block").join(name).join("device"))?;
+    let usb_device = device
+        .ancestors()
+        .find(|path| path.join("idVendor").exists() && path.join("remove").exists());
+    let target = match usb_device {
+        Some(usb_device) => usb_device.join("remove"),
+        None => device.join("delete"),
+    };
+    fs::write(target, "1")
+}
+
+/// Checks the filesystem of the provided unmounted drive repairing any
+/// errors that are found. Stops early if the provided function returns
+/// true. Returns a description of the result
+pub fn check_filesystem(
+    runner: &dyn CommandRunner,
+    path: &str,
+    cancelled: &dyn Fn() -> bool,
+) -> DrivesResult<String> {
+    let output = runner.run_cancellable(
+        "fsck",
+        &[
+            "-y", // Repair any errors without asking
+            path, // Drive path (e.g. /dev/sda1)
+        ],
+        cancelled,
+    )?;
+
+    // The exit code is a sum of flags describing the result
+    match output.code {
+        Some(0) => Ok("No filesystem errors were found".to_string()),
+        Some(1..=3) => Ok("Filesystem errors were found and fixed".to_string()),
+        Some(4..=7) => {
+            warn!(
+                "Filesystem of {} has errors: {}",
+                path,
+                output.stdout.trim_end()
+            );
+            Err(DrivesError::FilesystemErrors)
         }
+        _ => {
+            warn!(
+                "Failed to check filesystem of {}: {}",
+                path,
+                output.stderr.trim_end()
+            );
+            Err(DrivesError::FsckError)
+        }
+    }
+}
+
+/// Creates the result of an eject step from the provided result
+fn eject_step(action: EjectAction, target: &str, result: DrivesResultEmpty) -> EjectStep {
+    let error = result.as_ref().err().map(|err| err.to_string());
+    let holders = match result {
+        Err(DrivesError::TargetBusy(holders)) => holders,
+        _ => Vec::new(),
+    };
+    EjectStep {
+        action,
+        target: target.to_string(),
+        error,
+        holders,
+    }
+}
+
+/// Safely ejects the device that the provided drive path is on (or the
+/// device itself) by flushing pending writes, unmounting every partition
+/// of the device then powering it off. The device is only powered off
+/// when every partition was unmounted
+pub fn eject_device(runner: &dyn CommandRunner, path: &str) -> DrivesResult<EjectStatus> {
+    let mount_root = get_mount_root()?;
+    eject_device_in(runner, &mount_root, path)
+}
+
+/// Ejects the device that the provided drive path is on removing
+/// the folders of partitions mounted in the provided mount root
+fn eject_device_in(
+    runner: &dyn CommandRunner,
+    mount_root: &Path,
+    path: &str,
+) -> DrivesResult<EjectStatus> {
+    let device = get_block_devices::<Partition>(runner)?
+        .into_iter()
+        .find(|device| {
+            format!("/dev/{}", device.name) == path
+                || device
+                    .children
+                    .iter()
+                    .flatten()
+                    .any(|partition| partition.path == path)
+        })
+        .ok_or(DrivesError::DeviceNotFound)?;
+
+    let partitions = device.children.unwrap_or_default();
+    if partitions
+        .iter()
+        .any(|drive| drive.mount.as_deref().is_some_and(is_system_mount))
+    {
+        return Err(DrivesError::SystemDevice);
+    }
+
+    let device_path = format!("/dev/{}", device.name);
+    let mut steps = vec![eject_step(
+        EjectAction::Sync,
+        &device_path,
+        sync_devices(runner),
+    )];
+
+    for drive in &partitions {
+        let Some(mount) = &drive.mount else {
+            continue;
+        };
+        let mount = Path::new(mount);
+        let result = run_umount(runner, &drive.path, mount, false).and_then(|_| {
+            // Only folders created for mounting drives are removed
+            if mount.starts_with(mount_root) {
+                remove_mount_folder(mount)
+            } else {
+                Ok(())
+            }
+        });
+        steps.push(eject_step(EjectAction::Unmount, &drive.path, result));
+    }
+
+    let all_unmounted = steps
+        .iter()
+        .filter(|step| step.action == EjectAction::Unmount)
+        .all(|step| step.error.is_none());
+    if all_unmounted {
+        let result = power_off_device(runner, &device.name);
+        steps.push(eject_step(EjectAction::PowerOff, &device_path, result));
+    }
+
+    let safe_to_remove = all_unmounted && steps.iter().all(|step| step.error.is_none());
+    if safe_to_remove {
+        info!("Ejected device {}", device_path);
+    } else {
+        warn!("Device {} was not safely ejected", device_path);
+    }
+    Ok(EjectStatus {
+        device: device_path,
+        safe_to_remove,
+        steps,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::command::{CommandOutput, ScriptedRunner};
+    use std::os::unix::fs::MetadataExt;
+
+    /// Output of lsblk for a Pi booted from its SD card with a USB
+    /// stick that has a mounted, an unmounted and an unformatted part
+    const LSBLK_FIXTURE: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/lsblk.json"
+    ));
+    /// Output of lsblk with byte sizes for the same devices
+    const LSBLK_USAGE_FIXTURE: &str = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/lsblk_usage.json"
+    ));
+
+    const LSBLK_COMMAND: &str =
+        "lsblk -J -o UUID,NAME,LABEL,PATH,FSTYPE,MOUNTPOINT,FSSIZE,FSUSED,MODE";
+
+    fn lsblk_runner() -> ScriptedRunner {
+        ScriptedRunner::new().expect(LSBLK_COMMAND, CommandOutput::success(LSBLK_FIXTURE))
+    }
+
+    #[test]
+    fn lists_drives_without_system_parts() {
+        let runner = lsblk_runner();
+        let drives = get_drives(&runner).unwrap();
+        let names: Vec<&str> = drives.iter().map(|drive| drive.name.as_str()).collect();
+        // Loop devices, the boot and root parts and parts without a filesystem are excluded
+        assert_eq!(names, ["sda1", "sda2"]);
+
+        assert_eq!(drives[0].uuid, "A1B2-C3D4");
+        assert_eq!(drives[0].fstype.as_deref(), Some("vfat"));
+        assert_eq!(drives[0].mount.as_deref(), Some("/opt/drivepi/mount/usb"));
+        assert_eq!(drives[0].size.as_deref(), Some("14.9G"));
+        assert_eq!(drives[1].mount, None);
+        runner.assert_done();
+    }
+
+    #[test]
+    fn reports_invalid_lsblk_output() {
+        let runner =
+            ScriptedRunner::new().expect(LSBLK_COMMAND, CommandOutput::success("lsblk: error"));
+        assert!(matches!(get_drives(&runner), Err(DrivesError::ParseError)));
+
+        let runner = ScriptedRunner::new().expect_missing(LSBLK_COMMAND);
+        assert!(matches!(get_drives(&runner), Err(DrivesError::IOError)));
+    }
+
+    #[test]
+    fn finds_drives_by_uuid() {
+        let runner = lsblk_runner();
+        let drive = find_drive(&runner, "0f3c2a9e-5d1b-4c7a-9e0b-6a2d8f1c4b3e").unwrap();
+        assert_eq!(drive.map(|drive| drive.path), Some("/dev/sda2".to_string()));
+
+        // System parts can't be found
+        let runner = lsblk_runner();
+        let drive = find_drive(&runner, "3b614a3f-4a65-4480-876a-8a998e01ac9b").unwrap();
+        assert!(drive.is_none());
+    }
+
+    #[test]
+    fn calculates_free_space_of_deepest_mount() {
+        let usage_runner = || {
+            ScriptedRunner::new().expect(
+                "lsblk -J -b -o MOUNTPOINT,FSSIZE,FSUSED",
+                CommandOutput::success(LSBLK_USAGE_FIXTURE),
+            )
+        };
+
+        let path = Path::new("/opt/drivepi/mount/usb/Photos");
+        let free = get_free_space(&usage_runner(), path).unwrap();
+        assert_eq!(free, 15997075456 - 2254741504);
+
+        // Paths outside the drive mounts are on the root filesystem
+        let free = get_free_space(&usage_runner(), Path::new("/opt/drivepi")).unwrap();
+        assert_eq!(free, 30923764736 - 4187593728);
+
+        let free = get_free_space(&usage_runner(), Path::new("/boot/firmware/config.txt"));
+        assert_eq!(free.unwrap(), 534444032 - 64172032);
+    }
+
+    /// Owner using the group of the tests so the group can be changed
+    fn test_owner(umask: u32) -> MountOwner {
+        // SAFETY: getgid has no memory safety requirements and can't fail
+        let gid = unsafe { libc::getgid() };
+        MountOwner {
+            uid: 1000,
+            gid,
+            umask,
+        }
+    }
+
+    #[test]
+    fn parses_group_ids() {
+        let contents = "root:x:0:\nusers:x:100:pi,alice\ndrivepi:x:1001:\n";
+        assert_eq!(parse_group_id(contents, "users"), Some(100));
+        assert_eq!(parse_group_id(contents, "drivepi"), Some(1001));
+        assert_eq!(parse_group_id(contents, "samba"), None);
+        assert_eq!(parse_group_name(contents, 1001).as_deref(), Some("drivepi"));
+        assert_eq!(parse_group_name(contents, 1002), None);
+    }
+
+    #[test]
+    fn mounts_ownerless_drives_with_owner_options() {
+        let root = tempfile::tempdir().unwrap();
+        let mount = root.path().join("usb");
+        let owner = test_owner(0o002);
+        let runner = ScriptedRunner::new().expect(
+            &format!(
+                "mount -o rw,uid=1000,gid={},umask=002 /dev/sda1 {}",
+                owner.gid,
+                mount.display()
+            ),
+            CommandOutput::success(""),
+        );
+
+        let fstype = Some("vfat");
+        mount_drive_in(
+            &runner,
+            root.path(),
+            "/dev/sda1",
+            "usb",
+            fstype,
+            false,
+            &owner,
+        )
+        .unwrap();
+        assert!(mount.is_dir());
+        // Permissions come from the mount options so the folder isn't changed
+        let mode = fs::metadata(&mount).unwrap().permissions().mode();
+        assert_eq!(mode & libc::S_ISGID, 0);
+        runner.assert_done();
+    }
+
+    #[test]
+    fn shares_root_of_drives_with_permissions() {
+        let root = tempfile::tempdir().unwrap();
+        let mount = root.path().join("usb");
+        let owner = test_owner(0o002);
+        let runner = ScriptedRunner::new().expect(
+            &format!("mount -o rw /dev/sda2 {}", mount.display()),
+            CommandOutput::success(""),
+        );
+
+        let fstype = Some("ext4");
+        mount_drive_in(
+            &runner,
+            root.path(),
+            "/dev/sda2",
+            "usb",
+            fstype,
+            false,
+            &owner,
+        )
+        .unwrap();
+        let metadata = fs::metadata(&mount).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o7777, 0o2775);
+        assert_eq!(metadata.gid(), owner.gid);
+        runner.assert_done();
+    }
+
+    #[test]
+    fn mounts_drives_read_only() {
+        let root = tempfile::tempdir().unwrap();
+        let mount = root.path().join("usb");
+        let owner = test_owner(0o000);
+        let runner = ScriptedRunner::new()
+            .expect(
+                &format!("mount -o ro /dev/sda2 {}", mount.display()),
+                CommandOutput::success(""),
+            )
+            .expect(
+                &format!(
+                    "mount -o ro,uid=1000,gid={},umask=000 /dev/sda1 {}",
+                    owner.gid,
+                    mount.display()
+                ),
+                CommandOutput::success(""),
+            );
+
+        let fstype = Some("ext4");
+        mount_drive_in(
+            &runner,
+            root.path(),
+            "/dev/sda2",
+            "usb",
+            fstype,
+            true,
+            &owner,
+        )
+        .unwrap();
+        // Read only drives can't be changed
+        let mode = fs::metadata(&mount).unwrap().permissions().mode();
+        assert_eq!(mode & libc::S_ISGID, 0);
+
+        let fstype = Some("exfat");
+        mount_drive_in(
+            &runner,
+            root.path(),
+            "/dev/sda1",
+            "usb",
+            fstype,
+            true,
+            &owner,
+        )
+        .unwrap();
+        runner.assert_done();
+    }
+
+    #[test]
+    fn reports_mount_failures() {
+        let root = tempfile::tempdir().unwrap();
+        let mount = root.path().join("usb");
+        let runner = ScriptedRunner::new().expect(
+            &format!("mount -o rw /dev/sdb1 {}", mount.display()),
+            CommandOutput::failure(
+                32,
+                "mount: /mnt: special device /dev/sdb1 does not exist.\n",
+            ),
+        );
+
+        let owner = test_owner(0o000);
+        let result = mount_drive_in(
+            &runner,
+            root.path(),
+            "/dev/sdb1",
+            "usb",
+            None,
+            false,
+            &owner,
+        );
+        assert!(matches!(result, Err(DrivesError::MountError)));
+        assert!(!mount.exists());
+        runner.assert_done();
+    }
+
+    #[test]
+    fn keeps_existing_mount_folders_on_failure() {
+        let root = tempfile::tempdir().unwrap();
+        let mount = root.path().join("usb");
+        create_dir(&mount).unwrap();
+        let runner = ScriptedRunner::new().expect(
+            &format!("mount -o rw /dev/sdb1 {}", mount.display()),
+            CommandOutput::failure(32, "mount: /mnt: wrong fs type.\n"),
+        );
+
+        let owner = test_owner(0o000);
+        let result = mount_drive_in(
+            &runner,
+            root.path(),
+            "/dev/sdb1",
+            "usb",
+            None,
+            false,
+            &owner,
+        );
+        assert!(matches!(result, Err(DrivesError::MountError)));
+        assert!(mount.is_dir());
+        runner.assert_done();
+    }
+
+    #[test]
+    fn rejects_invalid_mount_names() {
+        let root = tempfile::tempdir().unwrap();
+        let runner = ScriptedRunner::new();
+        let owner = test_owner(0o002);
+
+        for name in ["", ".", "..", "../usb", "usb/drive", "/usb"] {
+            let result =
+                mount_drive_in(&runner, root.path(), "/dev/sda1", name, None, false, &owner);
+            assert!(matches!(result, Err(DrivesError::InvalidName)), "{}", name);
+        }
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 0);
+        runner.assert_done();
+    }
+
+    #[test]
+    fn unmounts_drives_and_removes_folder() {
+        let root = tempfile::tempdir().unwrap();
+        let mount = root.path().join("usb");
+        fs::create_dir(&mount).unwrap();
+        let runner = ScriptedRunner::new().expect("umount /dev/sda1", CommandOutput::success(""));
+
+        unmount_drive_in(&runner, root.path(), "/dev/sda1", &mount, None, false).unwrap();
+        assert!(!mount.exists());
+        runner.assert_done();
+    }
+
+    #[test]
+    fn keeps_mount_points_outside_mount_root() {
+        let root = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let mount = other.path().join("usb");
+        fs::create_dir(&mount).unwrap();
+        let runner = ScriptedRunner::new().expect("umount /dev/sda1", CommandOutput::success(""));
+
+        unmount_drive_in(&runner, root.path(), "/dev/sda1", &mount, None, false).unwrap();
+        assert!(mount.exists());
+        runner.assert_done();
+    }
+
+    #[test]
+    fn rejects_invalid_unmount_names() {
+        let runner = ScriptedRunner::new();
+        for name in ["", "..", "../usb", "usb/drive"] {
+            let result = unmount_drive(&runner, "/dev/sda1", name, None, false);
+            assert!(matches!(result, Err(DrivesError::InvalidName)), "{}", name);
+        }
+        runner.assert_done();
+    }
+
+    #[test]
+    fn finds_mount_points_of_drives() {
+        let contents = "\
+/dev/mmcblk0p2 / ext4 rw,noatime 0 0
+/dev/sda1 /opt/drivepi/mount/USB\\040Drive vfat rw,relatime 0 0
+/dev/sda2 /media/backup ext4 ro 0 0
+/dev/sda2 /opt/drivepi/mount/backup ext4 rw 0 0
+";
+        assert_eq!(
+            find_mount_point(contents, "/dev/sda1"),
+            Some(PathBuf::from("/opt/drivepi/mount/USB Drive"))
+        );
+        assert_eq!(
+            find_mount_point(contents, "/dev/sda2"),
+            Some(PathBuf::from("/opt/drivepi/mount/backup"))
+        );
+        assert_eq!(find_mount_point(contents, "/dev/sdb1"), None);
+    }
+
+    #[test]
+    fn reports_busy_drives() {
+        let root = tempfile::tempdir().unwrap();
+        let mount = root.path().join("usb");
+        fs::create_dir(&mount).unwrap();
+        let runner = ScriptedRunner::new().expect(
+            "umount /dev/sda1",
+            CommandOutput::failure(32, "umount: /mnt/usb: target is busy.\n"),
+        );
+
+        let result = unmount_drive_in(&runner, root.path(), "/dev/sda1", &mount, None, false);
+        assert!(matches!(result, Err(DrivesError::TargetBusy(_))));
+        // The drive is still mounted so its folder is kept
+        assert!(mount.exists());
+        runner.assert_done();
+    }
+
+    #[test]
+    fn lazily_unmounts_busy_drives() {
+        let root = tempfile::tempdir().unwrap();
+        let mount = root.path().join("usb");
+        let runner = ScriptedRunner::new()
+            .expect(
+                "umount /dev/sda1",
+                CommandOutput::failure(32, "umount: /mnt/usb: target is busy.\n"),
+            )
+            .expect("umount -l /dev/sda1", CommandOutput::success(""));
+
+        let force = Some(ForceUnmount::Lazy);
+        unmount_drive_in(&runner, root.path(), "/dev/sda1", &mount, force, false).unwrap();
+        runner.assert_done();
+    }
+
+    #[test]
+    fn reports_unmount_failures() {
+        let root = tempfile::tempdir().unwrap();
+        let mount = root.path().join("usb");
+        let runner = ScriptedRunner::new().expect(
+            "umount /dev/sdb1",
+            CommandOutput::failure(32, "umount: /dev/sdb1: not mounted.\n"),
+        );
+
+        let result = unmount_drive_in(&runner, root.path(), "/dev/sdb1", &mount, None, false);
+        assert!(matches!(result, Err(DrivesError::UnmountError)));
+        runner.assert_done();
+    }
+
+    #[test]
+    fn ejects_devices() {
+        let root = tempfile::tempdir().unwrap();
+        let runner = lsblk_runner()
+            .expect("sync", CommandOutput::success(""))
+            .expect("umount /dev/sda1", CommandOutput::success(""))
+            .expect(
+                "udisksctl power-off --no-user-interaction -b /dev/sda",
+                CommandOutput::success(""),
+            );
+
+        let status = eject_device_in(&runner, root.path(), "/dev/sda2").unwrap();
+        assert_eq!(status.device, "/dev/sda");
+        assert!(status.safe_to_remove);
+        let actions: Vec<EjectAction> = status.steps.iter().map(|step| step.action).collect();
+        assert_eq!(
+            actions,
+            [
+                EjectAction::Sync,
+                EjectAction::Unmount,
+                EjectAction::PowerOff
+            ]
+        );
+        assert_eq!(status.steps[1].target, "/dev/sda1");
+        runner.assert_done();
+    }
+
+    #[test]
+    fn ejects_devices_with_unlabelled_partitions() {
+        let root = tempfile::tempdir().unwrap();
+        let lsblk = r#"{"blockdevices": [{
+            "name": "sdb",
+            "children": [{
+                "uuid": null, "name": "sdb1", "label": null, "path": "/dev/sdb1",
+                "fstype": "vfat", "mountpoint": "/media/stick", "fssize": null,
+                "fsused": null, "mode": null
+            }]
+        }]}"#;
+        let runner = ScriptedRunner::new()
+            .expect(LSBLK_COMMAND, CommandOutput::success(lsblk))
+            .expect("sync", CommandOutput::success(""))
+            .expect("umount /dev/sdb1", CommandOutput::success(""))
+            .expect(
+                "udisksctl power-off --no-user-interaction -b /dev/sdb",
+                CommandOutput::success(""),
+            );
+
+        let status = eject_device_in(&runner, root.path(), "/dev/sdb1").unwrap();
+        assert!(status.safe_to_remove);
+        assert_eq!(status.steps[1].target, "/dev/sdb1");
+        runner.assert_done();
+    }
+
+    #[test]
+    fn keeps_busy_devices_powered() {
+        let root = tempfile::tempdir().unwrap();
+        let runner = lsblk_runner()
+            .expect("sync", CommandOutput::success(""))
+            .expect(
+                "umount /dev/sda1",
+                CommandOutput::failure(32, "umount: /mnt/usb: target is busy.\n"),
+            );
+
+        let status = eject_device_in(&runner, root.path(), "/dev/sda").unwrap();
+        assert!(!status.safe_to_remove);
+        assert_eq!(status.steps.len(), 2);
+        assert!(status.steps[1].error.is_some());
+        runner.assert_done();
+    }
+
+    #[test]
+    fn reports_filesystem_check_results() {
+        let check = |output: CommandOutput| {
+            let runner = ScriptedRunner::new().expect("fsck -y /dev/sda1", output);
+            check_filesystem(&runner, "/dev/sda1", &|| false)
+        };
+
+        assert!(check(CommandOutput::success("")).is_ok());
+        assert!(check(CommandOutput::failure(1, "")).is_ok());
+        assert!(matches!(
+            check(CommandOutput::failure(4, "")),
+            Err(DrivesError::FilesystemErrors)
+        ));
+        assert!(matches!(
+            check(CommandOutput::failure(8, "fsck.ntfs: not found\n")),
+            Err(DrivesError::FsckError)
+        ));
+    }
+
+    #[test]
+    fn refuses_to_eject_system_devices() {
+        let root = tempfile::tempdir().unwrap();
+        let result = eject_device_in(&lsblk_runner(), root.path(), "/dev/mmcblk0p1");
+        assert!(matches!(result, Err(DrivesError::SystemDevice)));
+
+        let result = eject_device_in(&lsblk_runner(), root.path(), "/dev/sdz");
+        assert!(matches!(result, Err(DrivesError::DeviceNotFound)));
+    }
+}
@@ -0,0 +1,129 @@
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::web::Bytes;
+use futures::{stream, Stream};
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+
+use crate::models::drives::DriveEvent;
+use crate::models::events::ServerEvent;
+use crate::stores::auth::AuthStore;
+
+/// The interval in seconds between heartbeat comments. Keeps idle
+/// connections open and is when the token expiry is checked
+const HEARTBEAT_INTERVAL: u64 = 15;
+
+/// The time in seconds before a token expires that clients are warned
+const TOKEN_EXPIRY_WARNING: u64 = 60 * 10;
+
+/// Comment sent as a heartbeat which is ignored by EventSource clients
+const HEARTBEAT: &[u8] = b": heartbeat\n\n";
+
+/// State of a single client event stream
+struct EventStream {
+    token: String,
+    auth_store: Arc<AuthStore>,
+    events: Receiver<ServerEvent>,
+    drive_events: Receiver<DriveEvent>,
+    heartbeat: Interval,
+    warned: bool,
+    closed: bool,
+}
+
+impl EventStream {
+    /// Waits for the next chunk of the stream which is either an
+    /// event or a heartbeat comment
+    async fn next_chunk(&mut self) -> Bytes {
+        tokio::select! {
+            result = self.events.recv() => self.received(result),
+            result = self.drive_events.recv() => self.received(result.map(ServerEvent::from)),
+            _ = self.heartbeat.tick() => match self.check_token().await {
+                Some(event) => encode_event(&event),
+                None => Bytes::from_static(HEARTBEAT),
+            },
+        }
+    }
+
+    /// Handles the result of receiving an event from one of the channels
+    fn received(&mut self, result: Result<ServerEvent, RecvError>) -> Bytes {
+        match result {
+            Ok(event) => encode_event(&event),
+            Err(RecvError::Lagged(_)) => encode_event(&ServerEvent::Resync),
+            Err(RecvError::Closed) => {
+                self.closed = true;
+                Bytes::new()
+            }
+        }
+    }
+
+    /// Checks whether the token for the stream is still valid returning an
+    /// event if the client should be warned or the stream should be closed
+    async fn check_token(&mut self) -> Option<ServerEvent> {
+        let now = SystemTime::now();
+        let expiry_time = match self.auth_store.get_token_expiry(&self.token).await {
+            Some(expiry_time) if expiry_time > now => expiry_time,
+            _ => {
+                self.closed = true;
+                return Some(ServerEvent::TokenExpired);
+            }
+        };
+
+        let remaining = expiry_time.duration_since(now).unwrap_or_default();
+        if self.warned || remaining > Duration::from_secs(TOKEN_EXPIRY_WARNING) {
+            return None;
+        }
+        self.warned = true;
+        Some(ServerEvent::TokenExpiring {
+            expiry_time: expiry_time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis(),
+        })
+    }
+}
+
+/// Encodes the provided event as a server sent event message
+fn encode_event(event: &ServerEvent) -> Bytes {
+    match serde_json::to_string(event) {
+        Ok(data) => Bytes::from(format!("data: {}\n\n", data)),
+        Err(err) => {
+            warn!("Failed to serialize server event: {}", err);
+            Bytes::from_static(HEARTBEAT)
+        }
+    }
+}
+
+/// Creates a server sent event stream of the events published to the
+/// provided receivers for the client authenticated with the provided
+/// token. The stream ends once the token expires or is removed
+pub fn stream_events(
+    token: String,
+    auth_store: Arc<AuthStore>,
+    events: Receiver<ServerEvent>,
+    drive_events: Receiver<DriveEvent>,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    let mut heartbeat = interval(Duration::from_secs(HEARTBEAT_INTERVAL));
+    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let state = EventStream {
+        token,
+        auth_store,
+        events,
+        drive_events,
+        heartbeat,
+        warned: false,
+        closed: false,
+    };
+
+    stream::unfold(state, |mut state| async move {
+        if state.closed {
+            return None;
+        }
+        let chunk = state.next_chunk().await;
+        Some((Ok(chunk), state))
+    })
+}
@@ -1,51 +1,855 @@
-use std::os::unix::fs::PermissionsExt;
-use std::path::Path;
+use std::cmp::Ordering;
+use std::ffi::{OsStr, OsString};
+use std::fs::Metadata;
+use std::io::{Read, Write};
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs, io};
+
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use log::{info, warn};
+use mime_guess::from_path;
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
 
 use crate::models::errors::FilesError;
-use crate::models::files::{DriveFile, DriveFolder, DriveList};
+use crate::models::files::{DriveFile, DriveFolder, DriveList, ListRequest, SortKey, SortOrder};
+use crate::stores::drives::DriveRegistry;
 use crate::utils::drives::get_mount_root;
+use crate::utils::resolve::{
+    create_dir_at, create_file_at, get_fd_path, metadata_at, open_beneath, open_beneath_at,
+    open_entry_at, read_dir_at, read_link_at, remove_at, rename_at, symlink_at,
+};
+use crate::utils::trash::TRASH_DIR;
+use crate::utils::uploads::STAGING_DIR;
+use crate::utils::{create_character_set, create_random_string};
+
+/// The character length of the random suffix for temporary upload files
+const UPLOAD_SUFFIX_LENGTH: usize = 8;
+
+/// The size of the chunks files are copied in. Progress
+/// is reported and cancellation checked after each chunk
+const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// The mode new files are created with before the umask is applied
+const FILE_MODE: libc::mode_t = 0o666;
+
+/// The mode new folders are created with before the umask is applied
+const FOLDER_MODE: libc::mode_t = 0o777;
 
 type FilesResult<T> = Result<T, FilesError>;
 
-/// Retrieves a list of files and folders in the provided mount
-/// path.
-pub fn get_files_at(drive_path: &String, path: &String) -> FilesResult<DriveList> {
+/// Converts an error from resolving a client provided path into a
+/// files error. Paths that would escape their drive are reported as
+/// outside the mount root rather than as cross device errors
+fn map_resolve_error(err: io::Error) -> FilesError {
+    match err.raw_os_error() {
+        Some(libc::EXDEV) => FilesError::OutsideMountRoot,
+        Some(libc::ELOOP) | Some(libc::EINVAL) => FilesError::InvalidTarget,
+        Some(libc::ENOTDIR) => FilesError::NotDirectory,
+        _ => err.into(),
+    }
+}
+
+/// Directory on a drive that has been opened beneath the drive root.
+/// Entries are created, renamed and removed relative to the open
+/// directory so that it can't be swapped for a symlink part way
+/// through an operation
+pub struct DriveDir {
+    /// The open directory. Opened with O_PATH so it can only be
+    /// used to resolve the entries inside of it
+    pub fd: OwnedFd,
+    /// The real path of the directory at the time it was opened
+    pub path: PathBuf,
+}
+
+impl DriveDir {
+    /// Opens the directory at the provided path
+    pub fn open(path: &Path) -> io::Result<DriveDir> {
+        let file = open_beneath(path, Path::new(""), libc::O_PATH | libc::O_DIRECTORY)?;
+        DriveDir::from_file(file)
+    }
+
+    /// Creates a drive directory from an open directory file
+    fn from_file(file: fs::File) -> io::Result<DriveDir> {
+        let path = get_fd_path(&file)?;
+        Ok(DriveDir {
+            fd: file.into(),
+            path,
+        })
+    }
+
+    /// Opens the directory with the provided name inside this directory
+    /// without following symlinks
+    pub fn open_child(&self, name: &OsStr) -> io::Result<DriveDir> {
+        let file = open_entry_at(&self.fd, name, libc::O_PATH | libc::O_DIRECTORY)?;
+        DriveDir::from_file(file)
+    }
+
+    /// Opens the directory at the provided path resolved beneath this directory
+    fn open_beneath(&self, path: &Path) -> FilesResult<DriveDir> {
+        let file = open_beneath_at(&self.fd, path, libc::O_PATH | libc::O_DIRECTORY)
+            .map_err(map_resolve_error)?;
+        Ok(DriveDir::from_file(file)?)
+    }
+
+    /// Creates a second handle to the same open directory
+    pub fn try_clone(&self) -> io::Result<DriveDir> {
+        Ok(DriveDir {
+            fd: self.fd.try_clone()?,
+            path: self.path.clone(),
+        })
+    }
+}
+
+/// Entry (file, folder or symlink) on a drive referenced by its
+/// name within its open parent directory
+pub struct DriveEntry {
+    pub parent: DriveDir,
+    pub name: OsString,
+}
+
+impl DriveEntry {
+    /// Retrieves the path of the entry within its parent directory
+    pub fn path(&self) -> PathBuf {
+        self.parent.path.join(&self.name)
+    }
+}
+
+/// Checks that the provided mount path of a drive is a folder directly
+/// inside the mount root and opens it. Drives mounted anywhere else
+/// are treated as not mounted
+fn get_mount_dir(mount_root: &Path, mount: &Path) -> FilesResult<DriveDir> {
+    let drive_name = mount
+        .strip_prefix(mount_root)
+        .map_err(|_| FilesError::DriveNotMounted)?;
+
+    let mut components = drive_name.components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return Err(FilesError::DriveNotMounted);
+    }
+
+    let flags = libc::O_PATH | libc::O_DIRECTORY;
+    let mount_root = open_beneath(mount_root, Path::new(""), flags)?;
+    let mount_dir = open_beneath_at(&mount_root, drive_name, flags | libc::O_NOFOLLOW)
+        .map_err(map_resolve_error)?;
+
+    // Drives unmounted outside of Drive-PI leave their folder behind until
+    // the registry catches up. Folders on the same device as the mount
+    // root aren't mount points so must not be written to
+    if mount_dir.metadata()?.dev() == mount_root.metadata()?.dev() {
+        return Err(FilesError::DriveNotMounted);
+    }
+    Ok(DriveDir::from_file(mount_dir)?)
+}
+
+/// Opens the root directory of the drive with the provided filesystem
+/// UUID from its mount point in the drive registry
+pub fn get_drive_dir(registry: &DriveRegistry, drive: &str) -> FilesResult<DriveDir> {
+    let drive = registry
+        .find_drive(drive)
+        .ok_or(FilesError::DriveNotFound)?;
+    let mount = drive.mount.ok_or(FilesError::DriveNotMounted)?;
+    get_mount_dir(&get_mount_root()?, Path::new(&mount))
+}
+
+/// Retrieves the filesystem UUIDs and opened root directories of
+/// all of the drives that are mounted in the mount root
+pub fn get_drive_roots(registry: &DriveRegistry) -> FilesResult<Vec<(String, DriveDir)>> {
     let mount_root = get_mount_root()?;
+    Ok(registry
+        .get_drives()
+        .into_iter()
+        .filter_map(|drive| {
+            let mount = drive.mount?;
+            let root = get_mount_dir(&mount_root, Path::new(&mount)).ok()?;
+            Some((drive.uuid, root))
+        })
+        .collect())
+}
 
-    let full_path = Path::new(drive_path).join(path).canonicalize()?;
+/// Opens the file at the provided path on the drive for reading. The file
+/// is opened as it is resolved so it can't be swapped for a symlink after
+/// being checked. Returns the open file along with its resolved path
+pub fn open_file_at(drive_root: &DriveDir, path: &str) -> FilesResult<(fs::File, PathBuf)> {
+    // Non blocking so that opening a FIFO on the drive can't stall the server
+    let file = open_beneath_at(
+        &drive_root.fd,
+        Path::new(path),
+        libc::O_RDONLY | libc::O_NONBLOCK,
+    )
+    .map_err(map_resolve_error)?;
 
-    // Ensure the directory is within the mount root directory
-    if !full_path.starts_with(mount_root) {
-        return Err(FilesError::OutsideMountRoot);
+    if !file.metadata()?.is_file() {
+        return Err(FilesError::NotFile);
     }
 
-    // Ensure the path is actually a directory and not a file
-    if !full_path.is_dir() {
-        return Err(FilesError::NotDirectory);
+    let full_path = get_fd_path(&file)?;
+    Ok((file, full_path))
+}
+
+/// Opens the directory at the provided path on the drive. The path is
+/// resolved beneath the drive root so that neither ".." components nor
+/// symlinks can be used to reach anything outside of the drive
+pub fn get_directory_at(drive_root: &DriveDir, path: &str) -> FilesResult<DriveDir> {
+    drive_root.open_beneath(Path::new(path))
+}
+
+/// Opens the directory at the provided path on the drive creating any
+/// folders that are missing. Each folder is resolved beneath the drive
+/// before its children are created so that symlinks can't be used to
+/// create folders outside of the drive
+pub fn create_directories_at(drive_root: &DriveDir, path: &Path) -> FilesResult<DriveDir> {
+    let mut directory = drive_root.try_clone()?;
+    let mut current = PathBuf::new();
+    for component in path.components() {
+        let name = match component {
+            Component::Normal(name) => name,
+            _ => return Err(FilesError::InvalidTarget),
+        };
+        current.push(name);
+        if let Err(err) = create_dir_at(&directory.fd, name, FOLDER_MODE) {
+            if err.kind() != io::ErrorKind::AlreadyExists {
+                return Err(err.into());
+            }
+        }
+        directory = drive_root.open_beneath(&current)?;
+    }
+    Ok(directory)
+}
+
+/// Resolves an entry (file, folder or symlink) on a drive. Only the
+/// parent directory is resolved so symlinks are treated as entries rather
+/// than followed. The entry must have a name so that the drive root
+/// itself cannot be modified
+pub fn get_entry_at(drive_root: &DriveDir, path: &str) -> FilesResult<DriveEntry> {
+    let path = Path::new(path);
+    let name = path.file_name().ok_or(FilesError::InvalidTarget)?;
+    let parent = path.parent().unwrap_or_else(|| Path::new(""));
+    let parent = drive_root.open_beneath(parent)?;
+
+    // Ensure the entry exists without following symlinks
+    metadata_at(&parent.fd, name).map_err(map_resolve_error)?;
+    Ok(DriveEntry {
+        parent,
+        name: name.to_os_string(),
+    })
+}
+
+/// Resolves multiple entries on the same drive
+pub fn get_entries_at(drive_root: &DriveDir, paths: &[String]) -> FilesResult<Vec<DriveEntry>> {
+    paths
+        .iter()
+        .map(|path| get_entry_at(drive_root, path))
+        .collect()
+}
+
+/// Ensures that a file name provided by a client is a single normal
+/// path component so that it cannot be used to escape its directory
+pub fn check_file_name(name: &str) -> FilesResult<()> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(()),
+        _ => Err(FilesError::InvalidName),
     }
+}
+
+/// Writes the contents of the provided stream to a file with the provided
+/// name in the provided directory. The contents are streamed into a temporary
+/// file which is renamed once complete so partial files are never visible.
+/// Fails if a file with the name already exists or if more than `available`
+/// bytes are written. Returns the number of bytes written
+pub async fn write_file_stream<S, E>(
+    directory: &DriveDir,
+    name: &str,
+    mut stream: S,
+    available: u64,
+) -> FilesResult<u64>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    check_file_name(name)?;
+
+    // Checked up front so conflicts are reported before the file is sent
+    let name = OsString::from(name);
+    ensure_not_exists_at(directory, &name)?;
+    let suffix = create_random_string(&create_character_set(), UPLOAD_SUFFIX_LENGTH);
+    let mut temp_name = OsString::from(".");
+    temp_name.push(&name);
+    temp_name.push(format!(".{}.upload", suffix));
+
+    let mut file = File::from_std(create_file_at(&directory.fd, &temp_name, FILE_MODE)?);
+    let mut written: u64 = 0;
+
+    let result: FilesResult<()> = async {
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|_| FilesError::UploadError)?;
+            written += chunk.len() as u64;
+            if written > available {
+                return Err(FilesError::InsufficientSpace);
+            }
+            file.write_all(&chunk).await?;
+        }
+        file.sync_all().await?;
+        Ok(())
+    }
+    .await;
+
+    drop(file);
+
+    // A file with the same name may have been created while uploading
+    let result = match result {
+        Ok(()) => {
+            let (target_dir, temp) = (directory.try_clone()?, temp_name.clone());
+            tokio::task::spawn_blocking(move || {
+                rename_at(&target_dir.fd, &temp, &target_dir.fd, &name)
+            })
+            .await
+            .map_err(|_| FilesError::IOError)
+            .and_then(|result| result.map_err(FilesError::from))
+        }
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = result {
+        if let Err(remove_err) = remove_at(&directory.fd, &temp_name) {
+            warn!("Failed to remove temporary upload file: {}", remove_err);
+        }
+        return Err(err);
+    }
+    Ok(written)
+}
+
+/// Creates a new folder with the provided name in the directory at
+/// the provided path on the drive
+pub fn create_folder(drive_root: &DriveDir, path: &str, name: &str) -> FilesResult<()> {
+    let directory = get_directory_at(drive_root, path)?;
+    check_file_name(name)?;
+    create_dir_at(&directory.fd, OsStr::new(name), FOLDER_MODE)?;
+    info!(
+        "Created folder {}",
+        directory.path.join(name).to_string_lossy()
+    );
+    Ok(())
+}
+
+/// Renames the entry at the provided path on the drive to the provided
+/// name keeping it in the same directory
+pub fn rename_entry(drive_root: &DriveDir, path: &str, name: &str) -> FilesResult<()> {
+    let entry = get_entry_at(drive_root, path)?;
+    check_file_name(name)?;
+    let directory = &entry.parent;
+    rename_at(&directory.fd, &entry.name, &directory.fd, OsStr::new(name))?;
+    info!(
+        "Renamed {} to {}",
+        entry.path().to_string_lossy(),
+        directory.path.join(name).to_string_lossy()
+    );
+    Ok(())
+}
+
+/// Resolves the entry at the provided path and the entry it will be moved
+/// or copied to in the target directory which can be on a different drive
+pub fn get_transfer_paths(
+    drive_root: &DriveDir,
+    path: &str,
+    target_root: &DriveDir,
+    target_path: &str,
+) -> FilesResult<(DriveEntry, DriveEntry)> {
+    let entry = get_entry_at(drive_root, path)?;
+    let target = get_transfer_target(&entry, target_root, target_path)?;
+    Ok((entry, target))
+}
+
+/// Moves the provided entry to the provided target. The target can be
+/// on a different drive in which case the entry is copied to the
+/// target drive and then removed
+pub fn move_entry(
+    entry: &DriveEntry,
+    target: &DriveEntry,
+    progress: &dyn CopyProgress,
+) -> FilesResult<()> {
+    match rename_at(
+        &entry.parent.fd,
+        &entry.name,
+        &target.parent.fd,
+        &target.name,
+    ) {
+        Ok(()) => progress.set_total(0),
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            progress.set_total(get_entry_size(entry.parent.fd.as_fd(), &entry.name)?);
+            copy_entry_to(entry, target, progress)?;
+            remove_at(&entry.parent.fd, &entry.name)?;
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    info!(
+        "Moved {} to {}",
+        entry.path().to_string_lossy(),
+        target.path().to_string_lossy()
+    );
+    Ok(())
+}
+
+/// Copies the provided entry to the provided target
+/// which can be on a different drive
+pub fn copy_entry(
+    entry: &DriveEntry,
+    target: &DriveEntry,
+    progress: &dyn CopyProgress,
+) -> FilesResult<()> {
+    progress.set_total(get_entry_size(entry.parent.fd.as_fd(), &entry.name)?);
+    copy_entry_to(entry, target, progress)?;
+
+    info!(
+        "Copied {} to {}",
+        entry.path().to_string_lossy(),
+        target.path().to_string_lossy()
+    );
+    Ok(())
+}
+
+/// Copies the provided entry to the provided target removing
+/// anything that was partially copied if the copy fails
+fn copy_entry_to(
+    entry: &DriveEntry,
+    target: &DriveEntry,
+    progress: &dyn CopyProgress,
+) -> io::Result<()> {
+    copy_recursive(
+        entry.parent.fd.as_fd(),
+        &entry.name,
+        target.parent.fd.as_fd(),
+        &target.name,
+        &mut create_copy_buffer(),
+        progress,
+    )
+    .inspect_err(|_| remove_partial_copy(target))
+}
+
+/// Deletes the entry at the provided path on the drive. Folders are
+/// deleted along with all of their contents
+pub fn delete_entry(drive_root: &DriveDir, path: &str) -> FilesResult<()> {
+    let entry = get_entry_at(drive_root, path)?;
+    remove_at(&entry.parent.fd, &entry.name)?;
+    info!("Deleted {}", entry.path().to_string_lossy());
+    Ok(())
+}
+
+/// Resolves the entry that an entry will be moved or copied to in the
+/// provided target directory ensuring nothing already exists there
+/// and that folders are not moved or copied into themselves
+fn get_transfer_target(
+    entry: &DriveEntry,
+    target_root: &DriveDir,
+    target_path: &str,
+) -> FilesResult<DriveEntry> {
+    let directory = get_directory_at(target_root, target_path)?;
+    if directory.path.starts_with(entry.path()) {
+        return Err(FilesError::InvalidTarget);
+    }
+    ensure_not_exists_at(&directory, &entry.name)?;
+    Ok(DriveEntry {
+        parent: directory,
+        name: entry.name.clone(),
+    })
+}
+
+/// Ensures that nothing (including broken symlinks) exists with
+/// the provided name in the provided directory
+pub fn ensure_not_exists_at(directory: &DriveDir, name: &OsStr) -> FilesResult<()> {
+    match metadata_at(&directory.fd, name) {
+        Ok(_) => Err(FilesError::AlreadyExists),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(map_resolve_error(err)),
+    }
+}
+
+/// Receives the progress of copying entries and decides whether the
+/// copy continues
+pub trait CopyProgress {
+    /// Called with the total number of bytes that will be copied
+    fn set_total(&self, total: u64);
+
+    /// Called after the provided number of bytes have been copied.
+    /// Returning an error stops the copy
+    fn copied(&self, bytes: u64) -> io::Result<()>;
+}
+
+/// Creates the buffer that file contents are copied through
+fn create_copy_buffer() -> Vec<u8> {
+    vec![0u8; COPY_BUFFER_SIZE]
+}
+
+/// Opens the folder with the provided name in the directory for
+/// reading its entries without following symlinks
+fn open_folder_at(directory: BorrowedFd<'_>, name: &OsStr) -> io::Result<fs::File> {
+    open_entry_at(directory, name, libc::O_RDONLY | libc::O_DIRECTORY)
+}
+
+/// Calculates the total size in bytes of the files in the provided
+/// entry including the contents of folders
+fn get_entry_size(directory: BorrowedFd<'_>, name: &OsStr) -> io::Result<u64> {
+    let metadata = metadata_at(directory, name)?;
+    if !metadata.is_dir() {
+        return Ok(if metadata.is_file() {
+            metadata.len()
+        } else {
+            0
+        });
+    }
+    let folder = open_folder_at(directory, name)?;
+    let mut size = 0;
+    for entry in read_dir_at(&folder)? {
+        size += get_entry_size(folder.as_fd(), &entry?.file_name())?;
+    }
+    Ok(size)
+}
+
+/// Copies the provided file, folder or symlink to the target name in
+/// the target directory copying the contents of folders recursively
+fn copy_recursive(
+    directory: BorrowedFd<'_>,
+    name: &OsStr,
+    target_dir: BorrowedFd<'_>,
+    target_name: &OsStr,
+    buffer: &mut [u8],
+    progress: &dyn CopyProgress,
+) -> io::Result<()> {
+    let metadata = metadata_at(directory, name)?;
+    if metadata.is_symlink() {
+        symlink_at(&read_link_at(directory, name)?, target_dir, target_name)?;
+    } else if metadata.is_dir() {
+        let folder = open_folder_at(directory, name)?;
+        create_dir_at(target_dir, target_name, FOLDER_MODE)?;
+        let target_folder = open_folder_at(target_dir, target_name)?;
+        for entry in read_dir_at(&folder)? {
+            let entry_name = entry?.file_name();
+            copy_recursive(
+                folder.as_fd(),
+                &entry_name,
+                target_folder.as_fd(),
+                &entry_name,
+                buffer,
+                progress,
+            )?;
+        }
+        target_folder.set_permissions(metadata.permissions())?;
+    } else {
+        copy_file(
+            directory,
+            name,
+            target_dir,
+            target_name,
+            &metadata,
+            buffer,
+            progress,
+        )?;
+    }
+    Ok(())
+}
+
+/// Copies the contents and permissions of the provided file to the
+/// target name reporting the progress after each chunk
+fn copy_file(
+    directory: BorrowedFd<'_>,
+    name: &OsStr,
+    target_dir: BorrowedFd<'_>,
+    target_name: &OsStr,
+    metadata: &Metadata,
+    buffer: &mut [u8],
+    progress: &dyn CopyProgress,
+) -> io::Result<()> {
+    let mut file = open_entry_at(directory, name, libc::O_RDONLY)?;
+    let mut target = create_file_at(target_dir, target_name, FILE_MODE)?;
+    loop {
+        let length = match file.read(buffer) {
+            Ok(0) => break,
+            Ok(length) => length,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        target.write_all(&buffer[..length])?;
+        progress.copied(length as u64)?;
+    }
+    target.set_permissions(metadata.permissions())
+}
+
+/// Removes the partially copied entry after a failed copy
+fn remove_partial_copy(target: &DriveEntry) {
+    if metadata_at(&target.parent.fd, &target.name).is_ok() {
+        if let Err(err) = remove_at(&target.parent.fd, &target.name) {
+            warn!("Failed to remove partial copy: {}", err);
+        }
+    }
+}
+
+/// Entry in a directory that is being listed. Only the details needed
+/// for sorting are loaded until the entry is known to be in the page
+struct ListEntry {
+    name: String,
+    /// The path of the entry relative to the drive root
+    path: PathBuf,
+    metadata: Metadata,
+    symlink: Option<PathBuf>,
+}
+
+impl ListEntry {
+    /// Compares this entry with another by the provided sort key.
+    /// Ties are broken by name so the order is stable between pages
+    fn compare(&self, other: &Self, sort: SortKey) -> Ordering {
+        let by_name = || {
+            self.name
+                .to_lowercase()
+                .cmp(&other.name.to_lowercase())
+                .then_with(|| self.name.cmp(&other.name))
+        };
+        let is_folder = self.metadata.is_dir();
+        let ordering = match sort {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size if !is_folder => self.metadata.len().cmp(&other.metadata.len()),
+            SortKey::Modified => self.modified().cmp(&other.modified()),
+            SortKey::Created => self.created().cmp(&other.created()),
+            SortKey::Accessed => self.accessed().cmp(&other.accessed()),
+            SortKey::Type if !is_folder => self.mime().cmp(&other.mime()),
+            SortKey::Size | SortKey::Type => Ordering::Equal,
+        };
+        ordering.then_with(by_name)
+    }
+
+    fn modified(&self) -> Option<u128> {
+        to_millis(self.metadata.modified())
+    }
+
+    fn created(&self) -> Option<u128> {
+        to_millis(self.metadata.created())
+    }
+
+    fn accessed(&self) -> Option<u128> {
+        to_millis(self.metadata.accessed())
+    }
+
+    fn mime(&self) -> String {
+        from_path(&self.name).first_or_octet_stream().to_string()
+    }
+
+    fn symlink(&self) -> Option<String> {
+        self.symlink
+            .as_ref()
+            .map(|target| target.to_string_lossy().to_string())
+    }
+
+    fn into_folder(self, drive_root: &DriveDir) -> DriveFolder {
+        // Only direct children are counted so large folders stay cheap.
+        // The folder is reopened beneath the drive in case it was replaced
+        let children = open_beneath_at(
+            &drive_root.fd,
+            &self.path,
+            libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NONBLOCK,
+        )
+        .and_then(read_dir_at)
+        .ok()
+        .map(|entries| entries.count() as u64);
+        DriveFolder {
+            permissions: self.metadata.permissions().mode(),
+            modified: self.modified(),
+            created: self.created(),
+            accessed: self.accessed(),
+            uid: self.metadata.uid(),
+            gid: self.metadata.gid(),
+            hidden: self.name.starts_with('.'),
+            symlink: self.symlink(),
+            children,
+            name: self.name,
+        }
+    }
+
+    fn into_file(self) -> DriveFile {
+        DriveFile {
+            size: self.metadata.len(),
+            permissions: self.metadata.permissions().mode(),
+            modified: self.modified(),
+            created: self.created(),
+            accessed: self.accessed(),
+            uid: self.metadata.uid(),
+            gid: self.metadata.gid(),
+            hidden: self.name.starts_with('.'),
+            symlink: self.symlink(),
+            mime: self.mime(),
+            name: self.name,
+        }
+    }
+}
+
+/// Converts the provided file time into milliseconds since the unix
+/// epoch. Returns None if the time is unavailable
+fn to_millis(time: io::Result<SystemTime>) -> Option<u128> {
+    time.ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_millis())
+}
+
+/// Retrieves a list of files and folders in the provided mount
+/// path. The entries are sorted and paged according to the request
+/// with folders always listed before files
+pub fn get_files_at(drive_root: &DriveDir, request: &ListRequest) -> FilesResult<DriveList> {
+    let directory = get_directory_at(drive_root, &request.path)?;
 
     let mut folders = Vec::new();
     let mut files = Vec::new();
 
-    for entry in full_path.read_dir()? {
-        let entry = entry?;
-        let metadata = entry.metadata()?;
+    let relative_dir = directory
+        .path
+        .strip_prefix(&drive_root.path)
+        .map_err(|_| FilesError::OutsideMountRoot)?;
+
+    for entry in read_dir_at(&directory.fd)? {
+        // Entries that can't be read are skipped rather than failing the list
+        let (entry, link_metadata) = match entry.and_then(|entry| {
+            let metadata = metadata_at(&directory.fd, &entry.file_name())?;
+            Ok((entry, metadata))
+        }) {
+            Ok(entry) => entry,
+            Err(err) => {
+                warn!(
+                    "Skipping unreadable entry in {}: {}",
+                    directory.path.to_string_lossy(),
+                    err
+                );
+                continue;
+            }
+        };
         let entry_name = entry.file_name().to_string_lossy().to_string();
-        let permissions = metadata.permissions().mode();
 
-        if metadata.is_dir() {
-            folders.push(DriveFolder {
-                name: entry_name,
-                permissions,
-            })
+        // Skip the internal directories used for staging uploads and trash
+        if entry_name == STAGING_DIR || entry_name == TRASH_DIR {
+            continue;
+        }
+
+        let path = relative_dir.join(entry.file_name());
+        let (metadata, symlink) = if link_metadata.is_symlink() {
+            // Symlinks are listed as their target when the target is on the
+            // drive otherwise as the symlink itself so that nothing about
+            // entries outside of the drive is exposed
+            let target = read_link_at(&directory.fd, &entry.file_name()).ok();
+            let metadata = open_beneath_at(&drive_root.fd, &path, libc::O_PATH)
+                .and_then(|file| file.metadata())
+                .unwrap_or(link_metadata);
+            (metadata, target)
         } else {
-            files.push(DriveFile {
-                name: entry_name,
-                size: metadata.len(),
-                permissions,
-            })
+            (link_metadata, None)
+        };
+
+        let list_entry = ListEntry {
+            name: entry_name,
+            path,
+            metadata,
+            symlink,
+        };
+        if list_entry.metadata.is_dir() {
+            folders.push(list_entry);
+        } else {
+            files.push(list_entry);
         }
     }
 
-    Ok(DriveList { folders, files })
+    for entries in [&mut folders, &mut files] {
+        entries.sort_by(|a, b| {
+            let ordering = a.compare(b, request.sort);
+            if request.order == SortOrder::Desc {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+
+    let total_folders = folders.len();
+    let total_files = files.len();
+
+    // Page across the folders followed by the files
+    let limit = request.limit.unwrap_or(usize::MAX);
+    let folder_offset = request.offset.min(total_folders);
+    let folder_count = limit.min(total_folders - folder_offset);
+    let file_offset = (request.offset - folder_offset).min(total_files);
+    let file_count = (limit - folder_count).min(total_files - file_offset);
+
+    let folders = folders
+        .into_iter()
+        .skip(folder_offset)
+        .take(folder_count)
+        .map(|entry| entry.into_folder(drive_root))
+        .collect();
+    let files = files
+        .into_iter()
+        .skip(file_offset)
+        .take(file_count)
+        .map(ListEntry::into_file)
+        .collect();
+
+    Ok(DriveList {
+        folders,
+        files,
+        total_folders,
+        total_files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::symlink;
+
+    use super::*;
+
+    #[test]
+    fn lists_symlinks_outside_drive_without_following() {
+        let mount_root = tempfile::tempdir().unwrap();
+        let drive_path = mount_root.path().join("usb");
+        let secret_path = mount_root.path().join("secret");
+        for folder in [drive_path.join("inside"), secret_path.clone()] {
+            fs::create_dir_all(&folder).unwrap();
+            fs::write(folder.join("a.txt"), "a").unwrap();
+            fs::write(folder.join("b.txt"), "b").unwrap();
+        }
+        fs::write(drive_path.join("file.txt"), "file").unwrap();
+        symlink("inside", drive_path.join("link_in")).unwrap();
+        symlink("../secret", drive_path.join("link_out")).unwrap();
+        symlink(&secret_path, drive_path.join("link_absolute")).unwrap();
+
+        let drive_root = DriveDir::open(&drive_path).unwrap();
+        let request: ListRequest =
+            serde_json::from_value(serde_json::json!({ "drive": "", "path": "" })).unwrap();
+        let list = get_files_at(&drive_root, &request).unwrap();
+
+        let folders: Vec<(&str, Option<u64>)> = list
+            .folders
+            .iter()
+            .map(|folder| (folder.name.as_str(), folder.children))
+            .collect();
+        assert_eq!(folders, [("inside", Some(2)), ("link_in", Some(2))]);
+        let files: Vec<(&str, Option<&str>)> = list
+            .files
+            .iter()
+            .map(|file| (file.name.as_str(), file.symlink.as_deref()))
+            .collect();
+        let absolute = secret_path.to_string_lossy();
+        assert_eq!(
+            files,
+            [
+                ("file.txt", None),
+                ("link_absolute", Some(absolute.as_ref())),
+                ("link_out", Some("../secret")),
+            ]
+        );
+        // The size is that of the symlink rather than of its target
+        assert_eq!(list.files[2].size, "../secret".len() as u64);
+    }
 }
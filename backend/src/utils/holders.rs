@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::models::drives::MountHolder;
+
+/// The directory containing the details of running processes
+const PROC_DIR: &str = "/proc";
+/// The file containing the names of the system users
+const PASSWD_FILE: &str = "/etc/passwd";
+
+/// The command name prefix of the samba processes serving the shares. Samba
+/// names its per-client processes after the client (e.g. smbd[192.168.42.2])
+const SAMBA_COMMAND: &str = "smbd";
+
+/// How long to wait for holders to exit after asking them
+/// to terminate before they are killed
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for holders to exit after killing them
+const KILL_TIMEOUT: Duration = Duration::from_secs(1);
+/// The interval between checking whether the holders have exited
+const TERMINATE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Parses the names of the users by their user ID from
+/// the contents of the passwd file
+fn parse_passwd(contents: &str) -> HashMap<u32, String> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            // {NAME}:{PASSWORD}:{UID}:{GID}:{GECOS}:{HOME}:{SHELL}
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse().ok()?;
+            Some((uid, name.to_string()))
+        })
+        .collect()
+}
+
+/// Retrieves the real user ID of the process with the provided proc directory
+fn get_process_uid(process_dir: &Path) -> Option<u32> {
+    let status = fs::read_to_string(process_dir.join("status")).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|uids| uids.split_whitespace().next())
+        .and_then(|uid| uid.parse().ok())
+}
+
+/// Retrieves the paths on the provided mount that the process with the
+/// provided proc directory is using as its working directory or has open
+fn get_process_paths(process_dir: &Path, mount: &Path) -> Vec<String> {
+    let fds = fs::read_dir(process_dir.join("fd"))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path());
+    let mut paths: Vec<String> = std::iter::once(process_dir.join("cwd"))
+        .chain(fds)
+        .filter_map(|link| fs::read_link(link).ok())
+        .filter(|path| path.starts_with(mount))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    paths.sort();
+    paths.dedup();
+    paths
+}
+
+/// Finds the processes holding the provided mount busy by scanning the
+/// working directories and open files of the processes in the provided
+/// proc directory. Processes that can't be inspected are skipped
+fn find_holders_in(
+    proc_dir: &Path,
+    mount: &Path,
+    users: &HashMap<u32, String>,
+) -> Vec<MountHolder> {
+    let Ok(entries) = fs::read_dir(proc_dir) else {
+        return Vec::new();
+    };
+
+    let mut holders = Vec::new();
+    for entry in entries.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let process_dir = entry.path();
+        let paths = get_process_paths(&process_dir, mount);
+        if paths.is_empty() {
+            continue;
+        }
+
+        let command = fs::read_to_string(process_dir.join("comm"))
+            .map(|command| command.trim_end().to_string())
+            .unwrap_or_default();
+        let user = get_process_uid(&process_dir).and_then(|uid| users.get(&uid).cloned());
+        holders.extend(paths.into_iter().map(|path| MountHolder {
+            pid,
+            command: command.clone(),
+            user: user.clone(),
+            path,
+        }));
+    }
+    holders.sort_by_key(|holder| holder.pid);
+    holders
+}
+
+/// Finds the processes holding the provided mount busy
+pub fn find_mount_holders(mount: &Path) -> Vec<MountHolder> {
+    let users = fs::read_to_string(PASSWD_FILE)
+        .map(|contents| parse_passwd(&contents))
+        .unwrap_or_default();
+    find_holders_in(Path::new(PROC_DIR), mount, &users)
+}
+
+/// Checks whether the provided holder is a samba process serving the shares
+fn is_samba_holder(holder: &MountHolder) -> bool {
+    holder.command.starts_with(SAMBA_COMMAND)
+}
+
+/// Checks whether the process with the provided PID is still running.
+/// Zombie processes have closed their files so they aren't running
+fn is_running(pid: u32) -> bool {
+    let Ok(stat) = fs::read_to_string(Path::new(PROC_DIR).join(pid.to_string()).join("stat"))
+    else {
+        return false;
+    };
+    // {PID} ({COMMAND}) {STATE} ... where the command can contain spaces
+    let state = stat
+        .rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().next());
+    state != Some("Z")
+}
+
+/// Waits for the processes with the provided PIDs to exit returning
+/// whether they all exited before the timeout
+fn wait_for_exit(pids: &[u32], timeout: Duration) -> bool {
+    let start = Instant::now();
+    loop {
+        if !pids.iter().any(|pid| is_running(*pid)) {
+            return true;
+        }
+        if start.elapsed() >= timeout {
+            return false;
+        }
+        thread::sleep(TERMINATE_POLL_INTERVAL);
+    }
+}
+
+/// Sends the provided signal to the process with the provided PID
+fn send_signal(pid: u32, signal: libc::c_int) {
+    // SAFETY: kill has no memory safety requirements
+    let result = unsafe { libc::kill(pid as libc::pid_t, signal) };
+    if result != 0 {
+        warn!(
+            "Failed to signal process {}: {}",
+            pid,
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// Terminates the processes holding a mount. Samba processes are only
+/// terminated when requested as they are serving other clients and this
+/// server is never terminated. Processes are asked to terminate first
+/// then killed if they haven't exited before the timeout. Returns the
+/// PIDs of the processes that were terminated
+pub fn terminate_holders(holders: &[MountHolder], include_samba: bool) -> Vec<u32> {
+    let own_pid = std::process::id();
+    let mut pids: Vec<u32> = holders
+        .iter()
+        .filter(|holder| holder.pid != own_pid && (include_samba || !is_samba_holder(holder)))
+        .map(|holder| holder.pid)
+        .collect();
+    pids.dedup();
+
+    for pid in &pids {
+        info!("Terminating process {} holding mount", pid);
+        send_signal(*pid, libc::SIGTERM);
+    }
+
+    if !wait_for_exit(&pids, TERMINATE_TIMEOUT) {
+        for pid in pids.iter().filter(|pid| is_running(**pid)) {
+            warn!("Process {} didn't exit in time killing it", pid);
+            send_signal(*pid, libc::SIGKILL);
+        }
+        wait_for_exit(&pids, KILL_TIMEOUT);
+    }
+    pids
+}
@@ -0,0 +1,243 @@
+use std::io;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use tokio::io::unix::AsyncFd;
+use tokio::time::{interval, sleep, MissedTickBehavior};
+
+use crate::stores::drives::DriveRegistry;
+use crate::utils::command::CommandRunner;
+use crate::utils::drives::get_drive_list;
+
+/// The interval in seconds between lsblk snapshots when uevents
+/// are not available
+const DRIVE_POLL_INTERVAL: u64 = 5;
+
+/// The interval in seconds between lsblk snapshots while listening
+/// for uevents. Catches changes that don't produce block uevents
+const DRIVE_RESYNC_INTERVAL: u64 = 60;
+
+/// The time in milliseconds to wait after a block uevent before taking
+/// a snapshot. Gives udev time to probe the filesystem of new devices
+const UEVENT_SETTLE_TIME: u64 = 1000;
+
+/// The size of the buffer uevent messages are read into
+const UEVENT_BUFFER_SIZE: usize = 8192;
+
+/// The netlink multicast group that the kernel sends uevents to
+const UEVENT_KERNEL_GROUP: u32 = 1;
+
+/// Background task which keeps the drive registry up to date with the
+/// connected drives. Listens for kernel uevents to detect drives being
+/// connected or disconnected and falls back to polling lsblk when the
+/// uevent socket is unavailable
+pub async fn monitor_drives(registry: Arc<DriveRegistry>, runner: Arc<dyn CommandRunner>) {
+    refresh_drives(&registry, runner.clone()).await;
+
+    match UEventSocket::open() {
+        Ok(socket) => {
+            info!("Listening for drive hotplug events");
+            watch_uevents(socket, &registry, &runner).await;
+        }
+        Err(err) => {
+            warn!(
+                "Failed to open uevent socket ({}) polling for drives instead",
+                err
+            );
+        }
+    }
+
+    poll_drives(&registry, &runner).await
+}
+
+/// Takes a snapshot of the connected drives using lsblk and
+/// updates the registry with the result
+pub async fn refresh_drives(registry: &DriveRegistry, runner: Arc<dyn CommandRunner>) {
+    match tokio::task::spawn_blocking(move || get_drive_list(runner.as_ref())).await {
+        Ok(Ok(response)) => registry.update(response.drives),
+        Ok(Err(err)) => warn!("Failed to load drives: {}", err),
+        Err(err) => warn!("Drive refresh task failed: {}", err),
+    }
+}
+
+/// Periodically refreshes the drive registry from lsblk snapshots
+async fn poll_drives(registry: &DriveRegistry, runner: &Arc<dyn CommandRunner>) {
+    let mut interval = interval(Duration::from_secs(DRIVE_POLL_INTERVAL));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        refresh_drives(registry, runner.clone()).await;
+    }
+}
+
+/// Refreshes the drive registry whenever a block device uevent is
+/// received. Returns if reading from the socket fails so the caller
+/// can fall back to polling
+async fn watch_uevents(
+    socket: UEventSocket,
+    registry: &DriveRegistry,
+    runner: &Arc<dyn CommandRunner>,
+) {
+    let mut buffer = vec![0u8; UEVENT_BUFFER_SIZE];
+    let mut resync = interval(Duration::from_secs(DRIVE_RESYNC_INTERVAL));
+    resync.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            result = socket.recv(&mut buffer) => {
+                let length = match result {
+                    Ok(UEventMessage::Received(length)) => length,
+                    Ok(UEventMessage::Overflow) => {
+                        // Dropped messages may have been block uevents so the
+                        // registry is refreshed without waiting for the resync
+                        warn!("Uevent socket overflowed refreshing drives");
+                        while let Ok(Some(_)) = socket.try_recv(&mut buffer) {}
+                        refresh_drives(registry, runner.clone()).await;
+                        continue;
+                    }
+                    Err(err) => {
+                        warn!("Failed to read uevent ({}) polling for drives instead", err);
+                        return;
+                    }
+                };
+                if !is_block_uevent(&buffer[..length]) {
+                    continue;
+                }
+
+                // Devices produce a burst of events (one for the disk and one
+                // for each partition) so wait for them all before refreshing
+                sleep(Duration::from_millis(UEVENT_SETTLE_TIME)).await;
+                while let Ok(Some(_)) = socket.try_recv(&mut buffer) {}
+                refresh_drives(registry, runner.clone()).await;
+            }
+            _ = resync.tick() => refresh_drives(registry, runner.clone()).await,
+        }
+    }
+}
+
+/// Checks whether the provided uevent message is for a block device
+/// being added, removed or changed (e.g. media inserted into a reader).
+/// Messages are a header followed by nul separated KEY=VALUE pairs
+fn is_block_uevent(message: &[u8]) -> bool {
+    let mut action = None;
+    let mut subsystem = None;
+    let mut devname = None;
+
+    for field in message.split(|byte| *byte == 0).skip(1) {
+        let field = String::from_utf8_lossy(field);
+        if let Some((key, value)) = field.split_once('=') {
+            match key {
+                "ACTION" => action = Some(value.to_string()),
+                "SUBSYSTEM" => subsystem = Some(value.to_string()),
+                "DEVNAME" => devname = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    let is_block = subsystem.as_deref() == Some("block")
+        && matches!(action.as_deref(), Some("add" | "remove" | "change"));
+    if is_block {
+        debug!(
+            "Block uevent {} {}",
+            action.unwrap_or_default(),
+            devname.unwrap_or_default()
+        );
+    }
+    is_block
+}
+
+/// The result of waiting for a uevent message
+enum UEventMessage {
+    /// A message of the contained length was read into the buffer
+    Received(usize),
+    /// The receive buffer overflowed and the kernel dropped messages
+    Overflow,
+}
+
+/// Non blocking netlink socket subscribed to kernel uevents
+struct UEventSocket {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl UEventSocket {
+    /// Opens a netlink socket bound to the kernel uevent group
+    fn open() -> io::Result<Self> {
+        // Safety: socket has no pointer arguments
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC | libc::SOCK_NONBLOCK,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Safety: The descriptor was just created and is owned by nothing else
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // Safety: sockaddr_nl is a plain struct of integers so zeroed is valid
+        let mut address: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = UEVENT_KERNEL_GROUP;
+
+        // Safety: The address pointer and length refer to a valid sockaddr_nl
+        let result = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Safety: The OwnedFd keeps the descriptor open for the lifetime of the AsyncFd
+        let fd = unsafe { AsyncFd::register(fd) }.map_err(|err| err.into_parts().1)?;
+        Ok(Self { fd })
+    }
+
+    /// Reads a single message without waiting. Returns None if
+    /// there are no messages waiting to be read
+    fn try_recv(&self, buffer: &mut [u8]) -> io::Result<Option<usize>> {
+        // Safety: The pointer and length refer to the provided buffer
+        let length = unsafe {
+            libc::recv(
+                self.fd.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+                libc::MSG_DONTWAIT,
+            )
+        };
+        if length < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::WouldBlock {
+                return Ok(None);
+            }
+            return Err(err);
+        }
+        Ok(Some(length as usize))
+    }
+
+    /// Waits for and reads a single message
+    async fn recv(&self, buffer: &mut [u8]) -> io::Result<UEventMessage> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            match self.try_recv(buffer) {
+                Ok(Some(length)) => return Ok(UEventMessage::Received(length)),
+                Ok(None) => guard.clear_ready(),
+                // The kernel drops messages when the receive buffer overflows
+                // so the caller can't know which devices changed
+                Err(err) if err.raw_os_error() == Some(libc::ENOBUFS) => {
+                    return Ok(UEventMessage::Overflow)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
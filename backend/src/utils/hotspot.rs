@@ -0,0 +1,1039 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use log::{error, info, warn};
+
+use crate::models::errors::HotspotError;
+use crate::models::hotspot::{
+    HotspotBand, HotspotClient, HotspotConfig, HotspotConfigRequest, HotspotStatus,
+};
+use crate::utils::command::CommandRunner;
+
+/// Environment variable for the path to the hostapd config
+const ENV_HOSTAPD_CONFIG_KEY: &str = "DRIVEPI_HOSTAPD_CONFIG";
+/// Environment variable for the wireless interface of the hotspot
+const ENV_HOTSPOT_INTERFACE_KEY: &str = "DRIVEPI_HOTSPOT_INTERFACE";
+/// Environment variable for the path to the dnsmasq lease file
+const ENV_DHCP_LEASES_KEY: &str = "DRIVEPI_DHCP_LEASES";
+
+/// The default path to the hostapd config
+const DEFAULT_HOSTAPD_CONFIG: &str = "/etc/hostapd/hostapd.conf";
+/// The default wireless interface of the hotspot
+const DEFAULT_HOTSPOT_INTERFACE: &str = "wlan0";
+/// The default path to the dnsmasq lease file
+const DEFAULT_DHCP_LEASES: &str = "/var/lib/misc/dnsmasq.leases";
+
+/// The name of the deny list created next to the hostapd config
+/// when the config doesn't already specify one
+const DEFAULT_DENY_FILE: &str = "hostapd.deny";
+
+/// The name of the systemd service running the access point
+const HOSTAPD_SERVICE: &str = "hostapd";
+
+/// The channels that can be used on each band. 0 lets hostapd pick
+const CHANNELS_2_4GHZ: [u32; 14] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14];
+const CHANNELS_5GHZ: [u32; 28] = [
+    36, 40, 44, 48, 52, 56, 60, 64, 100, 104, 108, 112, 116, 120, 124, 128, 132, 136, 140, 144,
+    149, 153, 157, 161, 165, 169, 173, 177,
+];
+
+/// The channels used when the band is changed without a channel
+const DEFAULT_CHANNEL_2_4GHZ: u32 = 6;
+const DEFAULT_CHANNEL_5GHZ: u32 = 36;
+
+/// Settings for where the hotspot configuration is stored
+pub struct HotspotSettings {
+    /// The path to the hostapd config
+    pub config_path: PathBuf,
+    /// The wireless interface used when the config doesn't specify one
+    pub interface: String,
+    /// The path to the dnsmasq lease file
+    pub leases_path: PathBuf,
+}
+
+impl HotspotSettings {
+    /// Creates the hotspot settings from the environment variables
+    pub fn from_env() -> HotspotSettings {
+        let config_path = std::env::var(ENV_HOSTAPD_CONFIG_KEY)
+            .unwrap_or_else(|_| DEFAULT_HOSTAPD_CONFIG.to_string());
+        let interface = std::env::var(ENV_HOTSPOT_INTERFACE_KEY)
+            .unwrap_or_else(|_| DEFAULT_HOTSPOT_INTERFACE.to_string());
+        let leases_path =
+            std::env::var(ENV_DHCP_LEASES_KEY).unwrap_or_else(|_| DEFAULT_DHCP_LEASES.to_string());
+        HotspotSettings {
+            config_path: PathBuf::from(config_path),
+            interface,
+            leases_path: PathBuf::from(leases_path),
+        }
+    }
+}
+
+/// Iterates the key value pairs of the provided hostapd style output
+/// or config ignoring comments and lines without a value
+fn parse_pairs(contents: &str) -> impl Iterator<Item = (&str, &str)> {
+    contents
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value))
+}
+
+/// Parses the hotspot configuration from the contents of a hostapd config
+pub fn parse_config(contents: &str) -> HotspotConfig {
+    let mut config = HotspotConfig::default();
+    for (key, value) in parse_pairs(contents) {
+        match key {
+            "interface" => config.interface = Some(value.trim().to_string()),
+            "ssid" => config.ssid = Some(value.to_string()),
+            "wpa_passphrase" => config.passphrase = Some(value.to_string()),
+            "channel" => config.channel = value.trim().parse().ok(),
+            "hw_mode" => {
+                config.band = match value.trim() {
+                    "a" => Some(HotspotBand::FiveGhz),
+                    "b" | "g" => Some(HotspotBand::TwoPointFourGhz),
+                    _ => None,
+                }
+            }
+            _ => {}
+        }
+    }
+    config
+}
+
+/// Parses the state, frequency and number of connected clients
+/// from the output of the hostapd_cli status command
+pub fn parse_status(output: &str) -> Result<(bool, Option<u32>, Option<u32>), HotspotError> {
+    let mut state = None;
+    let mut frequency = None;
+    let mut clients = None;
+    for (key, value) in parse_pairs(output) {
+        match key {
+            "state" => state = Some(value.trim() == "ENABLED"),
+            "freq" => frequency = value.trim().parse().ok(),
+            "num_sta[0]" => clients = value.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    let active = state.ok_or(HotspotError::CommandOutputError)?;
+    Ok((active, frequency, clients))
+}
+
+/// Reads the current contents of the hostapd config
+fn read_config(settings: &HotspotSettings) -> Result<String, HotspotError> {
+    fs::read_to_string(&settings.config_path).map_err(|err| match err.kind() {
+        ErrorKind::NotFound => HotspotError::NotConfigured,
+        _ => {
+            error!("Failed to read hostapd config: {}", err);
+            HotspotError::IOError
+        }
+    })
+}
+
+/// Retrieves the configuration of the hotspot and whether it is running
+pub fn get_hotspot_status(
+    runner: &dyn CommandRunner,
+    settings: &HotspotSettings,
+) -> Result<HotspotStatus, HotspotError> {
+    let config = parse_config(&read_config(settings)?);
+    let interface = config.interface.as_deref().unwrap_or(&settings.interface);
+
+    let output = runner
+        .run("hostapd_cli", &["-i", interface, "status"])
+        .map_err(|_| HotspotError::CommandError)?;
+
+    // hostapd_cli fails to connect when hostapd isn't running
+    let (active, frequency, clients) = if output.success {
+        parse_status(&output.stdout)?
+    } else {
+        (false, None, None)
+    };
+
+    Ok(HotspotStatus {
+        active,
+        frequency: frequency.filter(|_| active),
+        clients: clients.filter(|_| active),
+        config,
+    })
+}
+
+/// Checks that the provided network name is valid for hostapd
+fn check_ssid(ssid: &str) -> Result<(), HotspotError> {
+    if ssid.is_empty() || ssid.len() > 32 {
+        return Err(HotspotError::InvalidConfig("ssid must be 1 to 32 bytes"));
+    }
+    if ssid.chars().any(char::is_control) {
+        return Err(HotspotError::InvalidConfig(
+            "ssid must not contain control characters",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the provided passphrase is a valid WPA passphrase
+fn check_passphrase(passphrase: &str) -> Result<(), HotspotError> {
+    if !(8..=63).contains(&passphrase.len()) {
+        return Err(HotspotError::InvalidConfig(
+            "passphrase must be 8 to 63 characters",
+        ));
+    }
+    if !passphrase.bytes().all(|byte| (0x20..=0x7e).contains(&byte)) {
+        return Err(HotspotError::InvalidConfig(
+            "passphrase must only contain printable ascii characters",
+        ));
+    }
+    Ok(())
+}
+
+/// Merges the provided request into the current configuration checking
+/// that the resulting configuration is valid. The channel is reset to
+/// the default for the band when the band changes without a channel
+pub fn merge_config(
+    current: &HotspotConfig,
+    request: &HotspotConfigRequest,
+) -> Result<HotspotConfig, HotspotError> {
+    if let Some(ssid) = &request.ssid {
+        check_ssid(ssid)?;
+    }
+    if let Some(passphrase) = &request.passphrase {
+        check_passphrase(passphrase)?;
+    }
+
+    let band = request
+        .band
+        .or(current.band)
+        .unwrap_or(HotspotBand::TwoPointFourGhz);
+    let band_changed = request
+        .band
+        .is_some_and(|value| current.band != Some(value));
+    let channel = match (request.channel, band) {
+        (Some(channel), _) => Some(channel),
+        (None, HotspotBand::TwoPointFourGhz) if band_changed => Some(DEFAULT_CHANNEL_2_4GHZ),
+        (None, HotspotBand::FiveGhz) if band_changed => Some(DEFAULT_CHANNEL_5GHZ),
+        (None, _) => current.channel,
+    };
+
+    if let Some(channel) = channel.filter(|channel| *channel != 0) {
+        let channels: &[u32] = match band {
+            HotspotBand::TwoPointFourGhz => &CHANNELS_2_4GHZ,
+            HotspotBand::FiveGhz => &CHANNELS_5GHZ,
+        };
+        if !channels.contains(&channel) {
+            return Err(HotspotError::InvalidConfig(
+                "channel is not valid for the band",
+            ));
+        }
+    }
+
+    Ok(HotspotConfig {
+        interface: current.interface.clone(),
+        ssid: request.ssid.clone().or_else(|| current.ssid.clone()),
+        passphrase: request
+            .passphrase
+            .clone()
+            .or_else(|| current.passphrase.clone()),
+        channel,
+        band: Some(band),
+    })
+}
+
+/// Replaces the values of the provided keys in the hostapd config keeping
+/// the rest of the config intact. Keys that aren't present are appended
+fn replace_values(contents: &str, values: &[(&str, String)]) -> String {
+    let mut replaced = vec![false; values.len()];
+    let mut output = String::with_capacity(contents.len());
+
+    for line in contents.lines() {
+        let key = line
+            .split_once('=')
+            .filter(|_| !line.trim_start().starts_with('#'))
+            .map(|(key, _)| key.trim());
+        let index = key.and_then(|key| values.iter().position(|(name, _)| *name == key));
+        match index {
+            // Duplicate keys are dropped so the replaced value is used
+            Some(index) if replaced[index] => continue,
+            Some(index) => {
+                let (key, value) = &values[index];
+                output.push_str(&format!("{}={}", key, value));
+                replaced[index] = true;
+            }
+            None => output.push_str(line),
+        }
+        output.push('\n');
+    }
+
+    for ((key, value), _) in values.iter().zip(replaced).filter(|(_, found)| !found) {
+        output.push_str(&format!("{}={}\n", key, value));
+    }
+    output
+}
+
+/// Creates the contents of the hostapd config with the values
+/// from the provided configuration
+pub fn update_config(contents: &str, config: &HotspotConfig) -> String {
+    let mut values = Vec::new();
+    if let Some(ssid) = &config.ssid {
+        values.push(("ssid", ssid.clone()));
+    }
+    if let Some(band) = config.band {
+        let mode = match band {
+            HotspotBand::TwoPointFourGhz => "g",
+            HotspotBand::FiveGhz => "a",
+        };
+        values.push(("hw_mode", mode.to_string()));
+    }
+    if let Some(channel) = config.channel {
+        values.push(("channel", channel.to_string()));
+    }
+    if let Some(passphrase) = &config.passphrase {
+        values.push(("wpa_passphrase", passphrase.clone()));
+
+        // The passphrase is ignored unless WPA is enabled
+        if !parse_pairs(contents).any(|(key, _)| key == "wpa") {
+            values.push(("wpa", "2".to_string()));
+            values.push(("wpa_key_mgmt", "WPA-PSK".to_string()));
+        }
+    }
+    replace_values(contents, &values)
+}
+
+/// Writes the provided contents to a hostapd file through a temporary file
+/// keeping the permissions of the existing file as the config contains the
+/// passphrase
+fn write_file(path: &Path, contents: &str) -> Result<(), HotspotError> {
+    let permissions = match fs::metadata(path) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    fs::write(&tmp_path, contents)?;
+    if let Some(permissions) = permissions {
+        fs::set_permissions(&tmp_path, permissions)?;
+    }
+    fs::rename(&tmp_path, path).map_err(|err| {
+        error!("Failed to replace {}: {}", path.display(), err);
+        let _ = fs::remove_file(&tmp_path);
+        HotspotError::IOError
+    })
+}
+
+/// Runs the provided systemctl action on the hostapd service
+/// returning the error output of the command on failure
+fn run_systemctl(runner: &dyn CommandRunner, action: &str) -> Result<(), HotspotError> {
+    let output = runner
+        .run("systemctl", &[action, HOSTAPD_SERVICE])
+        .map_err(|_| HotspotError::CommandError)?;
+    if !output.success {
+        error!("Failed to {} hostapd: {}", action, output.stderr.trim_end());
+        return Err(match action {
+            "stop" => HotspotError::CommandError,
+            _ => HotspotError::NotActivated,
+        });
+    }
+    Ok(())
+}
+
+/// Checks whether the hostapd service is running
+fn is_service_active(runner: &dyn CommandRunner) -> Result<bool, HotspotError> {
+    runner
+        .run("systemctl", &["is-active", "--quiet", HOSTAPD_SERVICE])
+        .map(|output| output.success)
+        .map_err(|_| HotspotError::CommandError)
+}
+
+/// Changes the hotspot configuration restarting the hotspot
+/// if it is running so that the changes take effect
+pub fn set_hotspot_config(
+    runner: &dyn CommandRunner,
+    settings: &HotspotSettings,
+    request: &HotspotConfigRequest,
+) -> Result<HotspotStatus, HotspotError> {
+    let contents = read_config(settings)?;
+    let config = merge_config(&parse_config(&contents), request)?;
+    let updated = update_config(&contents, &config);
+
+    if updated != contents {
+        write_file(&settings.config_path, &updated)?;
+        info!("Updated hotspot configuration");
+
+        if is_service_active(runner)? {
+            run_systemctl(runner, "restart")?;
+        }
+    }
+    get_hotspot_status(runner, settings)
+}
+
+/// Starts the hotspot
+pub fn start_hotspot(
+    runner: &dyn CommandRunner,
+    settings: &HotspotSettings,
+) -> Result<HotspotStatus, HotspotError> {
+    // Starting without a config would only report a generic failure
+    read_config(settings)?;
+    run_systemctl(runner, "start")?;
+    info!("Started hotspot");
+    get_hotspot_status(runner, settings)
+}
+
+/// Stops the hotspot disconnecting any connected clients
+pub fn stop_hotspot(
+    runner: &dyn CommandRunner,
+    settings: &HotspotSettings,
+) -> Result<HotspotStatus, HotspotError> {
+    run_systemctl(runner, "stop")?;
+    info!("Stopped hotspot");
+    get_hotspot_status(runner, settings)
+}
+
+/// Normalizes the provided MAC address to lowercase returning
+/// None if it isn't a valid colon separated MAC address
+pub fn parse_mac(mac: &str) -> Option<String> {
+    let mac = mac.trim().to_ascii_lowercase();
+    let valid = mac.len() == 17
+        && mac.split(':').count() == 6
+        && mac
+            .split(':')
+            .all(|part| part.len() == 2 && part.bytes().all(|byte| byte.is_ascii_hexdigit()));
+    valid.then_some(mac)
+}
+
+/// Parses the clients from the contents of a dnsmasq lease file. Each
+/// lease is a line of "expiry mac ip hostname client-id" where the expiry
+/// is unix time in seconds (0 for infinite) and the hostname is "*" when
+/// the client didn't provide one
+pub fn parse_leases(contents: &str) -> Vec<HotspotClient> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let expiry: u128 = parts.next()?.parse().ok()?;
+            let mac = parse_mac(parts.next()?)?;
+            let ip = parts.next()?.to_string();
+            let hostname = parts.next().filter(|value| *value != "*");
+            Some(HotspotClient {
+                mac,
+                ip: Some(ip),
+                hostname: hostname.map(str::to_string),
+                expires: (expiry != 0).then_some(expiry * 1000),
+                state: None,
+                blocked: false,
+            })
+        })
+        .collect()
+}
+
+/// Parses the IP address, MAC address and state of the neighbours from
+/// the output of `ip neigh show dev <interface>`. Entries without a MAC
+/// address (e.g. FAILED or INCOMPLETE) are skipped
+pub fn parse_neighbours(output: &str) -> Vec<(String, String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let ip = parts.first()?;
+            let lladdr = parts.iter().position(|part| *part == "lladdr")?;
+            let mac = parse_mac(parts.get(lladdr + 1)?)?;
+            let state = parts.last()?;
+            Some((ip.to_string(), mac, state.to_string()))
+        })
+        .collect()
+}
+
+/// Merges the neighbour table into the clients from the DHCP leases adding
+/// neighbours that don't have a lease (e.g. clients with static addresses)
+pub fn merge_neighbours(
+    clients: &mut Vec<HotspotClient>,
+    neighbours: Vec<(String, String, String)>,
+) {
+    for (ip, mac, state) in neighbours {
+        match clients.iter_mut().find(|client| client.mac == mac) {
+            Some(client) => {
+                client.ip.get_or_insert(ip);
+                client.state = Some(state);
+            }
+            None => clients.push(HotspotClient {
+                mac,
+                ip: Some(ip),
+                hostname: None,
+                expires: None,
+                state: Some(state),
+                blocked: false,
+            }),
+        }
+    }
+}
+
+/// Retrieves the path to the hostapd deny list from the provided config
+/// contents resolving relative paths against the config folder
+fn get_deny_path(settings: &HotspotSettings, contents: &str) -> Option<PathBuf> {
+    let (_, value) = parse_pairs(contents).find(|(key, _)| *key == "deny_mac_file")?;
+    let path = Path::new(value.trim());
+    Some(match settings.config_path.parent() {
+        Some(parent) if path.is_relative() => parent.join(path),
+        _ => path.to_path_buf(),
+    })
+}
+
+/// Reads the blocked MAC addresses from the provided deny list
+fn read_deny_list(path: &Path) -> Result<Vec<String>, HotspotError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents
+            .lines()
+            .filter_map(|line| parse_mac(line.split('#').next().unwrap_or_default()))
+            .collect()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => {
+            error!("Failed to read hostapd deny list: {}", err);
+            Err(HotspotError::IOError)
+        }
+    }
+}
+
+/// Retrieves the MAC addresses blocked from connecting to the hotspot
+pub fn get_blocked_clients(settings: &HotspotSettings) -> Result<Vec<String>, HotspotError> {
+    let contents = read_config(settings)?;
+    match get_deny_path(settings, &contents) {
+        Some(path) => read_deny_list(&path),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Retrieves the clients connected to the hotspot using the dnsmasq
+/// leases along with the neighbour table of the hotspot interface
+pub fn get_hotspot_clients(
+    runner: &dyn CommandRunner,
+    settings: &HotspotSettings,
+) -> Result<Vec<HotspotClient>, HotspotError> {
+    let contents = read_config(settings)?;
+    let config = parse_config(&contents);
+    let interface = config.interface.as_deref().unwrap_or(&settings.interface);
+
+    let mut clients = match fs::read_to_string(&settings.leases_path) {
+        Ok(leases) => parse_leases(&leases),
+        Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+        Err(err) => {
+            error!("Failed to read dnsmasq leases: {}", err);
+            return Err(HotspotError::IOError);
+        }
+    };
+
+    let output = runner
+        .run("ip", &["neigh", "show", "dev", interface])
+        .map_err(|_| HotspotError::CommandError)?;
+    if output.success {
+        merge_neighbours(&mut clients, parse_neighbours(&output.stdout));
+    } else {
+        warn!(
+            "Failed to read neighbours of {}: {}",
+            interface,
+            output.stderr.trim_end()
+        );
+    }
+
+    let blocked = match get_deny_path(settings, &contents) {
+        Some(path) => read_deny_list(&path)?,
+        None => Vec::new(),
+    };
+    for client in &mut clients {
+        client.blocked = blocked.contains(&client.mac);
+    }
+    Ok(clients)
+}
+
+/// Adds or removes the provided MAC address from the hostapd deny list.
+/// A deny list is added to the config if it doesn't have one. Running
+/// hotspots are updated through hostapd_cli disconnecting blocked clients
+/// falling back to restarting hostapd. Responds with the blocked addresses
+pub fn set_client_blocked(
+    runner: &dyn CommandRunner,
+    settings: &HotspotSettings,
+    mac: &str,
+    blocked: bool,
+) -> Result<Vec<String>, HotspotError> {
+    let mac = parse_mac(mac).ok_or(HotspotError::InvalidConfig("invalid mac address"))?;
+    let contents = read_config(settings)?;
+    let interface = parse_config(&contents)
+        .interface
+        .unwrap_or_else(|| settings.interface.clone());
+
+    let (deny_path, config_changed) = match get_deny_path(settings, &contents) {
+        Some(path) => (path, false),
+        None => {
+            let path = settings.config_path.with_file_name(DEFAULT_DENY_FILE);
+            let mut values = vec![("deny_mac_file", path.to_string_lossy().to_string())];
+            if !parse_pairs(&contents).any(|(key, _)| key == "macaddr_acl") {
+                values.push(("macaddr_acl", "0".to_string()));
+            }
+            write_file(&settings.config_path, &replace_values(&contents, &values))?;
+            (path, true)
+        }
+    };
+
+    let mut list = read_deny_list(&deny_path)?;
+    if list.contains(&mac) == blocked && !config_changed {
+        return Ok(list);
+    }
+    list.retain(|value| *value != mac);
+    if blocked {
+        list.push(mac.clone());
+    }
+    let deny_contents: String = list.iter().map(|mac| format!("{}\n", mac)).collect();
+    write_file(&deny_path, &deny_contents)?;
+    info!(
+        "{} hotspot client {}",
+        if blocked { "Blocked" } else { "Unblocked" },
+        mac
+    );
+
+    if !is_service_active(runner)? {
+        return Ok(list);
+    }
+
+    // hostapd only reads a newly added deny list on startup
+    let updated = !config_changed && {
+        let action = if blocked { "ADD_MAC" } else { "DEL_MAC" };
+        let mut args = vec!["-i", interface.as_str(), "deny_acl", action, mac.as_str()];
+        // Older versions of hostapd_cli report unknown commands with a zero exit code
+        let added = runner
+            .run("hostapd_cli", &args)
+            .is_ok_and(|output| output.success && output.stdout.trim_end() == "OK");
+        if added && blocked {
+            args.truncate(2);
+            args.extend(["deauthenticate", mac.as_str()]);
+            let _ = runner.run("hostapd_cli", &args);
+        }
+        added
+    };
+    if !updated {
+        run_systemctl(runner, "restart")?;
+    }
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::command::{CommandOutput, ScriptedRunner};
+
+    const CONFIG: &str = "\
+# DietPi hostapd config
+interface=wlan0
+driver=nl80211
+ssid=Drive-PI
+hw_mode=g
+channel=3
+wpa=2
+wpa_passphrase=Drive-PI
+wpa_key_mgmt=WPA-PSK
+";
+
+    const STATUS: &str = "\
+state=ENABLED
+phy=phy0
+freq=2422
+channel=3
+num_sta[0]=2
+ssid[0]=Drive-PI
+";
+
+    fn create_settings(contents: Option<&str>) -> (tempfile::TempDir, HotspotSettings) {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("hostapd.conf");
+        if let Some(contents) = contents {
+            fs::write(&config_path, contents).unwrap();
+        }
+        let settings = HotspotSettings {
+            config_path,
+            interface: "wlan1".to_string(),
+            leases_path: dir.path().join("dnsmasq.leases"),
+        };
+        (dir, settings)
+    }
+
+    #[test]
+    fn parses_config() {
+        let config = parse_config(CONFIG);
+        assert_eq!(
+            config,
+            HotspotConfig {
+                interface: Some("wlan0".to_string()),
+                ssid: Some("Drive-PI".to_string()),
+                passphrase: Some("Drive-PI".to_string()),
+                channel: Some(3),
+                band: Some(HotspotBand::TwoPointFourGhz),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_status() {
+        assert_eq!(parse_status(STATUS).unwrap(), (true, Some(2422), Some(2)));
+        assert_eq!(
+            parse_status("state=DISABLED\n").unwrap(),
+            (false, None, None)
+        );
+        assert!(matches!(
+            parse_status("Failed to connect to hostapd"),
+            Err(HotspotError::CommandOutputError)
+        ));
+    }
+
+    #[test]
+    fn reads_running_status() {
+        let (_dir, settings) = create_settings(Some(CONFIG));
+        let runner = ScriptedRunner::new().expect(
+            "hostapd_cli -i wlan0 status",
+            CommandOutput::success(STATUS),
+        );
+        let status = get_hotspot_status(&runner, &settings).unwrap();
+        assert!(status.active);
+        assert_eq!(status.clients, Some(2));
+        assert_eq!(status.config.ssid.as_deref(), Some("Drive-PI"));
+        // The passphrase is kept out of responses
+        let value = serde_json::to_value(&status).unwrap();
+        assert!(value["config"].get("passphrase").is_none());
+        runner.assert_done();
+    }
+
+    #[test]
+    fn reads_stopped_status() {
+        let (_dir, settings) = create_settings(Some("ssid=Drive-PI\n"));
+        let runner = ScriptedRunner::new().expect(
+            "hostapd_cli -i wlan1 status",
+            CommandOutput::failure(255, "Failed to connect to hostapd"),
+        );
+        let status = get_hotspot_status(&runner, &settings).unwrap();
+        assert!(!status.active);
+        assert_eq!(status.frequency, None);
+        runner.assert_done();
+    }
+
+    #[test]
+    fn reports_missing_config() {
+        let (_dir, settings) = create_settings(None);
+        let runner = ScriptedRunner::new();
+        assert!(matches!(
+            get_hotspot_status(&runner, &settings),
+            Err(HotspotError::NotConfigured)
+        ));
+        assert!(matches!(
+            start_hotspot(&runner, &settings),
+            Err(HotspotError::NotConfigured)
+        ));
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn merges_and_validates_config() {
+        let current = parse_config(CONFIG);
+
+        let request = HotspotConfigRequest {
+            band: Some(HotspotBand::FiveGhz),
+            ..Default::default()
+        };
+        let config = merge_config(&current, &request).unwrap();
+        assert_eq!(config.channel, Some(DEFAULT_CHANNEL_5GHZ));
+        assert_eq!(config.ssid, current.ssid);
+
+        let invalid = [
+            HotspotConfigRequest {
+                ssid: Some(String::new()),
+                ..Default::default()
+            },
+            HotspotConfigRequest {
+                ssid: Some("Drive\nPI".to_string()),
+                ..Default::default()
+            },
+            HotspotConfigRequest {
+                passphrase: Some("short".to_string()),
+                ..Default::default()
+            },
+            HotspotConfigRequest {
+                passphrase: Some("pass\u{e9}word".to_string()),
+                ..Default::default()
+            },
+            HotspotConfigRequest {
+                channel: Some(36),
+                ..Default::default()
+            },
+            HotspotConfigRequest {
+                band: Some(HotspotBand::FiveGhz),
+                channel: Some(6),
+                ..Default::default()
+            },
+        ];
+        for request in invalid {
+            assert!(matches!(
+                merge_config(&current, &request),
+                Err(HotspotError::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn updates_config_keeping_other_lines() {
+        let config = HotspotConfig {
+            ssid: Some("Photos".to_string()),
+            channel: Some(11),
+            ..parse_config(CONFIG)
+        };
+        let updated = update_config(CONFIG, &config);
+        assert!(updated.contains("\nssid=Photos\n"));
+        assert!(updated.contains("\nchannel=11\n"));
+        assert!(updated.starts_with("# DietPi hostapd config\ninterface=wlan0\ndriver=nl80211\n"));
+        assert_eq!(updated.lines().count(), CONFIG.lines().count());
+
+        let config = HotspotConfig {
+            passphrase: Some("password".to_string()),
+            ..Default::default()
+        };
+        let updated = update_config("ssid=Open\nssid=Duplicate\n", &config);
+        assert_eq!(
+            updated,
+            "ssid=Open\nssid=Duplicate\nwpa_passphrase=password\nwpa=2\nwpa_key_mgmt=WPA-PSK\n"
+        );
+    }
+
+    #[test]
+    fn restarts_running_hotspot_after_config_change() {
+        let (_dir, settings) = create_settings(Some(CONFIG));
+        let runner = ScriptedRunner::new()
+            .expect(
+                "systemctl is-active --quiet hostapd",
+                CommandOutput::success(""),
+            )
+            .expect("systemctl restart hostapd", CommandOutput::success(""))
+            .expect(
+                "hostapd_cli -i wlan0 status",
+                CommandOutput::success(STATUS),
+            );
+        let request = HotspotConfigRequest {
+            passphrase: Some("correct horse".to_string()),
+            ..Default::default()
+        };
+        let status = set_hotspot_config(&runner, &settings, &request).unwrap();
+        assert_eq!(status.config.passphrase.as_deref(), Some("correct horse"));
+        let contents = fs::read_to_string(&settings.config_path).unwrap();
+        assert!(contents.contains("\nwpa_passphrase=correct horse\n"));
+        runner.assert_done();
+    }
+
+    #[test]
+    fn keeps_config_when_invalid() {
+        let (_dir, settings) = create_settings(Some(CONFIG));
+        let runner = ScriptedRunner::new();
+        let request = HotspotConfigRequest {
+            channel: Some(200),
+            ..Default::default()
+        };
+        assert!(matches!(
+            set_hotspot_config(&runner, &settings, &request),
+            Err(HotspotError::InvalidConfig(_))
+        ));
+        assert_eq!(fs::read_to_string(&settings.config_path).unwrap(), CONFIG);
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    fn starts_and_stops_hotspot() {
+        let (_dir, settings) = create_settings(Some(CONFIG));
+        let runner = ScriptedRunner::new()
+            .expect(
+                "systemctl start hostapd",
+                CommandOutput::failure(1, "Job for hostapd.service failed"),
+            )
+            .expect("systemctl stop hostapd", CommandOutput::success(""))
+            .expect(
+                "hostapd_cli -i wlan0 status",
+                CommandOutput::failure(255, ""),
+            );
+        assert!(matches!(
+            start_hotspot(&runner, &settings),
+            Err(HotspotError::NotActivated)
+        ));
+        let status = stop_hotspot(&runner, &settings).unwrap();
+        assert!(!status.active);
+        runner.assert_done();
+    }
+
+    #[test]
+    fn reports_missing_commands() {
+        let (_dir, settings) = create_settings(Some(CONFIG));
+        let runner = ScriptedRunner::new().expect_missing("hostapd_cli -i wlan0 status");
+        assert!(matches!(
+            get_hotspot_status(&runner, &settings),
+            Err(HotspotError::CommandError)
+        ));
+    }
+
+    const LEASES: &str = "\
+1700000000 AA:BB:CC:DD:EE:01 192.168.42.10 phone 01:aa:bb:cc:dd:ee:01
+0 aa:bb:cc:dd:ee:02 192.168.42.11 * *
+invalid line
+";
+
+    const NEIGHBOURS: &str = "\
+192.168.42.10 lladdr aa:bb:cc:dd:ee:01 REACHABLE
+192.168.42.12 lladdr aa:bb:cc:dd:ee:03 STALE
+192.168.42.13  FAILED
+";
+
+    #[test]
+    fn parses_mac_addresses() {
+        assert_eq!(
+            parse_mac("AA:bb:CC:dd:EE:0f").as_deref(),
+            Some("aa:bb:cc:dd:ee:0f")
+        );
+        for mac in [
+            "",
+            "aa:bb:cc:dd:ee",
+            "aa-bb-cc-dd-ee-ff",
+            "aa:bb:cc:dd:ee:fg",
+        ] {
+            assert_eq!(parse_mac(mac), None);
+        }
+    }
+
+    #[test]
+    fn parses_leases() {
+        let clients = parse_leases(LEASES);
+        assert_eq!(
+            clients,
+            vec![
+                HotspotClient {
+                    mac: "aa:bb:cc:dd:ee:01".to_string(),
+                    ip: Some("192.168.42.10".to_string()),
+                    hostname: Some("phone".to_string()),
+                    expires: Some(1_700_000_000_000),
+                    state: None,
+                    blocked: false,
+                },
+                HotspotClient {
+                    mac: "aa:bb:cc:dd:ee:02".to_string(),
+                    ip: Some("192.168.42.11".to_string()),
+                    hostname: None,
+                    expires: None,
+                    state: None,
+                    blocked: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_neighbours_into_clients() {
+        let neighbours = parse_neighbours(NEIGHBOURS);
+        assert_eq!(neighbours.len(), 2);
+
+        let mut clients = parse_leases(LEASES);
+        merge_neighbours(&mut clients, neighbours);
+        let states: Vec<_> = clients
+            .iter()
+            .map(|client| (client.mac.as_str(), client.state.as_deref()))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                ("aa:bb:cc:dd:ee:01", Some("REACHABLE")),
+                ("aa:bb:cc:dd:ee:02", None),
+                ("aa:bb:cc:dd:ee:03", Some("STALE")),
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_clients_with_blocked_state() {
+        let config = format!("{}deny_mac_file=hostapd.deny\n", CONFIG);
+        let (dir, settings) = create_settings(Some(&config));
+        fs::write(&settings.leases_path, LEASES).unwrap();
+        fs::write(
+            dir.path().join("hostapd.deny"),
+            "# Blocked\nAA:BB:CC:DD:EE:02\n",
+        )
+        .unwrap();
+        let runner = ScriptedRunner::new().expect(
+            "ip neigh show dev wlan0",
+            CommandOutput::success(NEIGHBOURS),
+        );
+        let clients = get_hotspot_clients(&runner, &settings).unwrap();
+        let blocked: Vec<_> = clients.iter().map(|client| client.blocked).collect();
+        assert_eq!(blocked, vec![false, true, false]);
+        assert_eq!(
+            get_blocked_clients(&settings).unwrap(),
+            vec!["aa:bb:cc:dd:ee:02"]
+        );
+        runner.assert_done();
+    }
+
+    #[test]
+    fn adds_deny_list_when_blocking() {
+        let (dir, settings) = create_settings(Some(CONFIG));
+        let runner = ScriptedRunner::new()
+            .expect(
+                "systemctl is-active --quiet hostapd",
+                CommandOutput::success(""),
+            )
+            .expect("systemctl restart hostapd", CommandOutput::success(""));
+        let blocked = set_client_blocked(&runner, &settings, "AA:BB:CC:DD:EE:01", true).unwrap();
+        assert_eq!(blocked, vec!["aa:bb:cc:dd:ee:01"]);
+
+        let deny_path = dir.path().join(DEFAULT_DENY_FILE);
+        let contents = fs::read_to_string(&settings.config_path).unwrap();
+        assert!(contents.ends_with(&format!(
+            "deny_mac_file={}\nmacaddr_acl=0\n",
+            deny_path.display()
+        )));
+        assert_eq!(
+            fs::read_to_string(&deny_path).unwrap(),
+            "aa:bb:cc:dd:ee:01\n"
+        );
+        runner.assert_done();
+    }
+
+    #[test]
+    fn blocks_clients_of_running_hotspot() {
+        let config = format!("{}deny_mac_file=hostapd.deny\n", CONFIG);
+        let (dir, settings) = create_settings(Some(&config));
+        let runner = ScriptedRunner::new()
+            .expect(
+                "systemctl is-active --quiet hostapd",
+                CommandOutput::success(""),
+            )
+            .expect(
+                "hostapd_cli -i wlan0 deny_acl ADD_MAC aa:bb:cc:dd:ee:01",
+                CommandOutput::success("OK\n"),
+            )
+            .expect(
+                "hostapd_cli -i wlan0 deauthenticate aa:bb:cc:dd:ee:01",
+                CommandOutput::success("OK\n"),
+            )
+            .expect(
+                "systemctl is-active --quiet hostapd",
+                CommandOutput::success(""),
+            )
+            .expect(
+                "hostapd_cli -i wlan0 deny_acl DEL_MAC aa:bb:cc:dd:ee:01",
+                CommandOutput::success("UNKNOWN COMMAND\n"),
+            )
+            .expect("systemctl restart hostapd", CommandOutput::success(""));
+        set_client_blocked(&runner, &settings, "aa:bb:cc:dd:ee:01", true).unwrap();
+        // Blocking again doesn't change anything
+        set_client_blocked(&runner, &settings, "aa:bb:cc:dd:ee:01", true).unwrap();
+        let blocked = set_client_blocked(&runner, &settings, "aa:bb:cc:dd:ee:01", false).unwrap();
+        assert!(blocked.is_empty());
+        assert_eq!(
+            fs::read_to_string(dir.path().join("hostapd.deny")).unwrap(),
+            ""
+        );
+        assert_eq!(fs::read_to_string(&settings.config_path).unwrap(), config);
+        runner.assert_done();
+
+        assert!(matches!(
+            set_client_blocked(&runner, &settings, "not a mac", true),
+            Err(HotspotError::InvalidConfig(_))
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt::Display;
+use std::io;
+use std::sync::Arc;
+
+use log::{info, warn};
+
+use crate::models::events::ServerEvent;
+use crate::models::jobs::JobState;
+use crate::stores::events::EventBus;
+use crate::stores::jobs::{Job, JobRef};
+use crate::utils::files::CopyProgress;
+
+impl CopyProgress for Job {
+    fn set_total(&self, total: u64) {
+        Job::set_total(self, total);
+    }
+
+    fn copied(&self, bytes: u64) -> io::Result<()> {
+        self.add_processed(bytes);
+        if self.is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "job cancelled"));
+        }
+        Ok(())
+    }
+}
+
+/// Runs the provided task for the provided job in the background on the
+/// blocking thread pool. The task can return a message describing the
+/// result. The result is recorded on the job and published once finished
+pub fn spawn_job<F, E>(job: JobRef, event_bus: Arc<EventBus>, task: F)
+where
+    F: FnOnce(&Job) -> Result<Option<String>, E> + Send + 'static,
+    E: Display,
+{
+    tokio::spawn(async move {
+        let task_job = job.clone();
+        let result =
+            tokio::task::spawn_blocking(move || task(&task_job).map_err(|err| err.to_string()))
+                .await
+                .unwrap_or_else(|err| Err(format!("job task failed: {}", err)));
+        job.finish(result);
+
+        let status = job.status();
+        match status.state {
+            JobState::Failed => warn!(
+                "Job {} ({:?} {}) failed: {}",
+                status.id,
+                status.kind,
+                status.target,
+                status.error.as_deref().unwrap_or_default()
+            ),
+            state => info!(
+                "Job {} ({:?} {}) finished: {:?}",
+                status.id, status.kind, status.target, state
+            ),
+        }
+        event_bus.publish(ServerEvent::JobFinished { job: status });
+    });
+}
@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+
+use crate::models::drives::{DriveEvent, KnownDrive};
+use crate::stores::known::KnownDriveStore;
+use crate::utils::drives::MOUNT_DIR;
+use crate::utils::write_json_file;
+
+/// The name of the file known drives are stored in. The
+/// file is stored next to the mount directory
+const KNOWN_DRIVES_FILE: &str = "known_drives.json";
+
+/// Retrieves the path to the file known drives are stored in
+pub fn get_known_drives_path() -> PathBuf {
+    Path::new(MOUNT_DIR).with_file_name(KNOWN_DRIVES_FILE)
+}
+
+/// Retrieves the current time in milliseconds since the unix epoch
+pub fn current_time_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_millis(0))
+        .as_millis()
+}
+
+/// Loads the known drives from the file at the provided path. No drives
+/// are loaded if the file doesn't exist or is invalid
+pub fn load_known_drives(path: &Path) -> Vec<KnownDrive> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Vec::new(),
+        Err(err) => {
+            warn!("Failed to read known drives: {}", err);
+            return Vec::new();
+        }
+    };
+    match serde_json::from_slice::<Vec<KnownDrive>>(&contents) {
+        Ok(drives) => drives,
+        Err(err) => {
+            warn!("Failed to parse known drives: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+/// Saves the provided known drives to the file at the provided path
+pub fn save_known_drives(path: &Path, drives: &HashMap<String, KnownDrive>) -> io::Result<()> {
+    let mut drives: Vec<&KnownDrive> = drives.values().collect();
+    drives.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+    write_json_file(path, &drives)
+}
+
+/// Background task which records drives in the known drive store as they
+/// are connected, changed or disconnected. Disconnected drives are recorded
+/// so their last seen time is when they were disconnected
+pub async fn track_known_drives(mut receiver: Receiver<DriveEvent>, store: Arc<KnownDriveStore>) {
+    loop {
+        let drive = match receiver.recv().await {
+            Ok(DriveEvent::Added(drive))
+            | Ok(DriveEvent::Changed(drive))
+            | Ok(DriveEvent::Removed(drive)) => drive,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("Known drive tracking missed {} drive events", missed);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if drive.uuid.is_empty() {
+            continue;
+        }
+        if let Err(err) = store.record_seen(&drive).await {
+            warn!("Failed to save known drives: {}", err);
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use log::{info, warn};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+
+/// Environment variable for whether to run the built-in mDNS responder
+const ENV_MDNS_KEY: &str = "DRIVEPI_MDNS";
+/// Environment variable for the host name advertised over mDNS
+const ENV_MDNS_HOSTNAME_KEY: &str = "DRIVEPI_MDNS_HOSTNAME";
+
+/// The default host name advertised over mDNS (drivepi.local)
+const DEFAULT_MDNS_HOSTNAME: &str = "drivepi";
+
+/// The instance name the services are advertised under
+const INSTANCE_NAME: &str = "Drive-PI";
+
+/// The DNS-SD service types for the web panel and the samba share
+const HTTP_SERVICE_TYPE: &str = "_http._tcp.local.";
+const SMB_SERVICE_TYPE: &str = "_smb._tcp.local.";
+
+/// The port samba listens on
+const SMB_PORT: u16 = 445;
+
+/// Retrieves whether the built-in mDNS responder is enabled. Disabled by
+/// default as the hotspot setup resolves drivepi.local using dnsmasq
+pub fn get_env_mdns_enabled() -> bool {
+    let Ok(value) = std::env::var(ENV_MDNS_KEY) else {
+        return false;
+    };
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" => true,
+        "false" | "0" | "no" => false,
+        _ => {
+            warn!(
+                "mDNS setting provided as {} is not a valid boolean defaulting to disabled",
+                value
+            );
+            false
+        }
+    }
+}
+
+/// Retrieves the host name to advertise over mDNS without the .local suffix
+pub fn get_env_mdns_hostname() -> String {
+    let hostname =
+        std::env::var(ENV_MDNS_HOSTNAME_KEY).unwrap_or_else(|_| DEFAULT_MDNS_HOSTNAME.to_string());
+    let hostname = hostname.trim_end_matches('.');
+    hostname
+        .strip_suffix(".local")
+        .unwrap_or(hostname)
+        .to_string()
+}
+
+/// Starts the mDNS responder if it is enabled advertising the host name
+/// along with the web panel on the provided port and the samba share. The
+/// addresses of every interface are advertised so the panel can be found
+/// on any network the device is connected to. The returned daemon must be
+/// kept alive for the services to stay advertised
+pub fn start_mdns(port: u16) -> Option<ServiceDaemon> {
+    if !get_env_mdns_enabled() {
+        return None;
+    }
+
+    let daemon = match ServiceDaemon::new() {
+        Ok(daemon) => daemon,
+        Err(err) => {
+            warn!("Failed to start mDNS responder: {}", err);
+            return None;
+        }
+    };
+
+    let host_name = format!("{}.local.", get_env_mdns_hostname());
+    let services = [
+        (HTTP_SERVICE_TYPE, port, &[("path", "/")][..]),
+        (SMB_SERVICE_TYPE, SMB_PORT, &[][..]),
+    ];
+
+    for (service_type, port, properties) in services {
+        let result = ServiceInfo::new(
+            service_type,
+            INSTANCE_NAME,
+            &host_name,
+            (),
+            port,
+            properties,
+        )
+        .map(ServiceInfo::enable_addr_auto)
+        .and_then(|service| daemon.register(service));
+
+        match result {
+            Ok(()) => info!(
+                "Advertising {} on {} port {} over mDNS",
+                service_type, host_name, port
+            ),
+            Err(err) => warn!("Failed to advertise {} over mDNS: {}", service_type, err),
+        }
+    }
+
+    Some(daemon)
+}
@@ -1,9 +1,28 @@
+pub mod archive;
+pub mod automount;
+pub mod command;
 pub mod drives;
+pub mod events;
 pub mod files;
+pub mod holders;
+pub mod hotplug;
+pub mod hotspot;
+pub mod jobs;
+pub mod known;
+pub mod mdns;
+pub mod resolve;
+pub mod samba;
+pub mod search;
+pub mod trash;
+pub mod uploads;
 
 use actix_web::web::Json;
 use log::warn;
 use rand::{thread_rng, Rng};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::Path;
 use std::process::Output;
 
 const ENV_PORT_KEY: &str = "DRIVEPI_PORT";
@@ -17,12 +36,12 @@ pub type JsonEmpty<E> = Result<Json<()>, E>;
 /// many times in a loop without reallocating this vec
 pub fn create_character_set() -> Vec<char> {
     let charset = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789-";
-    return charset.chars().collect();
+    charset.chars().collect()
 }
 
 /// Creates a random string of the provided length from randomly
 /// select chars in the provided charset
-pub fn create_random_string(charset: &Vec<char>, length: usize) -> String {
+pub fn create_random_string(charset: &[char], length: usize) -> String {
     let mut rng = thread_rng();
     let mut result = String::with_capacity(length);
 
@@ -30,13 +49,12 @@ pub fn create_random_string(charset: &Vec<char>, length: usize) -> String {
 
     for _ in 0..length {
         let char_index = rng.gen_range(0..char_count);
-        match charset.get(char_index) {
-            Some(char) => result.push(*char),
-            None => {}
+        if let Some(char) = charset.get(char_index) {
+            result.push(*char)
         }
     }
 
-    return result;
+    result
 }
 
 pub fn get_env_port() -> u16 {
@@ -57,11 +75,11 @@ pub fn get_env_port() -> u16 {
 }
 
 pub fn ok_json<V, E>(value: V) -> Result<Json<V>, E> {
-    return Ok(Json(value));
+    Ok(Json(value))
 }
 
 pub fn ok_json_empty<E>() -> Result<Json<()>, E> {
-    return Ok(Json(()));
+    Ok(Json(()))
 }
 
 #[inline]
@@ -73,3 +91,14 @@ pub fn status_result<F, O: FnOnce(&str) -> F>(output: Output, op: O) -> Result<(
         Err(op(&stderr))
     }
 }
+
+/// Writes the provided value as JSON to the file at the provided path.
+/// The value is written to a temporary file first so that a partially
+/// written file never replaces the existing one
+pub fn write_json_file<V: Serialize>(path: &Path, value: &V) -> io::Result<()> {
+    let contents = serde_json::to_vec_pretty(value)?;
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    fs::write(&temp_path, contents)?;
+    fs::rename(&temp_path, path)
+}
@@ -0,0 +1,609 @@
+use std::collections::VecDeque;
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::{self, File, Metadata, ReadDir};
+use std::io;
+use std::mem;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use libc::c_int;
+
+/// The maximum number of symlinks followed while resolving a single
+/// path. Matches the limit used by the kernel
+const MAX_SYMLINKS: usize = 40;
+
+/// Whether the kernel has been found not to support openat2 in which
+/// case paths are always resolved by walking them one component at a time
+static OPENAT2_UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Opens the entry at the provided path beneath the provided root directory.
+///
+/// Paths are resolved with the same rules as openat2 with RESOLVE_BENEATH.
+/// Absolute paths, ".." components and symlinks that would leave the root
+/// fail with EXDEV and symlink loops fail with ELOOP. Because the entry is
+/// opened while it is resolved, the returned file cannot have been swapped
+/// for a symlink after its path was checked.
+///
+/// Symlinks are followed unless the flags include O_NOFOLLOW. In that case a
+/// final symlink is opened itself when O_PATH is also set. The flags must not
+/// include O_CREAT
+pub fn open_beneath(root: &Path, path: &Path, flags: c_int) -> io::Result<File> {
+    let root = open_at(
+        libc::AT_FDCWD,
+        root.as_os_str(),
+        libc::O_PATH | libc::O_DIRECTORY,
+    )?;
+    open_beneath_at(&root, path, flags)
+}
+
+/// Opens the entry at the provided path beneath the provided open root
+/// directory using the same rules as [open_beneath]
+pub fn open_beneath_at<F: AsFd>(root: F, path: &Path, flags: c_int) -> io::Result<File> {
+    let root = root.as_fd();
+    if !OPENAT2_UNSUPPORTED.load(Ordering::Relaxed) {
+        match openat2_beneath(root, path, flags) {
+            Err(err) if is_unsupported(&err) => {
+                OPENAT2_UNSUPPORTED.store(true, Ordering::Relaxed);
+            }
+            result => return result,
+        }
+    }
+
+    walk_beneath(root, path, flags)
+}
+
+/// Retrieves the path that the provided open file was resolved to
+pub fn get_fd_path<F: AsFd>(file: F) -> io::Result<PathBuf> {
+    fs::read_link(get_proc_fd_path(file.as_fd()))
+}
+
+/// Opens the entry with the provided name in the provided open directory.
+/// Symlinks are never followed so opening a symlink fails with ELOOP
+/// unless the flags include O_PATH in which case the symlink is opened
+pub fn open_entry_at<F: AsFd>(directory: F, name: &OsStr, flags: c_int) -> io::Result<File> {
+    check_name(name)?;
+    open_at(
+        directory.as_fd().as_raw_fd(),
+        name,
+        flags | libc::O_NOFOLLOW,
+    )
+}
+
+/// Retrieves the metadata of the entry with the provided name in the
+/// provided open directory without following symlinks
+pub fn metadata_at<F: AsFd>(directory: F, name: &OsStr) -> io::Result<Metadata> {
+    open_entry_at(directory, name, libc::O_PATH)?.metadata()
+}
+
+/// Creates a new file with the provided name and mode in the provided open
+/// directory opened for writing. Fails with EEXIST if anything (including
+/// a symlink) already exists with the name
+pub fn create_file_at<F: AsFd>(directory: F, name: &OsStr, mode: libc::mode_t) -> io::Result<File> {
+    let name = to_name_c_string(name)?;
+    // Safety: The name is a valid nul terminated string
+    let fd = unsafe {
+        libc::openat(
+            directory.as_fd().as_raw_fd(),
+            name.as_ptr(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            mode as libc::c_uint,
+        )
+    };
+    fd_result(fd as i64)
+}
+
+/// Creates a new folder with the provided name and mode in the provided
+/// open directory
+pub fn create_dir_at<F: AsFd>(directory: F, name: &OsStr, mode: libc::mode_t) -> io::Result<()> {
+    let name = to_name_c_string(name)?;
+    // Safety: The name is a valid nul terminated string
+    let result = unsafe { libc::mkdirat(directory.as_fd().as_raw_fd(), name.as_ptr(), mode) };
+    check_result(result)
+}
+
+/// Creates a symlink with the provided name in the provided open
+/// directory pointing at the provided target
+pub fn symlink_at<F: AsFd>(target: &Path, directory: F, name: &OsStr) -> io::Result<()> {
+    let target = CString::new(target.as_os_str().as_bytes())?;
+    let name = to_name_c_string(name)?;
+    // Safety: The target and name are valid nul terminated strings
+    let result = unsafe {
+        libc::symlinkat(
+            target.as_ptr(),
+            directory.as_fd().as_raw_fd(),
+            name.as_ptr(),
+        )
+    };
+    check_result(result)
+}
+
+/// Reads the entries of the provided open directory. The directory is read
+/// through its /proc/self/fd link so the entries must only be used by name
+/// relative to the open directory rather than through their paths
+pub fn read_dir_at<F: AsFd>(directory: F) -> io::Result<ReadDir> {
+    fs::read_dir(get_proc_fd_path(directory.as_fd()))
+}
+
+/// Renames the entry with the provided name in one open directory to the
+/// target name in another failing with EEXIST instead of replacing anything
+/// that already exists at the target. Filesystems that don't support
+/// RENAME_NOREPLACE fall back to checking for the target before renaming
+pub fn rename_at<F: AsFd, T: AsFd>(
+    from_directory: F,
+    from_name: &OsStr,
+    to_directory: T,
+    to_name: &OsStr,
+) -> io::Result<()> {
+    let from_fd = from_directory.as_fd().as_raw_fd();
+    let to_fd = to_directory.as_fd().as_raw_fd();
+    let from_c = to_name_c_string(from_name)?;
+    let to_c = to_name_c_string(to_name)?;
+    // Safety: Both names are valid nul terminated strings
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            from_fd,
+            from_c.as_ptr(),
+            to_fd,
+            to_c.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if result == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() != Some(libc::EINVAL) {
+        return Err(err);
+    }
+    if metadata_at(to_directory, to_name).is_ok() {
+        return Err(io::Error::from_raw_os_error(libc::EEXIST));
+    }
+    // Safety: Both names are valid nul terminated strings
+    let result = unsafe { libc::renameat(from_fd, from_c.as_ptr(), to_fd, to_c.as_ptr()) };
+    check_result(result)
+}
+
+/// Removes the file, folder or symlink with the provided name from the
+/// provided open directory. Folders are removed along with all of their
+/// contents and symlinks are removed rather than followed
+pub fn remove_at<F: AsFd>(directory: F, name: &OsStr) -> io::Result<()> {
+    let directory = directory.as_fd();
+    let flags = if metadata_at(directory, name)?.is_dir() {
+        let child = open_entry_at(directory, name, libc::O_RDONLY | libc::O_DIRECTORY)?;
+        for entry in read_dir_at(&child)? {
+            remove_at(&child, &entry?.file_name())?;
+        }
+        libc::AT_REMOVEDIR
+    } else {
+        0
+    };
+    let name = to_name_c_string(name)?;
+    // Safety: The name is a valid nul terminated string
+    let result = unsafe { libc::unlinkat(directory.as_raw_fd(), name.as_ptr(), flags) };
+    check_result(result)
+}
+
+/// Checks whether the provided openat2 error means that the syscall is not
+/// available. Older kernels return ENOSYS and some seccomp sandboxes EPERM
+fn is_unsupported(err: &io::Error) -> bool {
+    matches!(err.raw_os_error(), Some(libc::ENOSYS) | Some(libc::EPERM))
+}
+
+/// Converts the provided path into a C string. Empty paths are
+/// converted to "." so that they refer to the directory itself
+fn to_c_string(path: &OsStr) -> io::Result<CString> {
+    if path.is_empty() {
+        Ok(CString::new(".")?)
+    } else {
+        Ok(CString::new(path.as_bytes())?)
+    }
+}
+
+/// Ensures the provided name is a single path component so that
+/// entries opened by name stay within their directory
+fn check_name(name: &OsStr) -> io::Result<()> {
+    let bytes = name.as_bytes();
+    if bytes.is_empty() || bytes == b"." || bytes == b".." || bytes.contains(&b'/') {
+        return Err(io::Error::from_raw_os_error(libc::EINVAL));
+    }
+    Ok(())
+}
+
+/// Converts the provided entry name into a C string ensuring
+/// that it is a single path component
+fn to_name_c_string(name: &OsStr) -> io::Result<CString> {
+    check_name(name)?;
+    Ok(CString::new(name.as_bytes())?)
+}
+
+/// Retrieves the /proc path that the provided descriptor can be
+/// reopened or read through
+fn get_proc_fd_path(fd: BorrowedFd<'_>) -> String {
+    format!("/proc/self/fd/{}", fd.as_raw_fd())
+}
+
+/// Converts the return value of a syscall that returns zero on success
+fn check_result(result: c_int) -> io::Result<()> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Converts the return value of a syscall that creates a file
+/// descriptor into an owned file
+fn fd_result(fd: i64) -> io::Result<File> {
+    if fd < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        // Safety: The descriptor was just created and is owned by nothing else
+        Ok(unsafe { File::from_raw_fd(fd as RawFd) })
+    }
+}
+
+/// Opens the provided path relative to the provided directory descriptor
+fn open_at(directory: RawFd, path: &OsStr, flags: c_int) -> io::Result<File> {
+    let path = to_c_string(path)?;
+    // Safety: The path is a valid nul terminated string
+    let fd = unsafe { libc::openat(directory, path.as_ptr(), flags | libc::O_CLOEXEC) };
+    fd_result(fd as i64)
+}
+
+/// Opens the provided path beneath the root directory using openat2
+fn openat2_beneath(root: BorrowedFd<'_>, path: &Path, flags: c_int) -> io::Result<File> {
+    let path = to_c_string(path.as_os_str())?;
+    // Safety: open_how is a plain struct of integers so zeroed is valid
+    let mut how: libc::open_how = unsafe { mem::zeroed() };
+    how.flags = (flags | libc::O_CLOEXEC) as u64;
+    how.resolve = libc::RESOLVE_BENEATH | libc::RESOLVE_NO_MAGICLINKS;
+
+    // Safety: The path is a valid nul terminated string and the size
+    // matches the open_how structure being passed
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_openat2,
+            root.as_raw_fd(),
+            path.as_ptr(),
+            &how as *const libc::open_how,
+            mem::size_of::<libc::open_how>(),
+        )
+    };
+    fd_result(fd as i64)
+}
+
+/// Reads the target of the symlink with the provided name in the directory
+pub fn read_link_at<F: AsFd>(directory: F, name: &OsStr) -> io::Result<PathBuf> {
+    let name = to_name_c_string(name)?;
+    let mut buffer = vec![0u8; libc::PATH_MAX as usize];
+    // Safety: The name is a valid nul terminated string and the
+    // length passed matches the length of the buffer
+    let length = unsafe {
+        libc::readlinkat(
+            directory.as_fd().as_raw_fd(),
+            name.as_ptr(),
+            buffer.as_mut_ptr() as *mut libc::c_char,
+            buffer.len(),
+        )
+    };
+    if length < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buffer.truncate(length as usize);
+    Ok(PathBuf::from(OsStr::from_bytes(&buffer)))
+}
+
+/// Creates the error returned when a path would escape the root
+fn escape_error() -> io::Error {
+    io::Error::from_raw_os_error(libc::EXDEV)
+}
+
+/// Pushes the components of the provided path onto the front of the
+/// queue of components still to be resolved. Absolute paths escape the
+/// root so they are rejected
+fn push_components(pending: &mut VecDeque<OsString>, path: &Path) -> io::Result<()> {
+    for component in path.components().rev() {
+        match component {
+            Component::Normal(name) => pending.push_front(name.to_os_string()),
+            Component::ParentDir => pending.push_front(OsString::from("..")),
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => return Err(escape_error()),
+        }
+    }
+    Ok(())
+}
+
+/// Opens the provided path beneath the root directory by opening each
+/// component relative to the last without following symlinks. Symlinks are
+/// resolved manually so that their targets are also kept beneath the root.
+/// Used when openat2 is not supported by the kernel
+fn walk_beneath(root: BorrowedFd<'_>, path: &Path, flags: c_int) -> io::Result<File> {
+    let follow_final = flags & libc::O_NOFOLLOW == 0;
+    let mut pending = VecDeque::new();
+    push_components(&mut pending, path)?;
+
+    // Stack of the directories walked through from the root
+    let mut directories = vec![File::from(root.try_clone_to_owned()?)];
+    let mut symlinks = 0;
+
+    while let Some(name) = pending.pop_front() {
+        let directory = directories.last().ok_or_else(escape_error)?;
+
+        if name == ".." {
+            if directories.len() == 1 {
+                return Err(escape_error());
+            }
+            directories.pop();
+            continue;
+        }
+
+        let entry = open_at(
+            directory.as_raw_fd(),
+            &name,
+            libc::O_PATH | libc::O_NOFOLLOW,
+        )?;
+        let metadata = entry.metadata()?;
+        let is_final = pending.is_empty();
+
+        if metadata.is_symlink() && (!is_final || follow_final) {
+            symlinks += 1;
+            if symlinks > MAX_SYMLINKS {
+                return Err(io::Error::from_raw_os_error(libc::ELOOP));
+            }
+            let target = read_link_at(directory, &name)?;
+            push_components(&mut pending, &target)?;
+            continue;
+        }
+
+        if is_final {
+            // Reopen the entry with the requested flags. O_NOFOLLOW ensures
+            // the entry wasn't replaced with a symlink since it was checked
+            return open_at(directory.as_raw_fd(), &name, flags | libc::O_NOFOLLOW);
+        }
+
+        if !metadata.is_dir() {
+            return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+        }
+        directories.push(entry);
+    }
+
+    // The path resolved to one of the walked directories
+    let directory = directories.last().ok_or_else(escape_error)?;
+    open_at(directory.as_raw_fd(), OsStr::new(""), flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Creates a root directory containing files, folders and symlinks
+    /// that point both inside and outside of the root
+    fn create_root() -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path();
+        fs::create_dir(path.join("dir")).unwrap();
+        fs::write(path.join("dir/file.txt"), "contents").unwrap();
+        symlink("../dir", path.join("dir/self")).unwrap();
+        symlink("dir/file.txt", path.join("link.txt")).unwrap();
+        symlink("/etc", path.join("etc")).unwrap();
+        symlink("..", path.join("up")).unwrap();
+        symlink(path.join("dir"), path.join("absolute")).unwrap();
+        symlink("loop_b", path.join("loop_a")).unwrap();
+        symlink("loop_a", path.join("loop_b")).unwrap();
+        root
+    }
+
+    /// Opens the path beneath the root using both openat2 (when the
+    /// kernel supports it) and the fallback walk
+    fn open_both(root: &Path, path: &str, flags: c_int) -> Vec<io::Result<File>> {
+        let root_file = open_at(libc::AT_FDCWD, root.as_os_str(), libc::O_PATH).unwrap();
+        let path = Path::new(path);
+        let mut results = vec![walk_beneath(root_file.as_fd(), path, flags)];
+        match openat2_beneath(root_file.as_fd(), path, flags) {
+            Err(err) if is_unsupported(&err) => {}
+            result => results.push(result),
+        }
+        results
+    }
+
+    /// Asserts that opening the path fails with the provided errno
+    fn assert_error(root: &Path, path: &str, flags: c_int, errno: c_int) {
+        for result in open_both(root, path, flags) {
+            let err = result.expect_err(path);
+            assert_eq!(err.raw_os_error(), Some(errno), "{}: {}", path, err);
+        }
+    }
+
+    /// Asserts that opening the path resolves to the expected path
+    fn assert_resolves(root: &Path, path: &str, flags: c_int, expected: &str) {
+        let expected = root.canonicalize().unwrap().join(expected);
+        for result in open_both(root, path, flags) {
+            let file = result.expect(path);
+            assert_eq!(get_fd_path(&file).unwrap(), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn resolves_nested_paths() {
+        let root = create_root();
+        assert_resolves(root.path(), "dir/file.txt", libc::O_RDONLY, "dir/file.txt");
+        assert_resolves(
+            root.path(),
+            "./dir/./file.txt",
+            libc::O_RDONLY,
+            "dir/file.txt",
+        );
+        assert_resolves(root.path(), "", libc::O_PATH, "");
+    }
+
+    #[test]
+    fn allows_parent_components_within_root() {
+        let root = create_root();
+        assert_resolves(
+            root.path(),
+            "dir/../dir/file.txt",
+            libc::O_RDONLY,
+            "dir/file.txt",
+        );
+        assert_resolves(root.path(), "dir/..", libc::O_PATH, "");
+    }
+
+    #[test]
+    fn follows_symlinks_within_root() {
+        let root = create_root();
+        assert_resolves(root.path(), "link.txt", libc::O_RDONLY, "dir/file.txt");
+        assert_resolves(
+            root.path(),
+            "dir/self/self/file.txt",
+            libc::O_RDONLY,
+            "dir/file.txt",
+        );
+    }
+
+    #[test]
+    fn opens_final_symlink_without_following() {
+        let root = create_root();
+        let flags = libc::O_PATH | libc::O_NOFOLLOW;
+        assert_resolves(root.path(), "link.txt", flags, "link.txt");
+        assert_resolves(root.path(), "etc", flags, "etc");
+    }
+
+    #[test]
+    fn rejects_parent_escapes() {
+        let root = create_root();
+        assert_error(root.path(), "..", libc::O_PATH, libc::EXDEV);
+        assert_error(root.path(), "../etc/passwd", libc::O_RDONLY, libc::EXDEV);
+        assert_error(
+            root.path(),
+            "dir/../../etc/passwd",
+            libc::O_RDONLY,
+            libc::EXDEV,
+        );
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let root = create_root();
+        assert_error(root.path(), "/etc/passwd", libc::O_RDONLY, libc::EXDEV);
+        let inside = root.path().join("dir/file.txt");
+        assert_error(
+            root.path(),
+            inside.to_str().unwrap(),
+            libc::O_RDONLY,
+            libc::EXDEV,
+        );
+    }
+
+    #[test]
+    fn rejects_symlink_escapes() {
+        let root = create_root();
+        assert_error(root.path(), "etc", libc::O_PATH, libc::EXDEV);
+        assert_error(root.path(), "etc/passwd", libc::O_RDONLY, libc::EXDEV);
+        assert_error(root.path(), "up/etc", libc::O_PATH, libc::EXDEV);
+        assert_error(root.path(), "dir/self/../up", libc::O_PATH, libc::EXDEV);
+        assert_error(
+            root.path(),
+            "absolute/file.txt",
+            libc::O_RDONLY,
+            libc::EXDEV,
+        );
+    }
+
+    #[test]
+    fn rejects_symlink_loops() {
+        let root = create_root();
+        assert_error(root.path(), "loop_a", libc::O_RDONLY, libc::ELOOP);
+        assert_error(root.path(), "loop_a/file.txt", libc::O_RDONLY, libc::ELOOP);
+    }
+
+    #[test]
+    fn rejects_missing_and_non_directory_components() {
+        let root = create_root();
+        assert_error(root.path(), "missing.txt", libc::O_RDONLY, libc::ENOENT);
+        assert_error(root.path(), "dir/file.txt/x", libc::O_RDONLY, libc::ENOTDIR);
+    }
+
+    #[test]
+    fn renames_without_replacing() {
+        let root = create_root();
+        let path = root.path();
+        fs::write(path.join("new.txt"), "new").unwrap();
+        let root_dir = File::open(path).unwrap();
+        let dir = File::open(path.join("dir")).unwrap();
+        let name = OsStr::new("new.txt");
+
+        let err = rename_at(&root_dir, name, &dir, OsStr::new("file.txt"));
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        // Broken symlinks are not replaced either
+        let err = rename_at(&root_dir, name, &root_dir, OsStr::new("loop_a"));
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(path.join("dir/file.txt")).unwrap(), b"contents");
+
+        rename_at(&root_dir, name, &dir, name).unwrap();
+        assert_eq!(fs::read(path.join("dir/new.txt")).unwrap(), b"new");
+    }
+
+    #[test]
+    fn rejects_names_that_are_not_single_components() {
+        let root = create_root();
+        let root_dir = File::open(root.path()).unwrap();
+        for name in ["", ".", "..", "dir/file.txt", "/etc"] {
+            let err = metadata_at(&root_dir, OsStr::new(name)).unwrap_err();
+            assert_eq!(err.raw_os_error(), Some(libc::EINVAL), "{}", name);
+        }
+    }
+
+    #[test]
+    fn entries_at_do_not_follow_symlinks() {
+        let root = create_root();
+        let root_dir = File::open(root.path()).unwrap();
+        let link = OsStr::new("link.txt");
+
+        assert!(metadata_at(&root_dir, link).unwrap().is_symlink());
+        let err = open_entry_at(&root_dir, link, libc::O_RDONLY).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ELOOP));
+        let err = create_file_at(&root_dir, link, 0o644).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(
+            read_link_at(&root_dir, link).unwrap(),
+            Path::new("dir/file.txt")
+        );
+    }
+
+    #[test]
+    fn removes_folders_without_following_symlinks() {
+        let root = create_root();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("keep.txt"), "keep").unwrap();
+        symlink(outside.path(), root.path().join("dir/outside")).unwrap();
+        let root_dir = File::open(root.path()).unwrap();
+
+        remove_at(&root_dir, OsStr::new("dir")).unwrap();
+        remove_at(&root_dir, OsStr::new("loop_a")).unwrap();
+        assert!(!root.path().join("dir").exists());
+        assert!(root.path().join("loop_b").symlink_metadata().is_ok());
+        assert_eq!(fs::read(outside.path().join("keep.txt")).unwrap(), b"keep");
+    }
+
+    #[test]
+    fn opens_beneath_open_directory() {
+        let root = create_root();
+        let dir = File::open(root.path().join("dir")).unwrap();
+        let file = open_beneath_at(&dir, Path::new("file.txt"), libc::O_PATH).unwrap();
+        assert_eq!(
+            get_fd_path(&file).unwrap(),
+            root.path().canonicalize().unwrap().join("dir/file.txt")
+        );
+        // The open directory is the root so links back up to it escape
+        let err = open_beneath_at(&dir, Path::new("self/file.txt"), libc::O_PATH).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EXDEV));
+    }
+}
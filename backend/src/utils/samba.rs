@@ -0,0 +1,997 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::{debug, error, info, warn};
+
+use crate::models::errors::SambaError;
+use crate::models::samba::{SambaUser, ShareSettings};
+use crate::utils::command::{CommandOutput, CommandRunner};
+use crate::utils::drives::{
+    get_drive_list, get_group_id, get_group_name, get_mount_root, get_read_only_mounts, MountOwner,
+    ENV_MOUNT_GROUP_KEY, MOUNT_DIR,
+};
+use crate::utils::write_json_file;
+
+/// Environment variable for choosing how mounted drives are shared
+const ENV_SAMBA_MODE_KEY: &str = "DRIVEPI_SAMBA_MODE";
+
+/// Environment variable for the path of the generated samba config
+const ENV_SAMBA_CONFIG_KEY: &str = "DRIVEPI_SAMBA_CONFIG";
+
+/// The default path of the generated samba config. This file is
+/// included from the main smb.conf
+const DEFAULT_SAMBA_CONFIG: &str = "/etc/samba/drivepi.conf";
+
+/// The name of the share used when sharing the whole mount root
+const AGGREGATE_SHARE_NAME: &str = "drivepi";
+
+/// Share names which have a special meaning to samba
+const RESERVED_SHARE_NAMES: [&str; 4] = ["global", "homes", "printers", "print$"];
+
+/// The maximum length of a share name that all clients support
+const MAX_SHARE_NAME_LENGTH: usize = 80;
+
+/// The name of the file the share settings of each drive are stored
+/// in. The file is stored next to the mount directory
+const SHARE_SETTINGS_FILE: &str = "samba_shares.json";
+
+/// The maximum length of a samba user name
+const MAX_USER_NAME_LENGTH: usize = 32;
+
+/// Lock held while the share settings file is modified so
+/// concurrent updates don't overwrite each other
+static SHARE_SETTINGS_LOCK: Mutex<()> = Mutex::new(());
+
+type SambaResult<T> = Result<T, SambaError>;
+
+/// How the mounted drives are shared
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ShareMode {
+    /// A single share over the whole mount root
+    Aggregate,
+    /// A separate share for each mounted drive
+    PerDrive,
+}
+
+/// Configuration for where the generated samba config is written
+/// and the samba binaries used to validate and reload it
+pub struct SambaConfig {
+    /// How the mounted drives are shared
+    pub mode: ShareMode,
+    /// The path of the generated config which smb.conf includes
+    pub config_path: PathBuf,
+    /// The testparm binary used to validate the config
+    pub testparm: PathBuf,
+    /// The smbcontrol binary used to reload smbd
+    pub smbcontrol: PathBuf,
+    /// The smbpasswd binary used to add and remove users
+    pub smbpasswd: PathBuf,
+    /// The pdbedit binary used to list users
+    pub pdbedit: PathBuf,
+    /// The useradd binary used to create the unix accounts for users
+    pub useradd: PathBuf,
+    /// The usermod binary used to add existing accounts to the mount group
+    pub usermod: PathBuf,
+    /// The name of the group mounted drives belong to. Users are added to
+    /// the group and shares create files with it so users can write them
+    pub group: Option<String>,
+    /// The umask of mounted drives which the permissions of files and
+    /// folders created through the shares are limited to
+    pub umask: u32,
+    /// The path of the file the share settings are stored in
+    pub settings_path: PathBuf,
+}
+
+impl SambaConfig {
+    /// Creates the samba config from the environment variables
+    pub fn from_env() -> SambaConfig {
+        let mode = match std::env::var(ENV_SAMBA_MODE_KEY) {
+            Ok(mode) if mode == "drives" => ShareMode::PerDrive,
+            Ok(mode) if mode == "aggregate" => ShareMode::Aggregate,
+            Ok(mode) => {
+                warn!(
+                    "Samba mode provided as {} is not one of aggregate or drives defaulting to aggregate",
+                    mode
+                );
+                ShareMode::Aggregate
+            }
+            Err(_) => ShareMode::Aggregate,
+        };
+        let config_path = std::env::var(ENV_SAMBA_CONFIG_KEY)
+            .unwrap_or_else(|_| DEFAULT_SAMBA_CONFIG.to_string());
+        let owner = MountOwner::from_env();
+        let group = get_share_group(owner.gid, get_group_name(owner.gid));
+        SambaConfig {
+            mode,
+            config_path: PathBuf::from(config_path),
+            testparm: PathBuf::from("testparm"),
+            smbcontrol: PathBuf::from("smbcontrol"),
+            smbpasswd: PathBuf::from("smbpasswd"),
+            pdbedit: PathBuf::from("pdbedit"),
+            useradd: PathBuf::from("useradd"),
+            usermod: PathBuf::from("usermod"),
+            group,
+            umask: owner.umask,
+            settings_path: Path::new(MOUNT_DIR).with_file_name(SHARE_SETTINGS_FILE),
+        }
+    }
+}
+
+/// Retrieves the name of the group samba users join and shares create files
+/// with from the provided mount group. The root group is never used as it
+/// would give every samba user the privileges of root's group
+fn get_share_group(gid: u32, name: Option<String>) -> Option<String> {
+    if gid == 0 {
+        warn!(
+            "Mount group is root so samba users aren't added to it, set {} to a dedicated group",
+            ENV_MOUNT_GROUP_KEY
+        );
+        return None;
+    }
+    if name.is_none() {
+        warn!(
+            "Mount group {} has no name so samba users can't join it",
+            gid
+        );
+    }
+    name
+}
+
+/// Structure representing a single samba share
+#[derive(Debug, PartialEq)]
+pub struct Share {
+    /// The name of the share (e.g. USB Drive)
+    pub name: String,
+    /// The path on disk that is shared
+    pub path: PathBuf,
+    /// Whether the share is read only
+    pub read_only: bool,
+    /// Whether guests can access the share without a password
+    pub guest: bool,
+    /// The users allowed to access the share. Any user can
+    /// access the share when this is empty
+    pub valid_users: Vec<String>,
+}
+
+/// Updates the samba shares to match the currently mounted drives. Failures
+/// are logged rather than returned so that mounting isn't affected by samba
+pub fn update_samba_shares(runner: &dyn CommandRunner) {
+    let config = SambaConfig::from_env();
+    if let Err(err) = sync_shares(&config, runner) {
+        warn!("Failed to update samba shares: {}", err);
+    }
+}
+
+/// Generates the shares for the currently mounted drives and applies them
+pub fn sync_shares(config: &SambaConfig, runner: &dyn CommandRunner) -> SambaResult<()> {
+    // Samba isn't installed when its config directory doesn't exist
+    if config
+        .config_path
+        .parent()
+        .is_some_and(|parent| !parent.as_os_str().is_empty() && !parent.exists())
+    {
+        debug!("Samba config directory missing, skipping share update");
+        return Ok(());
+    }
+
+    let mount_root = get_mount_root()?;
+    let shares = match config.mode {
+        ShareMode::Aggregate => vec![Share {
+            name: AGGREGATE_SHARE_NAME.to_string(),
+            path: mount_root,
+            read_only: false,
+            guest: false,
+            valid_users: Vec::new(),
+        }],
+        ShareMode::PerDrive => get_drive_shares(config, runner, &mount_root)?,
+    };
+    let contents = render_config(&shares, config.group.as_deref(), config.umask);
+    apply_config(config, runner, &contents)
+}
+
+/// Creates a share for each drive mounted directly beneath the mount root
+/// using the share settings stored for the drive
+fn get_drive_shares(
+    config: &SambaConfig,
+    runner: &dyn CommandRunner,
+    mount_root: &Path,
+) -> SambaResult<Vec<Share>> {
+    let drives = get_drive_list(runner)?.drives;
+    let read_only_mounts = get_read_only_mounts()?;
+    let mut settings = load_share_settings(&config.settings_path);
+
+    let mut names = HashSet::new();
+    let mut shares = Vec::new();
+    for drive in drives {
+        let path = match &drive.mount {
+            Some(mount) => PathBuf::from(mount),
+            None => continue,
+        };
+        if path.parent() != Some(mount_root) {
+            continue;
+        }
+        // Line breaks in the path would add lines to the config
+        if path.to_string_lossy().chars().any(char::is_control) {
+            warn!("Not sharing drive {} with invalid mount path", drive.path);
+            continue;
+        }
+        let folder = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = get_unique_name(&mut names, &get_share_name(&folder));
+        let settings = settings.remove(&drive.uuid).unwrap_or_default();
+        shares.push(Share {
+            name,
+            read_only: settings.read_only || read_only_mounts.contains(&path),
+            guest: settings.guest,
+            valid_users: settings.valid_users,
+            path,
+        });
+    }
+    shares.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(shares)
+}
+
+/// Creates a valid share name from the provided folder name by replacing
+/// the characters that samba or windows clients don't allow in share names
+pub fn get_share_name(folder: &str) -> String {
+    let name: String = folder
+        .chars()
+        .map(|value| match value {
+            '[' | ']' | '\\' | '/' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | ';' | ',' | '='
+            | '+' | '%' => '_',
+            value if value.is_control() => '_',
+            value => value,
+        })
+        .take(MAX_SHARE_NAME_LENGTH)
+        .collect();
+    let name = name.trim().to_string();
+
+    let is_reserved = RESERVED_SHARE_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(&name))
+        || name.eq_ignore_ascii_case(AGGREGATE_SHARE_NAME);
+    if name.is_empty() || is_reserved {
+        format!("drive-{}", name)
+    } else {
+        name
+    }
+}
+
+/// Appends a number to the provided name if a share with the same name
+/// (ignoring case like samba does) already exists
+fn get_unique_name(names: &mut HashSet<String>, name: &str) -> String {
+    let mut unique = name.to_string();
+    let mut index = 2;
+    while !names.insert(unique.to_lowercase()) {
+        unique = format!("{}-{}", name, index);
+        index += 1;
+    }
+    unique
+}
+
+/// Renders the samba config containing the provided shares
+pub fn render_config(shares: &[Share], group: Option<&str>, umask: u32) -> String {
+    let mut output =
+        String::from("# Generated by Drive-PI, changes to this file will be overwritten\n");
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+    for share in shares {
+        let _ = write!(
+            output,
+            "\n[{}]\n   comment = Drive-PI share {}\n   path = {}\n   read only = {}\n   guest ok = {}\n   browsable = yes\n",
+            share.name,
+            share.name,
+            share.path.to_string_lossy(),
+            yes_no(share.read_only),
+            yes_no(share.guest)
+        );
+        if !share.valid_users.is_empty() {
+            let _ = writeln!(output, "   valid users = {}", share.valid_users.join(" "));
+        }
+        // Files are created with the group of the drives and the permissions
+        // of the umask so every samba user can change them
+        if let Some(group) = group {
+            let _ = writeln!(output, "   force group = {}", group);
+        }
+        let _ = write!(
+            output,
+            "   create mask = {:04o}\n   directory mask = {:04o}\n",
+            0o666 & !umask,
+            0o777 & !umask
+        );
+    }
+    output
+}
+
+/// Validates the provided config with testparm and replaces the generated
+/// config with it before reloading smbd. Nothing is changed if the config
+/// is invalid or is the same as the current config
+pub fn apply_config(
+    config: &SambaConfig,
+    runner: &dyn CommandRunner,
+    contents: &str,
+) -> SambaResult<()> {
+    match fs::read_to_string(&config.config_path) {
+        Ok(current) if current == contents => return Ok(()),
+        Ok(_) => {}
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    let mut temp_path = config.config_path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    fs::write(&temp_path, contents)?;
+
+    if let Err(err) = check_config(config, runner, &temp_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+
+    fs::rename(&temp_path, &config.config_path)?;
+    info!(
+        "Updated samba shares in {}",
+        config.config_path.to_string_lossy()
+    );
+    reload_samba(config, runner)
+}
+
+/// Checks the config file at the provided path using testparm
+fn check_config(config: &SambaConfig, runner: &dyn CommandRunner, path: &Path) -> SambaResult<()> {
+    let output = run_samba_command(
+        runner,
+        &config.testparm,
+        &[
+            "-s", // Don't wait for a key press before dumping the config
+            "--suppress-prompt",
+            &path.to_string_lossy(),
+        ],
+        None,
+    )?;
+    output.status_result(|stderr| SambaError::InvalidConfig(stderr.trim().to_string()))
+}
+
+/// Tells smbd to reload its config so the share changes take effect
+fn reload_samba(config: &SambaConfig, runner: &dyn CommandRunner) -> SambaResult<()> {
+    let output = run_samba_command(runner, &config.smbcontrol, &["smbd", "reload-config"], None)?;
+    output.status_result(|stderr| SambaError::ReloadError(stderr.trim().to_string()))
+}
+
+/// Loads the share settings of each drive mapped to the filesystem UUID
+/// of the drive. No settings are loaded if the file doesn't exist or is invalid
+fn load_share_settings(path: &Path) -> HashMap<String, ShareSettings> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return HashMap::new(),
+        Err(err) => {
+            warn!("Failed to read samba share settings: {}", err);
+            return HashMap::new();
+        }
+    };
+    match serde_json::from_slice(&contents) {
+        Ok(settings) => settings,
+        Err(err) => {
+            warn!("Failed to parse samba share settings: {}", err);
+            HashMap::new()
+        }
+    }
+}
+
+/// Loads the share settings, modifies them using the provided function and
+/// saves the result while holding the lock for the settings file
+fn modify_share_settings<F>(config: &SambaConfig, modify: F) -> SambaResult<()>
+where
+    F: FnOnce(&mut HashMap<String, ShareSettings>),
+{
+    let _guard = SHARE_SETTINGS_LOCK
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    let mut settings = load_share_settings(&config.settings_path);
+    modify(&mut settings);
+    write_json_file(&config.settings_path, &settings)?;
+    Ok(())
+}
+
+/// Retrieves the share settings for the drive with the provided UUID
+pub fn get_share_settings(config: &SambaConfig, drive: &str) -> ShareSettings {
+    load_share_settings(&config.settings_path)
+        .remove(drive)
+        .unwrap_or_default()
+}
+
+/// Replaces the share settings for the drive with the provided UUID
+/// and updates the samba shares to use them. Settings are rejected when
+/// every drive is in the same share as they wouldn't be applied
+pub fn set_share_settings(
+    config: &SambaConfig,
+    runner: &dyn CommandRunner,
+    drive: &str,
+    settings: ShareSettings,
+) -> SambaResult<()> {
+    if config.mode == ShareMode::Aggregate {
+        return Err(SambaError::SharedTogether);
+    }
+    for user in &settings.valid_users {
+        check_user_name(user)?;
+    }
+    modify_share_settings(config, |stored| {
+        stored.insert(drive.to_string(), settings);
+    })?;
+    sync_shares(config, runner)
+}
+
+/// Checks that the provided user name is a valid unix user name
+fn check_user_name(name: &str) -> SambaResult<()> {
+    let mut chars = name.chars();
+    let valid_start = chars
+        .next()
+        .is_some_and(|value| value.is_ascii_lowercase() || value == '_');
+    let valid_rest = chars.all(|value| {
+        value.is_ascii_lowercase() || value.is_ascii_digit() || value == '_' || value == '-'
+    });
+    if valid_start && valid_rest && name.len() <= MAX_USER_NAME_LENGTH {
+        Ok(())
+    } else {
+        Err(SambaError::InvalidName)
+    }
+}
+
+/// Parses the users from the output of pdbedit -L which lists
+/// users as {NAME}:{UID}:{FULL_NAME} on each line
+fn parse_users(output: &str) -> Vec<SambaUser> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ':');
+            let name = parts.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            Some(SambaUser {
+                name: name.to_string(),
+                uid: parts.next().and_then(|uid| uid.trim().parse().ok()),
+                full_name: parts.next().unwrap_or_default().trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Runs the provided samba binary with the runner writing the optional
+/// input to its standard input. Failing to run the binary, including it
+/// running longer than its timeout, is reported as a command error
+fn run_samba_command(
+    runner: &dyn CommandRunner,
+    program: &Path,
+    args: &[&str],
+    input: Option<&str>,
+) -> SambaResult<CommandOutput> {
+    let program = program.to_string_lossy();
+    let result = match input {
+        Some(input) => runner.run_with_input(&program, args, input),
+        None => runner.run(&program, args),
+    };
+    result.map_err(|err| {
+        error!("Failed to execute {} command: {}", program, err);
+        SambaError::CommandError
+    })
+}
+
+/// Runs the provided user management command returning its output
+/// or an error containing stderr if the command failed
+fn run_user_command(
+    runner: &dyn CommandRunner,
+    program: &Path,
+    args: &[&str],
+) -> SambaResult<CommandOutput> {
+    let output = run_samba_command(runner, program, args, None)?;
+    output.status_result(|stderr| SambaError::UserError(stderr.trim().to_string()))?;
+    Ok(output)
+}
+
+/// Retrieves the list of samba users
+pub fn get_users(config: &SambaConfig, runner: &dyn CommandRunner) -> SambaResult<Vec<SambaUser>> {
+    let output = run_user_command(runner, &config.pdbedit, &["-L"])?;
+    Ok(parse_users(&output.stdout))
+}
+
+/// Creates a samba user with the provided name and password or changes the
+/// password if the user already exists. Samba users need a unix account so
+/// one without a home directory or login shell is created if missing
+pub fn create_user(
+    config: &SambaConfig,
+    runner: &dyn CommandRunner,
+    name: &str,
+    password: &str,
+) -> SambaResult<()> {
+    check_user_name(name)?;
+    // smbpasswd reads the password from stdin one line at a time
+    if password.is_empty() || password.contains(['\n', '\r']) {
+        return Err(SambaError::InvalidPassword);
+    }
+
+    // A misconfigured group would give every samba user root privileges
+    if let Some(group) = &config.group {
+        if group == "root" || get_group_id(group) == Some(0) {
+            error!("Refusing to add samba user {} to group {}", name, group);
+            return Err(SambaError::PrivilegedGroup);
+        }
+    }
+
+    let mut args = vec![
+        "--no-create-home",  // Users only access files through samba
+        "--shell",           // Specify the login shell
+        "/usr/sbin/nologin", // Prevent logging in to the system
+    ];
+    if let Some(group) = &config.group {
+        // The group of the mounted drives gives the user write access
+        args.extend(["--groups", group]);
+    }
+    args.push(name);
+    let output = run_samba_command(runner, &config.useradd, &args, None)?;
+    // Exit code 9 means the unix account already exists
+    if output.code == Some(9) {
+        if let Some(group) = &config.group {
+            run_user_command(
+                runner,
+                &config.usermod,
+                &[
+                    "--append", // Keep the existing groups of the user
+                    "--groups", // Groups to add the user to
+                    group, name, // Name of the user
+                ],
+            )?;
+        }
+    } else {
+        output.status_result(|stderr| SambaError::UserError(stderr.trim().to_string()))?;
+    }
+
+    // The password is entered twice for confirmation
+    let input = format!("{}\n{}\n", password, password);
+    let output = run_samba_command(
+        runner,
+        &config.smbpasswd,
+        &[
+            "-a", // Add the user or update its password
+            "-s", // Read the password from stdin
+            name, // Name of the user
+        ],
+        Some(&input),
+    )?;
+    output.status_result(|stderr| SambaError::UserError(stderr.trim().to_string()))?;
+
+    info!("Created samba user {}", name);
+    Ok(())
+}
+
+/// Removes the samba user with the provided name and removes the user from
+/// the valid users of every share. The unix account of the user is kept
+pub fn delete_user(
+    config: &SambaConfig,
+    runner: &dyn CommandRunner,
+    name: &str,
+) -> SambaResult<()> {
+    check_user_name(name)?;
+    let users = get_users(config, runner)?;
+    if !users.iter().any(|user| user.name == name) {
+        return Err(SambaError::UserNotFound);
+    }
+
+    run_user_command(
+        runner,
+        &config.smbpasswd,
+        &[
+            "-x", // Remove the user
+            name, // Name of the user
+        ],
+    )?;
+
+    modify_share_settings(config, |stored| {
+        for settings in stored.values_mut() {
+            settings.valid_users.retain(|user| user != name);
+        }
+    })?;
+    info!("Removed samba user {}", name);
+    sync_shares(config, runner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::utils::command::{ScriptedRunner, SystemRunner};
+
+    /// Writes an executable shell script stub for a binary which appends
+    /// its arguments to a log file before running the provided script
+    fn create_script(dir: &Path, name: &str, script: &str) -> PathBuf {
+        let path = dir.join(name);
+        let log = dir.join(format!("{}.log", name));
+        let script = format!(
+            "#!/bin/sh\necho \"$@\" >> \"{}\"\n{}\n",
+            log.to_string_lossy(),
+            script
+        );
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    /// Writes an executable shell script stub for a binary which logs
+    /// its arguments and exits with the provided code
+    fn create_stub(dir: &Path, name: &str, code: i32) -> PathBuf {
+        let script = format!("echo \"{} failed\" >&2\nexit {}", name, code);
+        create_script(dir, name, &script)
+    }
+
+    /// Reads the arguments each stub was called with
+    fn read_calls(dir: &Path, name: &str) -> Vec<String> {
+        fs::read_to_string(dir.join(format!("{}.log", name)))
+            .map(|log| log.lines().map(str::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    /// Creates a config which uses stub binaries exiting with the
+    /// provided codes and writes to a temporary directory
+    fn create_config(testparm_code: i32, smbcontrol_code: i32) -> (TempDir, SambaConfig) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SambaConfig {
+            mode: ShareMode::PerDrive,
+            config_path: dir.path().join("drivepi.conf"),
+            testparm: create_stub(dir.path(), "testparm", testparm_code),
+            smbcontrol: create_stub(dir.path(), "smbcontrol", smbcontrol_code),
+            smbpasswd: create_stub(dir.path(), "smbpasswd", 0),
+            pdbedit: create_stub(dir.path(), "pdbedit", 0),
+            useradd: create_stub(dir.path(), "useradd", 0),
+            usermod: create_stub(dir.path(), "usermod", 0),
+            group: Some("drivepi".to_string()),
+            umask: 0o002,
+            settings_path: dir.path().join("samba_shares.json"),
+        };
+        (dir, config)
+    }
+
+    #[test]
+    fn renders_shares() {
+        let shares = [
+            Share {
+                name: "USB Drive".to_string(),
+                path: PathBuf::from("/bin/drivepi/mount/USB Drive"),
+                read_only: false,
+                guest: false,
+                valid_users: Vec::new(),
+            },
+            Share {
+                name: "Backup".to_string(),
+                path: PathBuf::from("/bin/drivepi/mount/Backup"),
+                read_only: true,
+                guest: true,
+                valid_users: vec!["alice".to_string(), "bob".to_string()],
+            },
+        ];
+        let config = render_config(&shares, Some("drivepi"), 0o002);
+        assert!(config.starts_with("# Generated by Drive-PI"));
+        assert!(config.contains(
+            "[USB Drive]\n   comment = Drive-PI share USB Drive\n   path = /bin/drivepi/mount/USB Drive\n   read only = no\n   guest ok = no\n   browsable = yes\n   force group = drivepi\n   create mask = 0664\n   directory mask = 0775\n\n"
+        ));
+        assert!(config.contains(
+            "[Backup]\n   comment = Drive-PI share Backup\n   path = /bin/drivepi/mount/Backup\n   read only = yes\n   guest ok = yes\n   browsable = yes\n   valid users = alice bob\n   force group = drivepi\n"
+        ));
+
+        // Without a group name files keep the group of the user
+        let config = render_config(&shares[..1], None, 0o022);
+        assert!(!config.contains("force group"));
+        assert!(config.contains("   create mask = 0644\n   directory mask = 0755\n"));
+    }
+
+    #[test]
+    fn renders_no_shares() {
+        let config = render_config(&[], None, 0o002);
+        assert!(!config.contains('['));
+    }
+
+    #[test]
+    fn creates_valid_share_names() {
+        assert_eq!(get_share_name("USB Drive"), "USB Drive");
+        assert_eq!(get_share_name("[bad]/name"), "_bad__name");
+        assert_eq!(get_share_name("a;b=c"), "a_b_c");
+        assert_eq!(get_share_name("Global"), "drive-Global");
+        assert_eq!(get_share_name("drivepi"), "drive-drivepi");
+        assert_eq!(get_share_name("  "), "drive-");
+        assert_eq!(
+            get_share_name(&"a".repeat(100)).len(),
+            MAX_SHARE_NAME_LENGTH
+        );
+    }
+
+    #[test]
+    fn creates_unique_share_names() {
+        let mut names = HashSet::new();
+        assert_eq!(get_unique_name(&mut names, "USB"), "USB");
+        assert_eq!(get_unique_name(&mut names, "usb"), "usb-2");
+        assert_eq!(get_unique_name(&mut names, "USB"), "USB-3");
+    }
+
+    #[test]
+    fn applies_valid_config() {
+        let (dir, config) = create_config(0, 0);
+        apply_config(&config, &SystemRunner, "[share]\n").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&config.config_path).unwrap(),
+            "[share]\n"
+        );
+        let testparm_calls = read_calls(dir.path(), "testparm");
+        assert_eq!(testparm_calls.len(), 1);
+        assert!(testparm_calls[0].starts_with("-s --suppress-prompt "));
+        assert!(testparm_calls[0].ends_with("drivepi.conf.tmp"));
+        assert_eq!(
+            read_calls(dir.path(), "smbcontrol"),
+            vec!["smbd reload-config"]
+        );
+        assert!(!dir.path().join("drivepi.conf.tmp").exists());
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        let (dir, config) = create_config(1, 0);
+        fs::write(&config.config_path, "[old]\n").unwrap();
+
+        let err = apply_config(&config, &SystemRunner, "[new]\n").unwrap_err();
+        assert!(matches!(err, SambaError::InvalidConfig(message) if message == "testparm failed"));
+        assert_eq!(fs::read_to_string(&config.config_path).unwrap(), "[old]\n");
+        assert!(read_calls(dir.path(), "smbcontrol").is_empty());
+        assert!(!dir.path().join("drivepi.conf.tmp").exists());
+    }
+
+    #[test]
+    fn skips_unchanged_config() {
+        let (dir, config) = create_config(0, 0);
+        fs::write(&config.config_path, "[share]\n").unwrap();
+
+        apply_config(&config, &SystemRunner, "[share]\n").unwrap();
+        assert!(read_calls(dir.path(), "testparm").is_empty());
+        assert!(read_calls(dir.path(), "smbcontrol").is_empty());
+    }
+
+    #[test]
+    fn reports_reload_failure() {
+        let (_dir, config) = create_config(0, 1);
+        let err = apply_config(&config, &SystemRunner, "[share]\n").unwrap_err();
+        assert!(matches!(err, SambaError::ReloadError(message) if message == "smbcontrol failed"));
+        // The config is still replaced so the next reload picks it up
+        assert_eq!(
+            fs::read_to_string(&config.config_path).unwrap(),
+            "[share]\n"
+        );
+    }
+
+    #[test]
+    fn reports_missing_binaries() {
+        let (dir, mut config) = create_config(0, 0);
+        config.testparm = dir.path().join("missing");
+        let err = apply_config(&config, &SystemRunner, "[share]\n").unwrap_err();
+        assert!(matches!(err, SambaError::CommandError));
+        assert!(!config.config_path.exists());
+    }
+
+    #[test]
+    fn checks_user_names() {
+        for name in ["alice", "_svc", "user-2", "a_b"] {
+            assert!(check_user_name(name).is_ok(), "{}", name);
+        }
+        for name in [
+            "",
+            "Alice",
+            "2user",
+            "-user",
+            "a b",
+            "a:b",
+            "root\n",
+            &"a".repeat(33),
+        ] {
+            assert!(
+                matches!(check_user_name(name), Err(SambaError::InvalidName)),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn parses_users() {
+        let users = parse_users("alice:1001:Alice Smith\nbob:1002:\n\nbroken\n");
+        assert_eq!(
+            users,
+            vec![
+                SambaUser {
+                    name: "alice".to_string(),
+                    uid: Some(1001),
+                    full_name: "Alice Smith".to_string(),
+                },
+                SambaUser {
+                    name: "bob".to_string(),
+                    uid: Some(1002),
+                    full_name: String::new(),
+                },
+                SambaUser {
+                    name: "broken".to_string(),
+                    uid: None,
+                    full_name: String::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn creates_users() {
+        let (dir, mut config) = create_config(0, 0);
+        let stdin = dir.path().join("stdin");
+        let script = format!("cat > \"{}\"", stdin.to_string_lossy());
+        config.smbpasswd = create_script(dir.path(), "smbpasswd", &script);
+        // Existing unix accounts are reused
+        config.useradd = create_stub(dir.path(), "useradd", 9);
+
+        create_user(&config, &SystemRunner, "alice", "secret").unwrap();
+        assert_eq!(
+            read_calls(dir.path(), "useradd"),
+            vec!["--no-create-home --shell /usr/sbin/nologin --groups drivepi alice"]
+        );
+        // Existing accounts are added to the group separately
+        assert_eq!(
+            read_calls(dir.path(), "usermod"),
+            vec!["--append --groups drivepi alice"]
+        );
+        assert_eq!(read_calls(dir.path(), "smbpasswd"), vec!["-a -s alice"]);
+        assert_eq!(fs::read_to_string(stdin).unwrap(), "secret\nsecret\n");
+    }
+
+    #[test]
+    fn never_adds_users_to_root_group() {
+        let (dir, mut config) = create_config(0, 0);
+        config.group = get_share_group(0, Some("root".to_string()));
+        assert_eq!(config.group, None);
+        assert_eq!(
+            get_share_group(1001, Some("drivepi".to_string())).as_deref(),
+            Some("drivepi")
+        );
+
+        config.useradd = create_stub(dir.path(), "useradd", 9);
+        create_user(&config, &SystemRunner, "alice", "secret").unwrap();
+        assert_eq!(
+            read_calls(dir.path(), "useradd"),
+            vec!["--no-create-home --shell /usr/sbin/nologin alice"]
+        );
+        assert!(read_calls(dir.path(), "usermod").is_empty());
+
+        let share = Share {
+            name: "USB Drive".to_string(),
+            path: PathBuf::from("/bin/drivepi/mount/USB Drive"),
+            read_only: false,
+            guest: false,
+            valid_users: Vec::new(),
+        };
+        let rendered = render_config(&[share], config.group.as_deref(), config.umask);
+        assert!(!rendered.contains("force group"));
+    }
+
+    #[test]
+    fn rejects_privileged_groups() {
+        let (dir, mut config) = create_config(0, 0);
+        for group in ["root", "0"] {
+            config.group = Some(group.to_string());
+            assert!(matches!(
+                create_user(&config, &SystemRunner, "alice", "secret"),
+                Err(SambaError::PrivilegedGroup)
+            ));
+        }
+        assert!(read_calls(dir.path(), "useradd").is_empty());
+        assert!(read_calls(dir.path(), "usermod").is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_users() {
+        let (dir, config) = create_config(0, 0);
+        assert!(matches!(
+            create_user(&config, &SystemRunner, "Bad Name", "secret"),
+            Err(SambaError::InvalidName)
+        ));
+        assert!(matches!(
+            create_user(&config, &SystemRunner, "alice", "one\ntwo"),
+            Err(SambaError::InvalidPassword)
+        ));
+        assert!(matches!(
+            create_user(&config, &SystemRunner, "alice", ""),
+            Err(SambaError::InvalidPassword)
+        ));
+        assert!(read_calls(dir.path(), "smbpasswd").is_empty());
+    }
+
+    #[test]
+    fn reports_user_failures() {
+        let (dir, mut config) = create_config(0, 0);
+        config.smbpasswd = create_stub(dir.path(), "smbpasswd", 1);
+        let err = create_user(&config, &SystemRunner, "alice", "secret").unwrap_err();
+        assert!(matches!(err, SambaError::UserError(message) if message == "smbpasswd failed"));
+    }
+
+    #[test]
+    fn deletes_users() {
+        let (dir, mut config) = create_config(0, 0);
+        config.pdbedit = create_script(dir.path(), "pdbedit", "echo alice:1001:\necho bob:1002:");
+        // Skip rendering the shares which would list the real drives
+        config.config_path = dir.path().join("missing/drivepi.conf");
+
+        let settings = ShareSettings {
+            read_only: false,
+            guest: false,
+            valid_users: vec!["alice".to_string(), "bob".to_string()],
+        };
+        let runner = SystemRunner;
+        set_share_settings(&config, &runner, "1111-AAAA", settings).unwrap();
+
+        delete_user(&config, &runner, "alice").unwrap();
+        assert_eq!(read_calls(dir.path(), "smbpasswd"), vec!["-x alice"]);
+        assert_eq!(
+            get_share_settings(&config, "1111-AAAA").valid_users,
+            vec!["bob"]
+        );
+
+        assert!(matches!(
+            delete_user(&config, &runner, "carol"),
+            Err(SambaError::UserNotFound)
+        ));
+    }
+
+    #[test]
+    fn stores_share_settings() {
+        let (dir, mut config) = create_config(0, 0);
+        config.config_path = dir.path().join("missing/drivepi.conf");
+
+        assert_eq!(
+            get_share_settings(&config, "1111-AAAA"),
+            ShareSettings::default()
+        );
+        let settings = ShareSettings {
+            read_only: true,
+            guest: true,
+            valid_users: vec!["alice".to_string()],
+        };
+        let runner = ScriptedRunner::new();
+        set_share_settings(&config, &runner, "1111-AAAA", settings.clone()).unwrap();
+        assert_eq!(get_share_settings(&config, "1111-AAAA"), settings);
+        assert_eq!(
+            get_share_settings(&config, "2222-BBBB"),
+            ShareSettings::default()
+        );
+
+        let invalid = ShareSettings {
+            valid_users: vec!["bad name".to_string()],
+            ..ShareSettings::default()
+        };
+        assert!(matches!(
+            set_share_settings(&config, &runner, "1111-AAAA", invalid),
+            Err(SambaError::InvalidName)
+        ));
+    }
+
+    #[test]
+    fn rejects_share_settings_when_shared_together() {
+        let (dir, mut config) = create_config(0, 0);
+        config.mode = ShareMode::Aggregate;
+
+        let runner = ScriptedRunner::new();
+        assert!(matches!(
+            set_share_settings(&config, &runner, "1111-AAAA", ShareSettings::default()),
+            Err(SambaError::SharedTogether)
+        ));
+        assert!(!config.settings_path.exists());
+        assert!(read_calls(dir.path(), "testparm").is_empty());
+    }
+}
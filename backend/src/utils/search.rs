@@ -0,0 +1,399 @@
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs::{File, Metadata};
+use std::io;
+use std::os::fd::AsFd;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::UNIX_EPOCH;
+
+use actix_web::web::Bytes;
+use futures::{stream, Stream};
+use glob::{MatchOptions, Pattern};
+use log::warn;
+use regex::{Regex, RegexBuilder};
+use tokio::sync::mpsc;
+
+use crate::models::errors::FilesError;
+use crate::models::search::{SearchKind, SearchMode, SearchRequest, SearchResult};
+use crate::utils::files::DriveDir;
+use crate::utils::resolve::{metadata_at, open_entry_at, read_dir_at};
+use crate::utils::trash::TRASH_DIR;
+use crate::utils::uploads::STAGING_DIR;
+
+/// The maximum number of results a single search can return
+pub const MAX_SEARCH_RESULTS: usize = 1000;
+
+/// The number of results that can be waiting to be sent to the
+/// client before the search waits
+const SEARCH_CHANNEL_CAPACITY: usize = 32;
+
+type FilesResult<T> = Result<T, FilesError>;
+
+/// Matcher for comparing entry names against a search query
+enum NameMatcher {
+    Substring {
+        query: String,
+        case_sensitive: bool,
+    },
+    Glob {
+        pattern: Pattern,
+        options: MatchOptions,
+    },
+    Regex(Regex),
+}
+
+impl NameMatcher {
+    fn new(mode: SearchMode, query: &str, case_sensitive: bool) -> FilesResult<Self> {
+        Ok(match mode {
+            SearchMode::Substring => NameMatcher::Substring {
+                query: if case_sensitive {
+                    query.to_string()
+                } else {
+                    query.to_lowercase()
+                },
+                case_sensitive,
+            },
+            SearchMode::Glob => NameMatcher::Glob {
+                pattern: Pattern::new(query).map_err(|_| FilesError::InvalidPattern)?,
+                options: MatchOptions {
+                    case_sensitive,
+                    ..MatchOptions::new()
+                },
+            },
+            SearchMode::Regex => NameMatcher::Regex(
+                RegexBuilder::new(query)
+                    .case_insensitive(!case_sensitive)
+                    .build()
+                    .map_err(|_| FilesError::InvalidPattern)?,
+            ),
+        })
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NameMatcher::Substring {
+                query,
+                case_sensitive: true,
+            } => name.contains(query.as_str()),
+            NameMatcher::Substring { query, .. } => name.to_lowercase().contains(query.as_str()),
+            NameMatcher::Glob { pattern, options } => pattern.matches_with(name, *options),
+            NameMatcher::Regex(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// Filter created from a search request which entries are checked against
+pub struct SearchFilter {
+    matcher: NameMatcher,
+    kind: SearchKind,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<u128>,
+    modified_before: Option<u128>,
+}
+
+impl SearchFilter {
+    /// Creates a filter from the provided request. Fails if the
+    /// query is not a valid pattern for the requested mode
+    pub fn new(request: &SearchRequest) -> FilesResult<Self> {
+        Ok(Self {
+            matcher: NameMatcher::new(request.mode, &request.query, request.case_sensitive)?,
+            kind: request.kind,
+            min_size: request.min_size,
+            max_size: request.max_size,
+            modified_after: request.modified_after,
+            modified_before: request.modified_before,
+        })
+    }
+
+    fn matches(&self, name: &str, metadata: &Metadata, modified: Option<u128>) -> bool {
+        let is_folder = metadata.is_dir();
+        match self.kind {
+            SearchKind::File if is_folder => return false,
+            SearchKind::Folder if !is_folder => return false,
+            _ => {}
+        }
+
+        // Size filters only apply to files
+        if !is_folder {
+            let size = metadata.len();
+            if self.min_size.is_some_and(|min| size < min)
+                || self.max_size.is_some_and(|max| size > max)
+            {
+                return false;
+            }
+        }
+
+        if self.modified_after.is_some() || self.modified_before.is_some() {
+            let modified = match modified {
+                Some(modified) => modified,
+                None => return false,
+            };
+            if self.modified_after.is_some_and(|after| modified < after)
+                || self.modified_before.is_some_and(|before| modified > before)
+            {
+                return false;
+            }
+        }
+
+        self.matcher.matches(name)
+    }
+}
+
+/// Folder on a drive that a search starts from
+pub struct SearchRoot {
+    /// The filesystem UUID of the drive
+    pub drive: String,
+    /// The opened folder on the drive to search in
+    pub directory: DriveDir,
+    /// The path of the folder relative to the root of the drive
+    pub path: PathBuf,
+}
+
+/// Folder waiting to be searched. Folders are kept as a name in their
+/// opened parent until they are searched so that only the parents of
+/// waiting folders are kept open rather than every waiting folder
+struct QueuedFolder<'a> {
+    root: &'a SearchRoot,
+    /// The opened parent of the folder or None for the search root
+    parent: Option<Rc<File>>,
+    name: OsString,
+    /// The path of the folder relative to the root of the drive
+    path: PathBuf,
+}
+
+impl QueuedFolder<'_> {
+    /// Opens the folder without following symlinks
+    fn open(&self) -> io::Result<File> {
+        match &self.parent {
+            Some(parent) => open_entry_at(parent, &self.name, libc::O_PATH | libc::O_DIRECTORY),
+            None => Ok(File::from(self.root.directory.fd.try_clone()?)),
+        }
+    }
+}
+
+/// Creates a stream of newline delimited JSON search results for entries
+/// under the provided search roots matching the filter. The search runs on
+/// the blocking thread pool and stops once the limit is reached or the
+/// stream is dropped (e.g. when the client disconnects)
+pub fn stream_search(
+    roots: Vec<SearchRoot>,
+    filter: SearchFilter,
+    limit: usize,
+) -> impl Stream<Item = io::Result<Bytes>> {
+    let (sender, receiver) = mpsc::channel::<Bytes>(SEARCH_CHANNEL_CAPACITY);
+
+    tokio::task::spawn_blocking(move || {
+        let should_stop = || sender.is_closed();
+        search(roots, &filter, limit, should_stop, |result| {
+            let mut line = match serde_json::to_vec(&result) {
+                Ok(line) => line,
+                Err(err) => {
+                    warn!("Failed to serialize search result: {}", err);
+                    return true;
+                }
+            };
+            line.push(b'\n');
+            sender.blocking_send(Bytes::from(line)).is_ok()
+        });
+    });
+
+    stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|line| (Ok(line), receiver))
+    })
+}
+
+/// Walks the entries under each search root breadth first calling the
+/// provided function with each matching result. The walk stops once the
+/// limit is reached, the function returns false or `should_stop` returns
+/// true which is checked before each folder and entry so that searches
+/// without matches still stop. Symlinks are not followed and unreadable
+/// folders are skipped
+fn search<S, F>(
+    roots: Vec<SearchRoot>,
+    filter: &SearchFilter,
+    limit: usize,
+    should_stop: S,
+    mut emit: F,
+) where
+    S: Fn() -> bool,
+    F: FnMut(SearchResult) -> bool,
+{
+    let mut remaining = limit;
+    let mut queue: VecDeque<QueuedFolder> = roots
+        .iter()
+        .map(|root| QueuedFolder {
+            root,
+            parent: None,
+            name: OsString::new(),
+            path: root.path.clone(),
+        })
+        .collect();
+
+    while let Some(queued) = queue.pop_front() {
+        if should_stop() {
+            return;
+        }
+        let (folder, entries) = match queued
+            .open()
+            .and_then(|folder| read_dir_at(&folder).map(|entries| (folder, entries)))
+        {
+            Ok(result) => result,
+            Err(_) => continue,
+        };
+        let folder = Rc::new(folder);
+
+        for entry in entries.flatten() {
+            if should_stop() {
+                return;
+            }
+            let name = entry.file_name();
+            if name == STAGING_DIR || name == TRASH_DIR {
+                continue;
+            }
+            // Entries are only used by name relative to the opened folder
+            let metadata = match metadata_at(folder.as_fd(), &name) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            let path = queued.path.join(&name);
+            if metadata.is_dir() {
+                queue.push_back(QueuedFolder {
+                    root: queued.root,
+                    parent: Some(folder.clone()),
+                    name: name.clone(),
+                    path: path.clone(),
+                });
+            }
+
+            let name = name.to_string_lossy();
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_millis());
+            if !filter.matches(&name, &metadata, modified) {
+                continue;
+            }
+
+            if !emit(SearchResult {
+                drive: queued.root.drive.clone(),
+                path: path.to_string_lossy().to_string(),
+                name: name.to_string(),
+                folder: metadata.is_dir(),
+                size: metadata.len(),
+                modified,
+            }) {
+                return;
+            }
+            remaining -= 1;
+            if remaining == 0 {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Creates a drive with nested folders where only the
+    /// deepest folder contains a matching file
+    fn create_drive() -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let deepest = root.path().join("a/b/c");
+        fs::create_dir_all(&deepest).unwrap();
+        for folder in ["", "a", "a/b"] {
+            for index in 0..10 {
+                fs::write(root.path().join(folder).join(format!("{}.bin", index)), "").unwrap();
+            }
+        }
+        fs::write(deepest.join("match.txt"), "").unwrap();
+        root
+    }
+
+    /// Creates the search roots for searching the whole drive
+    fn create_roots(root: &TempDir) -> Vec<SearchRoot> {
+        vec![SearchRoot {
+            drive: "1234-ABCD".to_string(),
+            directory: DriveDir::open(root.path()).unwrap(),
+            path: PathBuf::new(),
+        }]
+    }
+
+    /// Creates a filter matching names containing the query
+    fn create_filter(query: &str) -> SearchFilter {
+        let request: SearchRequest =
+            serde_json::from_value(serde_json::json!({ "query": query })).unwrap();
+        SearchFilter::new(&request).unwrap()
+    }
+
+    #[test]
+    fn finds_matches_in_nested_folders() {
+        let root = create_drive();
+        let mut results = Vec::new();
+        search(
+            create_roots(&root),
+            &create_filter("match"),
+            10,
+            || false,
+            |result| {
+                results.push(result.path);
+                true
+            },
+        );
+        assert_eq!(results, ["a/b/c/match.txt"]);
+    }
+
+    #[test]
+    fn stops_walking_without_matches_once_stopped() {
+        let root = create_drive();
+        let checks = Cell::new(0);
+        let mut results = Vec::new();
+        search(
+            create_roots(&root),
+            &create_filter("match"),
+            10,
+            || {
+                checks.set(checks.get() + 1);
+                checks.get() > 5
+            },
+            |result| {
+                results.push(result.path);
+                true
+            },
+        );
+        // The walk stops at the first check after being stopped
+        assert_eq!(checks.get(), 6);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn does_not_follow_symlinked_folders() {
+        let root = create_drive();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("match-outside.txt"), "").unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("escape")).unwrap();
+        std::os::unix::fs::symlink(root.path().join("a"), root.path().join("a/b/loop")).unwrap();
+
+        let mut results = Vec::new();
+        search(
+            create_roots(&root),
+            &create_filter("match"),
+            10,
+            || false,
+            |result| {
+                results.push(result.path);
+                true
+            },
+        );
+        assert_eq!(results, ["a/b/c/match.txt"]);
+    }
+}
@@ -0,0 +1,408 @@
+use std::ffi::{OsStr, OsString};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{info, warn};
+
+use crate::models::errors::FilesError;
+use crate::models::trash::{TrashItem, TrashRecord};
+use crate::utils::drives::get_mount_root;
+use crate::utils::files::{
+    check_file_name, create_directories_at, ensure_not_exists_at, get_entry_at, DriveDir,
+};
+use crate::utils::resolve::{
+    create_dir_at, create_file_at, metadata_at, open_entry_at, read_dir_at, remove_at, rename_at,
+};
+use crate::utils::{create_character_set, create_random_string};
+
+/// The name of the hidden directory on each drive that deleted
+/// files and folders are moved to
+pub const TRASH_DIR: &str = ".drivepi-trash";
+
+/// The extension of the sidecar records stored next to trash items
+const RECORD_EXTENSION: &str = "json";
+
+/// The character length of the ids given to trash items
+const TRASH_ID_LENGTH: usize = 16;
+
+const ENV_TRASH_RETENTION_KEY: &str = "DRIVEPI_TRASH_RETENTION";
+/// The default number of days items are kept in the trash
+const DEFAULT_TRASH_RETENTION: u64 = 30;
+
+type FilesResult<T> = Result<T, FilesError>;
+
+/// Retrieves the number of days that items should be kept in the
+/// trash before they are permanently removed
+pub fn get_env_trash_retention() -> Duration {
+    let retention_env = std::env::var(ENV_TRASH_RETENTION_KEY);
+    let days = if let Ok(retention_raw) = retention_env {
+        if let Ok(days) = retention_raw.parse::<u64>() {
+            days
+        } else {
+            warn!(
+                "Trash retention provided as {} is not a valid number of days defaulting to {}",
+                retention_raw, DEFAULT_TRASH_RETENTION
+            );
+            DEFAULT_TRASH_RETENTION
+        }
+    } else {
+        DEFAULT_TRASH_RETENTION
+    };
+    Duration::from_secs(days * 60 * 60 * 24)
+}
+
+/// Retrieves the current time in milliseconds since the unix epoch
+fn current_time_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::from_millis(0))
+        .as_millis()
+}
+
+/// Retrieves the name of the sidecar record for the provided trash item
+fn get_record_name(id: &str) -> OsString {
+    OsString::from(format!("{}.{}", id, RECORD_EXTENSION))
+}
+
+/// Opens the trash directory of the drive with the provided root
+fn open_trash_dir(drive_root: &DriveDir) -> io::Result<DriveDir> {
+    drive_root.open_child(OsStr::new(TRASH_DIR))
+}
+
+/// Opens the trash directory of the drive with the provided
+/// root creating it if it doesn't already exist
+fn create_trash_dir(drive_root: &DriveDir) -> io::Result<DriveDir> {
+    match create_dir_at(&drive_root.fd, OsStr::new(TRASH_DIR), 0o777) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(err),
+    }
+    open_trash_dir(drive_root)
+}
+
+/// Reads the sidecar record for the provided trash item
+fn read_record(trash_dir: &DriveDir, id: &str) -> FilesResult<TrashRecord> {
+    // Non blocking so that a FIFO in the trash can't stall the server
+    let mut file = open_entry_at(
+        &trash_dir.fd,
+        &get_record_name(id),
+        libc::O_RDONLY | libc::O_NONBLOCK,
+    )?;
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    serde_json::from_slice(&contents).map_err(|err| {
+        warn!("Failed to parse trash record {}: {}", id, err);
+        FilesError::IOError
+    })
+}
+
+/// Retrieves the ids of the items in the provided trash directory
+/// that have a sidecar record
+fn get_record_ids(trash_dir: &DriveDir) -> FilesResult<Vec<String>> {
+    let mut ids = Vec::new();
+    for entry in read_dir_at(&trash_dir.fd)? {
+        let name = PathBuf::from(entry?.file_name());
+        if name.extension().is_none_or(|ext| ext != RECORD_EXTENSION) {
+            continue;
+        }
+        if let Some(id) = name.file_stem() {
+            ids.push(id.to_string_lossy().to_string());
+        }
+    }
+    Ok(ids)
+}
+
+/// Moves the entry at the provided path on the drive into the trash
+/// directory of its drive along with a sidecar record of its original
+/// path and the time it was deleted
+pub fn trash_entry(drive_root: &DriveDir, path: &str) -> FilesResult<()> {
+    let entry = get_entry_at(drive_root, path)?;
+    let trash_dir = create_trash_dir(drive_root)?;
+
+    // Items already in the trash must be removed through the trash
+    let entry_path = entry.path();
+    if entry_path.starts_with(&trash_dir.path) {
+        return Err(FilesError::InvalidTarget);
+    }
+
+    let relative_path = entry_path
+        .strip_prefix(&drive_root.path)
+        .map_err(|_| FilesError::OutsideMountRoot)?;
+
+    let character_set = create_character_set();
+    let id = loop {
+        let id = create_random_string(&character_set, TRASH_ID_LENGTH);
+        if ensure_not_exists_at(&trash_dir, OsStr::new(&id)).is_ok() {
+            break id;
+        }
+    };
+
+    let record = TrashRecord {
+        path: relative_path.to_string_lossy().to_string(),
+        deleted_at: current_time_millis(),
+    };
+    let record_name = get_record_name(&id);
+    let contents = serde_json::to_vec(&record).map_err(|_| FilesError::IOError)?;
+    create_file_at(&trash_dir.fd, &record_name, 0o666)?.write_all(&contents)?;
+
+    if let Err(err) = rename_at(
+        &entry.parent.fd,
+        &entry.name,
+        &trash_dir.fd,
+        OsStr::new(&id),
+    ) {
+        if let Err(remove_err) = remove_at(&trash_dir.fd, &record_name) {
+            warn!("Failed to remove trash record: {}", remove_err);
+        }
+        return Err(err.into());
+    }
+
+    info!("Moved {} to trash", entry_path.to_string_lossy());
+    Ok(())
+}
+
+/// Retrieves the list of items in the trash of the drive with the provided
+/// root. Items without a valid sidecar record are skipped
+pub fn get_trash_items(drive_root: &DriveDir) -> FilesResult<Vec<TrashItem>> {
+    let trash_dir = match open_trash_dir(drive_root) {
+        Ok(trash_dir) => trash_dir,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut items = Vec::new();
+    for id in get_record_ids(&trash_dir)? {
+        let metadata = match metadata_at(&trash_dir.fd, OsStr::new(&id)) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let record = match read_record(&trash_dir, &id) {
+            Ok(record) => record,
+            Err(_) => continue,
+        };
+        let name = Path::new(&record.path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        items.push(TrashItem {
+            id,
+            name,
+            path: record.path,
+            deleted_at: record.deleted_at,
+            folder: metadata.is_dir(),
+        })
+    }
+
+    Ok(items)
+}
+
+/// Restores the item with the provided id in the trash of the drive with
+/// the provided root to its original path. Missing parent folders
+/// of the original path are recreated. Returns the original path
+pub fn restore_trash_item(drive_root: &DriveDir, id: &str) -> FilesResult<String> {
+    check_file_name(id)?;
+    let trash_dir = open_trash_dir(drive_root)?;
+    let record = read_record(&trash_dir, id)?;
+
+    // Records are stored on the drive so ensure they can't escape it
+    let original_path = Path::new(&record.path);
+    let is_relative = original_path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if !is_relative {
+        return Err(FilesError::InvalidTarget);
+    }
+
+    let name = original_path.file_name().ok_or(FilesError::InvalidTarget)?;
+    let parent = original_path.parent().unwrap_or_else(|| Path::new(""));
+    let directory = create_directories_at(drive_root, parent)?;
+    rename_at(&trash_dir.fd, OsStr::new(id), &directory.fd, name)?;
+    remove_at(&trash_dir.fd, &get_record_name(id))?;
+
+    info!(
+        "Restored {} from trash",
+        directory.path.join(name).to_string_lossy()
+    );
+    Ok(record.path)
+}
+
+/// Permanently removes the item with the provided id from the trash
+/// directory along with its sidecar record
+fn remove_trash_item(trash_dir: &DriveDir, id: &str) -> FilesResult<()> {
+    match remove_at(&trash_dir.fd, OsStr::new(id)) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    remove_at(&trash_dir.fd, &get_record_name(id))?;
+    Ok(())
+}
+
+/// Permanently removes the item with the provided id from the trash of the
+/// drive with the provided root or every item if no id is provided
+pub fn empty_trash(drive_root: &DriveDir, id: Option<&str>) -> FilesResult<()> {
+    if let Some(id) = id {
+        check_file_name(id)?;
+        remove_trash_item(&open_trash_dir(drive_root)?, id)?;
+    } else {
+        match remove_at(&drive_root.fd, OsStr::new(TRASH_DIR)) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    info!(
+        "Emptied trash {}",
+        drive_root.path.join(TRASH_DIR).to_string_lossy()
+    );
+    Ok(())
+}
+
+/// Permanently removes items that have been in the trash for longer than
+/// the provided retention period from the trash of every mounted drive.
+/// Returns the number of items that were removed
+pub fn purge_expired_trash(retention: Duration) -> FilesResult<usize> {
+    purge_expired_trash_in(&get_mount_root()?, retention)
+}
+
+/// Purges the expired items from the trash of every drive
+/// mounted in the provided mount root
+fn purge_expired_trash_in(mount_root: &Path, retention: Duration) -> FilesResult<usize> {
+    let mount_root = DriveDir::open(mount_root)?;
+    let cutoff = current_time_millis().saturating_sub(retention.as_millis());
+    let mut removed = 0;
+
+    for drive in read_dir_at(&mount_root.fd)? {
+        let trash_dir = match mount_root
+            .open_child(&drive?.file_name())
+            .and_then(|drive_root| open_trash_dir(&drive_root))
+        {
+            Ok(trash_dir) => trash_dir,
+            Err(_) => continue,
+        };
+
+        for id in get_record_ids(&trash_dir)? {
+            let is_expired = read_record(&trash_dir, &id)
+                .map(|record| record.deleted_at < cutoff)
+                .unwrap_or(false);
+            if !is_expired {
+                continue;
+            }
+            match remove_trash_item(&trash_dir, &id) {
+                Ok(()) => removed += 1,
+                Err(err) => warn!("Failed to purge trash item {}: {}", id, err),
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Creates a mount root containing a single drive with a file and
+    /// a folder returning the mount root along with the drive root
+    fn create_drive() -> (TempDir, DriveDir) {
+        let mount_root = tempfile::tempdir().unwrap();
+        let path = mount_root.path().join("usb");
+        fs::create_dir_all(path.join("dir")).unwrap();
+        fs::write(path.join("dir/file.txt"), "contents").unwrap();
+        fs::write(path.join("other.txt"), "other").unwrap();
+        let drive_root = DriveDir::open(&path).unwrap();
+        (mount_root, drive_root)
+    }
+
+    /// Retrieves the id of the trash item with the provided original path
+    fn get_item_id(drive_root: &DriveDir, path: &str) -> String {
+        get_trash_items(drive_root)
+            .unwrap()
+            .into_iter()
+            .find(|item| item.path == path)
+            .map(|item| item.id)
+            .unwrap()
+    }
+
+    /// Replaces the record of the trash item with the provided id
+    fn write_record(drive_root: &DriveDir, id: &str, record: &TrashRecord) {
+        let record_path = drive_root.path.join(TRASH_DIR).join(get_record_name(id));
+        fs::write(record_path, serde_json::to_vec(record).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn purges_items_older_than_retention() {
+        let (mount_root, drive_root) = create_drive();
+        trash_entry(&drive_root, "dir").unwrap();
+        trash_entry(&drive_root, "other.txt").unwrap();
+        let id = get_item_id(&drive_root, "dir");
+        let retention = Duration::from_secs(60 * 60 * 24);
+        let deleted_at = current_time_millis() - retention.as_millis() - 1000;
+        let record = TrashRecord {
+            path: "dir".to_string(),
+            deleted_at,
+        };
+        write_record(&drive_root, &id, &record);
+
+        assert_eq!(
+            purge_expired_trash_in(mount_root.path(), retention).unwrap(),
+            1
+        );
+        let items = get_trash_items(&drive_root).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].path, "other.txt");
+        assert!(!drive_root.path.join(TRASH_DIR).join(&id).exists());
+        assert_eq!(
+            purge_expired_trash_in(mount_root.path(), retention).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn restores_without_replacing_existing_paths() {
+        let (_mount_root, drive_root) = create_drive();
+        trash_entry(&drive_root, "dir/file.txt").unwrap();
+        let id = get_item_id(&drive_root, "dir/file.txt");
+        let file_path = drive_root.path.join("dir/file.txt");
+        fs::write(&file_path, "replacement").unwrap();
+
+        let result = restore_trash_item(&drive_root, &id);
+        assert!(matches!(result, Err(FilesError::AlreadyExists)));
+        assert_eq!(fs::read(&file_path).unwrap(), b"replacement");
+        assert_eq!(get_trash_items(&drive_root).unwrap().len(), 1);
+
+        // Missing parent folders are recreated
+        fs::remove_dir_all(drive_root.path.join("dir")).unwrap();
+        assert_eq!(
+            restore_trash_item(&drive_root, &id).unwrap(),
+            "dir/file.txt"
+        );
+        assert_eq!(fs::read(&file_path).unwrap(), b"contents");
+        assert!(get_trash_items(&drive_root).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_records_outside_the_drive() {
+        let (mount_root, drive_root) = create_drive();
+        trash_entry(&drive_root, "other.txt").unwrap();
+        let id = get_item_id(&drive_root, "other.txt");
+
+        for path in ["/tmp/other.txt", "../other.txt", "dir/../../other.txt", ""] {
+            let record = TrashRecord {
+                path: path.to_string(),
+                deleted_at: current_time_millis(),
+            };
+            write_record(&drive_root, &id, &record);
+            let result = restore_trash_item(&drive_root, &id);
+            assert!(matches!(result, Err(FilesError::InvalidTarget)), "{}", path);
+        }
+        assert!(!mount_root.path().join("other.txt").exists());
+        assert!(drive_root.path.join(TRASH_DIR).join(&id).exists());
+    }
+}
@@ -0,0 +1,346 @@
+use std::ffi::OsStr;
+use std::io::{self, ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use log::warn;
+use tokio::fs::File;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use crate::models::errors::{FilesError, UploadsError};
+use crate::stores::uploads::UploadSession;
+use crate::utils::drives::get_mount_root;
+use crate::utils::files::{get_directory_at, DriveDir};
+use crate::utils::resolve::{
+    create_dir_at, create_file_at, metadata_at, open_entry_at, read_dir_at, remove_at, rename_at,
+};
+use crate::utils::{create_character_set, create_random_string};
+
+/// The name of the directory on each drive that in progress
+/// uploads are staged in
+pub const STAGING_DIR: &str = ".drivepi-uploads";
+
+/// The character length of the staging file names
+const STAGING_NAME_LENGTH: usize = 16;
+
+type UploadsResult<T> = Result<T, UploadsError>;
+type FilesResult<T> = Result<T, FilesError>;
+
+/// Opens the staging directory of the drive with the provided root
+fn open_staging_dir(drive_root: &DriveDir) -> io::Result<DriveDir> {
+    drive_root.open_child(OsStr::new(STAGING_DIR))
+}
+
+/// Creates a new empty staging file in the staging directory of the
+/// drive with the provided root returning the name of the file. Staging
+/// files are kept on the same drive so they can be renamed into place
+/// once complete
+pub fn create_staging_file(drive_root: &DriveDir) -> UploadsResult<String> {
+    match create_dir_at(&drive_root.fd, OsStr::new(STAGING_DIR), 0o777) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(err.into()),
+    }
+    let staging_dir = open_staging_dir(drive_root)?;
+
+    let name = create_random_string(&create_character_set(), STAGING_NAME_LENGTH);
+    let staging_name = format!("{}.part", name);
+    create_file_at(&staging_dir.fd, OsStr::new(&staging_name), 0o666)?;
+    Ok(staging_name)
+}
+
+/// Appends the contents of the provided stream to the staging file of the
+/// provided session. The offset must match the number of bytes already
+/// received. The session offset is updated as chunks are written so that
+/// interrupted requests can be resumed from the last written byte
+pub async fn append_upload<S, E>(
+    drive_root: &DriveDir,
+    session: &mut UploadSession,
+    offset: u64,
+    mut stream: S,
+) -> UploadsResult<()>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    if offset != session.offset {
+        return Err(UploadsError::OffsetMismatch);
+    }
+
+    let staging_dir = open_staging_dir(drive_root)?;
+    let file = open_entry_at(
+        &staging_dir.fd,
+        OsStr::new(&session.staging_name),
+        libc::O_WRONLY,
+    )?;
+    let mut file = File::from_std(file);
+
+    // Discard any bytes past the offset from previously failed writes
+    file.set_len(offset).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+
+    let result: UploadsResult<()> = async {
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|_| FilesError::UploadError)?;
+            let chunk_length = chunk.len() as u64;
+            if session.offset + chunk_length > session.length {
+                return Err(UploadsError::LengthExceeded);
+            }
+            file.write_all(&chunk).await?;
+            session.offset += chunk_length;
+        }
+        Ok(())
+    }
+    .await;
+
+    file.sync_data().await?;
+    session.last_activity = SystemTime::now();
+    result
+}
+
+/// Moves the completed staging file of the provided session to its
+/// target directory returning the path of the completed file. Fails
+/// if anything already exists with the name of the file
+pub fn finish_upload(drive_root: &DriveDir, session: &UploadSession) -> UploadsResult<PathBuf> {
+    if session.offset != session.length {
+        return Err(UploadsError::Incomplete);
+    }
+    let directory = get_directory_at(drive_root, &session.path)?;
+    let staging_dir = open_staging_dir(drive_root)?;
+    let name = OsStr::new(&session.name);
+    rename_at(
+        &staging_dir.fd,
+        OsStr::new(&session.staging_name),
+        &directory.fd,
+        name,
+    )?;
+    Ok(directory.path.join(name))
+}
+
+/// Removes the staging file for the provided session
+pub fn remove_staging_file(drive_root: &DriveDir, session: &UploadSession) {
+    let result = open_staging_dir(drive_root)
+        .and_then(|staging_dir| remove_at(&staging_dir.fd, OsStr::new(&session.staging_name)));
+    if let Err(err) = result {
+        if err.kind() != ErrorKind::NotFound {
+            warn!("Failed to remove upload staging file: {}", err);
+        }
+    }
+}
+
+/// Removes the staging files that haven't been written to for longer than
+/// the provided expiry from the staging directory of every mounted drive.
+/// Upload sessions are only kept in memory so the staging files of uploads
+/// that were in progress when the server stopped are otherwise never
+/// removed. Returns the number of staging files that were removed
+pub fn remove_stale_staging_files(expiry: Duration) -> FilesResult<usize> {
+    remove_stale_staging_files_in(&get_mount_root()?, expiry)
+}
+
+/// Removes the stale staging files of every drive mounted in
+/// the provided mount root
+fn remove_stale_staging_files_in(mount_root: &Path, expiry: Duration) -> FilesResult<usize> {
+    let mount_root = DriveDir::open(mount_root)?;
+    let mut removed = 0;
+
+    for drive in read_dir_at(&mount_root.fd)? {
+        let staging_dir = match mount_root
+            .open_child(&drive?.file_name())
+            .and_then(|drive_root| open_staging_dir(&drive_root))
+        {
+            Ok(staging_dir) => staging_dir,
+            Err(_) => continue,
+        };
+
+        for entry in read_dir_at(&staging_dir.fd)? {
+            let name = entry?.file_name();
+            let is_stale = metadata_at(&staging_dir.fd, &name)
+                .and_then(|metadata| metadata.modified())
+                .map(|modified| modified.elapsed().is_ok_and(|elapsed| elapsed >= expiry))
+                .unwrap_or(false);
+            if !is_stale {
+                continue;
+            }
+            match remove_at(&staging_dir.fd, &name) {
+                Ok(()) => removed += 1,
+                Err(err) => warn!("Failed to remove stale upload staging file: {}", err),
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use futures::stream;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::stores::uploads::UploadStore;
+
+    /// Creates a drive containing the folder that uploads are sent to
+    fn create_drive() -> (TempDir, DriveDir) {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("dir")).unwrap();
+        let drive_root = DriveDir::open(root.path()).unwrap();
+        (root, drive_root)
+    }
+
+    /// Creates a session for uploading a file with the provided
+    /// length into the folder on the drive
+    fn create_session(drive_root: &DriveDir, length: u64) -> UploadSession {
+        UploadSession {
+            staging_name: create_staging_file(drive_root).unwrap(),
+            drive: String::new(),
+            path: "dir".to_string(),
+            name: "file.txt".to_string(),
+            length,
+            offset: 0,
+            last_activity: SystemTime::now(),
+        }
+    }
+
+    /// Creates a request body stream from the provided chunks
+    fn create_body(chunks: &[&'static str]) -> impl Stream<Item = Result<Bytes, ()>> + Unpin {
+        stream::iter(
+            chunks
+                .iter()
+                .map(|chunk| Ok(Bytes::from_static(chunk.as_bytes())))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Retrieves the path of the staging file of the provided session
+    fn get_staging_path(root: &TempDir, session: &UploadSession) -> PathBuf {
+        root.path().join(STAGING_DIR).join(&session.staging_name)
+    }
+
+    #[test]
+    fn creates_empty_staging_files() {
+        let (root, drive_root) = create_drive();
+        let first = create_session(&drive_root, 3);
+        let second = create_session(&drive_root, 3);
+
+        assert_ne!(first.staging_name, second.staging_name);
+        for session in [&first, &second] {
+            let metadata = fs::metadata(get_staging_path(&root, session)).unwrap();
+            assert!(metadata.is_file());
+            assert_eq!(metadata.len(), 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_chunks_at_wrong_offset() {
+        let (root, drive_root) = create_drive();
+        let mut session = create_session(&drive_root, 6);
+
+        append_upload(&drive_root, &mut session, 0, create_body(&["ab", "c"]))
+            .await
+            .unwrap();
+        assert_eq!(session.offset, 3);
+
+        for offset in [0, 2, 4] {
+            let result =
+                append_upload(&drive_root, &mut session, offset, create_body(&["x"])).await;
+            assert!(matches!(result, Err(UploadsError::OffsetMismatch)));
+        }
+        assert_eq!(session.offset, 3);
+        assert_eq!(fs::read(get_staging_path(&root, &session)).unwrap(), b"abc");
+
+        let result = append_upload(&drive_root, &mut session, 3, create_body(&["defg"])).await;
+        assert!(matches!(result, Err(UploadsError::LengthExceeded)));
+    }
+
+    #[tokio::test]
+    async fn rejects_finishing_incomplete_uploads() {
+        let (root, drive_root) = create_drive();
+        let mut session = create_session(&drive_root, 6);
+        append_upload(&drive_root, &mut session, 0, create_body(&["abc"]))
+            .await
+            .unwrap();
+
+        let result = finish_upload(&drive_root, &session);
+        assert!(matches!(result, Err(UploadsError::Incomplete)));
+        assert!(get_staging_path(&root, &session).exists());
+        assert!(!root.path().join("dir/file.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn finishes_without_replacing_existing_files() {
+        let (root, drive_root) = create_drive();
+        let mut session = create_session(&drive_root, 3);
+        append_upload(&drive_root, &mut session, 0, create_body(&["abc"]))
+            .await
+            .unwrap();
+        let target_path = root.path().join("dir/file.txt");
+        fs::write(&target_path, "existing").unwrap();
+
+        let result = finish_upload(&drive_root, &session);
+        assert!(matches!(
+            result,
+            Err(UploadsError::FilesError(FilesError::AlreadyExists))
+        ));
+        assert_eq!(fs::read(&target_path).unwrap(), b"existing");
+
+        fs::remove_file(&target_path).unwrap();
+        let finished_path = finish_upload(&drive_root, &session).unwrap();
+        assert_eq!(finished_path, drive_root.path.join("dir/file.txt"));
+        assert_eq!(fs::read(&target_path).unwrap(), b"abc");
+        assert!(!get_staging_path(&root, &session).exists());
+    }
+
+    #[tokio::test]
+    async fn removes_staging_files_of_expired_sessions() {
+        let (root, drive_root) = create_drive();
+        let store = UploadStore::new();
+        let mut expired = create_session(&drive_root, 3);
+        expired.last_activity = SystemTime::now() - Duration::from_secs(60 * 60 * 25);
+        let expired_path = get_staging_path(&root, &expired);
+        let active = create_session(&drive_root, 3);
+        let active_path = get_staging_path(&root, &active);
+        store.create_session(expired).await;
+        store.create_session(active).await;
+
+        let removed = store.remove_expired().await;
+        assert_eq!(removed.len(), 1);
+        for session in removed {
+            remove_staging_file(&drive_root, &*session.lock().await);
+        }
+
+        assert!(!expired_path.exists());
+        assert!(active_path.exists());
+        assert!(store.remove_expired().await.is_empty());
+    }
+
+    #[test]
+    fn removes_stale_staging_files() {
+        let mount_root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(mount_root.path().join("usb/dir")).unwrap();
+        let drive_root = DriveDir::open(&mount_root.path().join("usb")).unwrap();
+        let stale = create_staging_file(&drive_root).unwrap();
+        let fresh = create_staging_file(&drive_root).unwrap();
+
+        // Staging files left behind by uploads from before a restart
+        // have no session so are only found by their modified time
+        let staging_path = mount_root.path().join("usb").join(STAGING_DIR);
+        fs::File::options()
+            .write(true)
+            .open(staging_path.join(&stale))
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(60 * 60 * 25))
+            .unwrap();
+
+        let expiry = Duration::from_secs(60 * 60 * 24);
+        assert_eq!(
+            remove_stale_staging_files_in(mount_root.path(), expiry).unwrap(),
+            1
+        );
+        assert!(!staging_path.join(&stale).exists());
+        assert!(staging_path.join(&fresh).exists());
+    }
+}
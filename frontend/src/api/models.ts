@@ -18,6 +18,20 @@ export interface AuthResponse {
 export interface DrivesResponse {
     drives: DriveItem[];
     mount_root: string;
+    known: KnownDrive[]; // Every drive that has been seen including disconnected drives
+}
+
+// Structure represents a drive that has been connected before
+export interface KnownDrive {
+    uuid: string; // Filesystem UUID for the device
+    label: string; // Device Label when last seen
+    fstype: Option<string>; // Filesystem type when last seen
+    name: Option<string>; // Friendly name for the drive
+    mount_name: Option<string>; // Folder name the drive is mounted to
+    read_only: Option<boolean>; // Whether the drive is mounted read only
+    notes: Option<string>; // Notes about the drive
+    last_seen: number; // The last time the drive was connected in milliseconds
+    connected: boolean; // Whether the drive is currently connected
 }
 
 // Structure represents a drive / partition on the server
@@ -26,6 +40,7 @@ export interface DriveItem {
     name: string; // Device name (e.g. sda1)
     label: string; // Device Label (e.g. My Drive)
     path: string; // Device Path (e.g. /dev/sda1)
+    fstype: Option<string>; // Filesystem type (e.g. vfat)
 
     mount: Option<string>; // Mounted fs path (e.g. /mnt/sda1)
     size: Option<string>; // Filesystem capacity
@@ -38,6 +53,8 @@ export interface DriveItem {
 export interface FilesResponse {
     files: DriveFile[];
     folders: DriveFolder[];
+    total_folders: number; // Total number of folders in the directory
+    total_files: number; // Total number of files in the directory
 }
 
 // Structure represents a file on the server
@@ -45,11 +62,27 @@ export interface DriveFile {
     name: string; // Name of the file (e.g. file.txt)
     size: number; // Size of the file in bytes
     permissions: number; // File permissions (e.g. 644)
+    modified: Option<number>; // Last modified time in milliseconds
+    created: Option<number>; // Creation time in milliseconds
+    accessed: Option<number>; // Last accessed time in milliseconds
+    uid: number; // User id of the owner
+    gid: number; // Group id of the owner
+    hidden: boolean; // Whether the file name starts with a dot
+    symlink: Option<string>; // Symlink target path
+    mime: string; // Guessed MIME type (e.g. image/png)
 }
 
 // Structure represents a folder on the server
 export interface DriveFolder {
     name: string; // Name of folder (e.g. My Folder)
     permissions: number; // Folder permissions (e.g. 644)
+    modified: Option<number>; // Last modified time in milliseconds
+    created: Option<number>; // Creation time in milliseconds
+    accessed: Option<number>; // Last accessed time in milliseconds
+    uid: number; // User id of the owner
+    gid: number; // Group id of the owner
+    hidden: boolean; // Whether the folder name starts with a dot
+    symlink: Option<string>; // Symlink target path
+    children: Option<number>; // Number of direct children
 }
 
@@ -23,10 +23,10 @@ const FileBrowser: FunctionComponent<Properties> = ({drive}) => {
 
     let isRoot = path.length === 0;
 
-    async function getFiles(path: string, drive_path: string) {
+    async function getFiles(path: string, drive: string) {
         try {
             const response: FilesResponse =
-                await request("POST", "files", {path, drive_path});
+                await request("POST", "files", {path, drive});
             setDriveState({
                 files: response.files,
                 folders: response.folders
@@ -59,7 +59,7 @@ const FileBrowser: FunctionComponent<Properties> = ({drive}) => {
     // Effect for loading the files when the path or drive changes
     useEffect(() => {
         if (drive != null && drive.mount != null) {
-            getFiles(path, drive.mount)
+            getFiles(path, drive.uuid)
                 .then()
                 .catch(console.error)
         }